@@ -7,7 +7,7 @@ type E = Box<dyn std::error::Error+Sync+Send>;
 
 #[async_std::main]
 async fn main() -> Result<(),E> {
-  let mut db: DB<_,_,P,V> = eyros::open_from_path2(
+  let db: DB<_,_,P,V> = eyros::open_from_path2(
     &std::path::PathBuf::from("/tmp/eyros.db")
   ).await?;
 
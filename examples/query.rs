@@ -8,7 +8,7 @@ type E = Box<dyn std::error::Error+Sync+Send>;
 #[async_std::main]
 async fn main() -> Result<(),E> {
   let args: Vec<String> = std::env::args().collect();
-  let mut db: DB<_,_,P,V> = eyros::open_from_path2(
+  let db: DB<_,_,P,V> = eyros::open_from_path2(
     &std::path::PathBuf::from(args[1].clone())
   ).await?;
 
@@ -10,7 +10,8 @@ module.exports = function (opts) {
     }
     return api.open_f32_f32_f32(Object.assign({}, opts, {
       storage: wrapStorage(opts.storage),
-      remove: opts.remove || function () {}
+      remove: opts.remove || function () {},
+      list: opts.list || function () { return [] }
     }))
   })
 }
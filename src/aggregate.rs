@@ -0,0 +1,105 @@
+use crate::{DB,Tree,Point,Value,Error,RA};
+
+/// Accumulator for `DB::aggregate()`, which folds every record that intersects a bounding box
+/// into a result without collecting or cloning the records.
+pub trait Aggregate<P,V>: Send where P: Point, V: Value {
+  type Output;
+  /// Add a record that intersects the bounding box.
+  fn add(&mut self, point: &P, value: &V);
+  /// Add `count` records that lie entirely within the bounding box without visiting them, and
+  /// return true. Return false to have the records passed to `add()` instead, which is the only
+  /// choice for aggregates that look at the records.
+  fn add_count(&mut self, _count: u64) -> bool {
+    false
+  }
+  /// Return the result once every record has been added.
+  fn finish(self) -> Self::Output;
+}
+
+/// Aggregate that counts records.
+#[derive(Debug,Clone,Default)]
+pub struct Count {
+  count: u64,
+}
+
+impl Count {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl<P,V> Aggregate<P,V> for Count where P: Point, V: Value {
+  type Output = u64;
+  fn add(&mut self, _point: &P, _value: &V) {
+    self.count += 1;
+  }
+  fn add_count(&mut self, count: u64) -> bool {
+    self.count += count;
+    true
+  }
+  fn finish(self) -> u64 {
+    self.count
+  }
+}
+
+/// Aggregate that folds records into an accumulator with a function, like `Iterator::fold()`.
+///
+/// ```rust,no_run
+/// # use eyros::{DB,Coord,Tree2,Fold};
+/// # #[async_std::main]
+/// # async fn main () -> Result<(),Box<dyn std::error::Error+Sync+Send>> {
+/// # type P = (Coord<f32>,Coord<f32>);
+/// # type V = u32;
+/// # type T = Tree2<f32,f32,V>;
+/// # let db: DB<_,T,P,V> = DB::open_in_memory().await?;
+/// let sum = db.aggregate(&((-180.0,-90.0),(180.0,90.0)), Fold::new(0u64, |sum, _p, v| {
+///   *sum += *v as u64;
+/// })).await?;
+/// # Ok(()) }
+/// ```
+pub struct Fold<A,F> {
+  acc: A,
+  f: F,
+}
+
+impl<A,F> Fold<A,F> {
+  /// Start from `init` and call `f` with the accumulator and each record.
+  pub fn new<P,V>(init: A, f: F) -> Self where F: FnMut(&mut A,&P,&V) {
+    Self { acc: init, f }
+  }
+}
+
+impl<P,V,A,F> Aggregate<P,V> for Fold<A,F>
+where P: Point, V: Value, A: Send, F: FnMut(&mut A,&P,&V)+Send {
+  type Output = A;
+  fn add(&mut self, point: &P, value: &V) {
+    (self.f)(&mut self.acc, point, value)
+  }
+  fn finish(self) -> A {
+    self.acc
+  }
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Fold every record that intersects `bbox` into `agg` and return its result. Trees are walked
+  /// along the same path as `query()`, but records are passed to `agg` by reference instead of
+  /// being collected into a stream.
+  pub async fn aggregate<A>(&self, bbox: &P::Bounds, agg: A) -> Result<A::Output,Error>
+  where A: Aggregate<P,V> {
+    self.snapshot().await?.aggregate(bbox, agg).await
+  }
+  /// Return the number of records that intersect `bbox`. Trees that lie entirely within `bbox`
+  /// are counted from the record count of their `TreeRef` without being loaded.
+  pub async fn count(&self, bbox: &P::Bounds) -> Result<u64,Error> {
+    self.aggregate(bbox, Count::new()).await
+  }
+  /// Return the number of records in the database, from the record counts of the roots. Trees
+  /// written before record counts were stored are loaded and counted until `migrate()` is run.
+  pub async fn len(&self) -> Result<u64,Error> {
+    self.snapshot().await?.len().await
+  }
+  /// Return whether the database holds no records.
+  pub async fn is_empty(&self) -> Result<bool,Error> {
+    Ok(self.len().await? == 0)
+  }
+}
@@ -0,0 +1,77 @@
+use desert::{ToBytes,FromBytes,CountBytes,varint};
+use crate::{Error,checkpoint::{Checkpoints,Checkpoint}};
+
+impl ToBytes for Checkpoints {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    let mut offset = 0;
+    let mut buf = vec![0u8;self.count_bytes()];
+    offset += varint::encode(self.next_seq, &mut buf[offset..])?;
+    offset += varint::encode(self.list.len() as u64, &mut buf[offset..])?;
+    for c in self.list.iter() {
+      offset += varint::encode(c.name.len() as u64, &mut buf[offset..])?;
+      buf[offset..offset+c.name.len()].copy_from_slice(c.name.as_bytes());
+      offset += c.name.len();
+      offset += varint::encode(c.seq, &mut buf[offset..])?;
+      offset += varint::encode(c.meta.len() as u64, &mut buf[offset..])?;
+      buf[offset..offset+c.meta.len()].copy_from_slice(&c.meta);
+      offset += c.meta.len();
+      offset += varint::encode(c.trees.len() as u64, &mut buf[offset..])?;
+      for id in c.trees.iter() {
+        offset += varint::encode(*id, &mut buf[offset..])?;
+      }
+    }
+    Ok(buf)
+  }
+}
+
+impl FromBytes for Checkpoints {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    let mut offset = 0;
+    let (s,next_seq) = varint::decode(&src[offset..])?;
+    offset += s;
+    let (s,len) = varint::decode(&src[offset..])?;
+    offset += s;
+    let mut list = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+      let (s,name_len) = varint::decode(&src[offset..])?;
+      offset += s;
+      let name = String::from_utf8_lossy(&src[offset..offset+(name_len as usize)]).to_string();
+      offset += name_len as usize;
+      let (s,seq) = varint::decode(&src[offset..])?;
+      offset += s;
+      let (s,meta_len) = varint::decode(&src[offset..])?;
+      offset += s;
+      let meta = src[offset..offset+(meta_len as usize)].to_vec();
+      offset += meta_len as usize;
+      let (s,trees_len) = varint::decode(&src[offset..])?;
+      offset += s;
+      let mut trees = Vec::with_capacity(trees_len as usize);
+      for _ in 0..trees_len {
+        let (s,id) = varint::decode(&src[offset..])?;
+        offset += s;
+        trees.push(id);
+      }
+      list.push(Checkpoint { name, seq, meta, trees });
+    }
+    Ok((offset,Self { next_seq, list }))
+  }
+}
+
+impl CountBytes for Checkpoints {
+  fn count_bytes(&self) -> usize {
+    let mut size = varint::length(self.next_seq) + varint::length(self.list.len() as u64);
+    for c in self.list.iter() {
+      size += varint::length(c.name.len() as u64) + c.name.len();
+      size += varint::length(c.seq);
+      size += varint::length(c.meta.len() as u64) + c.meta.len();
+      size += varint::length(c.trees.len() as u64);
+      for id in c.trees.iter() {
+        size += varint::length(*id);
+      }
+    }
+    size
+  }
+  fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+    unimplemented![]
+  }
+}
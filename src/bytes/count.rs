@@ -1,5 +1,6 @@
 use desert::{CountBytes,varint};
 use crate::{Coord,Scalar,Value,Error};
+use super::{TREE_VERSION,tree_ref::encode_count};
 
 macro_rules! impl_count_bytes {
   ($Tree:ident,$Branch:ident,$Node:ident,$count_point_bytes:ident,($($i:tt),+),($($T:tt),+)) => {
@@ -7,7 +8,17 @@ macro_rules! impl_count_bytes {
 
     impl<$($T),+,V> CountBytes for $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
       fn count_bytes(&self) -> usize {
-        let mut bytes = self.root.count_bytes();
+        self.count_bytes_version(TREE_VERSION)
+      }
+      fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+        unimplemented![]
+      }
+    }
+
+    impl<$($T),+,V> $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
+      /// Size of the tree written in format `version`.
+      pub fn count_bytes_version(&self, version: u32) -> usize {
+        let mut bytes = self.root.count_bytes_version(version);
         let mut cursors = vec![&*self.root];
         while let Some(node) = cursors.pop() {
           match &*node {
@@ -25,19 +36,26 @@ macro_rules! impl_count_bytes {
               }
             },
             $Node::Data(_,_) => {
-              bytes += node.count_bytes();
+              bytes += node.count_bytes_version(version);
             },
           }
         }
         bytes
       }
+    }
+
+    impl<$($T),+,V> CountBytes for $Branch<$($T),+,V> where $($T: Scalar),+, V: Value {
+      fn count_bytes(&self) -> usize {
+        self.count_bytes_version(TREE_VERSION)
+      }
       fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
         unimplemented![]
       }
     }
 
-    impl<$($T),+,V> CountBytes for $Branch<$($T),+,V> where $($T: Scalar),+, V: Value {
-      fn count_bytes(&self) -> usize {
+    impl<$($T),+,V> $Branch<$($T),+,V> where $($T: Scalar),+, V: Value {
+      /// Size of the branch written in format `version`.
+      pub fn count_bytes_version(&self, version: u32) -> usize {
         let mut size = 0;
         let mut pivot_len = 0;
         loop {
@@ -51,20 +69,27 @@ macro_rules! impl_count_bytes {
         size += varint::length(self.intersections.len() as u64);
         size += (self.intersections.len()*pivot_len+7)/8;
         for (_,b) in self.intersections.iter() {
-          size += b.count_bytes();
+          size += b.count_bytes_version(version);
         }
         for b in self.nodes.iter() {
-          size += b.count_bytes();
+          size += b.count_bytes_version(version);
         }
         size
       }
+    }
+
+    impl<$($T),+,V> CountBytes for $Node<$($T),+,V> where $($T: Scalar),+, V: Value {
+      fn count_bytes(&self) -> usize {
+        self.count_bytes_version(TREE_VERSION)
+      }
       fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
         unimplemented![]
       }
     }
 
-    impl<$($T),+,V> CountBytes for $Node<$($T),+,V> where $($T: Scalar),+, V: Value {
-      fn count_bytes(&self) -> usize {
+    impl<$($T),+,V> $Node<$($T),+,V> where $($T: Scalar),+, V: Value {
+      /// Size of the node written in format `version`.
+      pub fn count_bytes_version(&self, version: u32) -> usize {
         match &self {
           $Node::Branch(_branch) => 4,
           $Node::Data(rows,refs) => 4
@@ -73,6 +98,7 @@ macro_rules! impl_count_bytes {
             })
             + refs.iter().fold(0usize, |sum,r| {
               sum + varint::length(r.id as u64)
+                + if version >= 2 { varint::length(encode_count(r.count)) } else { 0 }
                 $(+ match &r.bounds.$i {
                   Coord::Interval(x,y) => x.count_bytes() + y.count_bytes(),
                   _ => panic!["unexpected scalar in TreeRef bound"],
@@ -80,9 +106,6 @@ macro_rules! impl_count_bytes {
             }),
         }
       }
-      fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
-        unimplemented![]
-      }
     }
 
     fn $count_point_bytes<$($T),+>(pt: &($(Coord<$T>),+)) -> usize where $($T: Scalar),+ {
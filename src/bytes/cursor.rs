@@ -0,0 +1,115 @@
+use desert::{ToBytes,FromBytes,CountBytes,varint};
+use crate::{Point,TreeRef,Error,EyrosErrorKind,query::QueryCursor};
+use super::tree_ref::{encode_count,decode_count};
+
+// refs are written as in the meta file, with their bounds as a `Point::Bounds`
+
+impl<P> ToBytes for QueryCursor<P> where P: Point {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    let mut offset = 0;
+    let mut buf = vec![0u8;self.count_bytes()];
+    offset += varint::encode(self.refs.len() as u64, &mut buf[offset..])?;
+    for r in self.refs.iter() {
+      offset += write_ref(r, &mut buf[offset..])?;
+    }
+    match &self.tree {
+      Some((r,skip,total,crc)) => {
+        buf[offset] = 1;
+        offset += 1;
+        offset += write_ref(r, &mut buf[offset..])?;
+        offset += varint::encode(*skip, &mut buf[offset..])?;
+        offset += varint::encode(*total, &mut buf[offset..])?;
+        crc.write_bytes(&mut buf[offset..])?;
+      },
+      None => {
+        buf[offset] = 0;
+      },
+    }
+    Ok(buf)
+  }
+}
+
+// cursors come from clients, so any bytes that don't decode fail with CursorInvalid
+impl<P> FromBytes for QueryCursor<P> where P: Point {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    match read_cursor(src) {
+      Ok(x) => Ok(x),
+      Err(_) => EyrosErrorKind::CursorInvalid {}.raise(),
+    }
+  }
+}
+
+fn read_cursor<P: Point>(src: &[u8]) -> Result<(usize,QueryCursor<P>),Error> {
+  let mut offset = 0;
+  let (s,len) = varint::decode(&src[offset..])?;
+  offset += s;
+  // every ref takes at least one byte, so a forged length can't reserve more than that
+  let mut refs = Vec::with_capacity((len as usize).min(src.len()));
+  for _ in 0..len {
+    let (s,r) = read_ref(&src[offset..])?;
+    offset += s;
+    refs.push(r);
+  }
+  let flag = match src.get(offset) {
+    Some(flag) => *flag,
+    None => return EyrosErrorKind::CursorInvalid {}.raise(),
+  };
+  offset += 1;
+  let tree = match flag {
+    0 => None,
+    1 => {
+      let (s,r) = read_ref(&src[offset..])?;
+      offset += s;
+      let (s,skip) = varint::decode(&src[offset..])?;
+      offset += s;
+      let (s,total) = varint::decode(&src[offset..])?;
+      offset += s;
+      let (s,crc) = u32::from_bytes(&src[offset..])?;
+      offset += s;
+      Some((r,skip,total,crc))
+    },
+    _ => return EyrosErrorKind::CursorInvalid {}.raise(),
+  };
+  Ok((offset,QueryCursor { refs, tree }))
+}
+
+impl<P> CountBytes for QueryCursor<P> where P: Point {
+  fn count_bytes(&self) -> usize {
+    let mut size = varint::length(self.refs.len() as u64);
+    for r in self.refs.iter() {
+      size += count_ref(r);
+    }
+    size += 1;
+    if let Some((r,skip,total,crc)) = &self.tree {
+      size += count_ref(r) + varint::length(*skip) + varint::length(*total) + crc.count_bytes();
+    }
+    size
+  }
+  fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+    unimplemented![]
+  }
+}
+
+fn write_ref<P: Point>(r: &TreeRef<P>, buf: &mut [u8]) -> Result<usize,Error> {
+  let mut offset = 0;
+  offset += varint::encode(r.id, &mut buf[offset..])?;
+  offset += varint::encode(encode_count(r.count), &mut buf[offset..])?;
+  offset += r.bounds.to_bounds()?.write_bytes(&mut buf[offset..])?;
+  Ok(offset)
+}
+
+fn read_ref<P: Point>(src: &[u8]) -> Result<(usize,TreeRef<P>),Error> {
+  let mut offset = 0;
+  let (s,id) = varint::decode(&src[offset..])?;
+  offset += s;
+  let (s,count) = varint::decode(&src[offset..])?;
+  offset += s;
+  let (s,bounds) = P::Bounds::from_bytes(&src[offset..])?;
+  offset += s;
+  Ok((offset,TreeRef { id, bounds: P::from_bounds(&bounds), count: decode_count(count) }))
+}
+
+fn count_ref<P: Point>(r: &TreeRef<P>) -> usize {
+  varint::length(r.id) + varint::length(encode_count(r.count))
+    + r.bounds.to_bounds().unwrap().count_bytes()
+}
@@ -1,5 +1,6 @@
 use desert::{FromBytes,varint};
-use crate::{Scalar,Coord,Value,tree::TreeRef,Error};
+use crate::{Scalar,Coord,Value,tree::TreeRef,Error,EyrosErrorKind};
+use super::{FromBytesVersion,TREE_VERSION,tree_ref::decode_count};
 use async_std::sync::Arc;
 
 macro_rules! impl_from_bytes {
@@ -9,16 +10,25 @@ macro_rules! impl_from_bytes {
     use crate::tree::{$Tree,$Branch,$Node};
     impl<$($T),+,V> FromBytes for $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
       fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+        Self::from_bytes_version(src, TREE_VERSION)
+      }
+    }
+
+    impl<$($T),+,V> FromBytesVersion for $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
+      fn from_bytes_version(src: &[u8], version: u32) -> Result<(usize,Self),Error> {
+        if version != 1 && version != 2 {
+          return EyrosErrorKind::UnsupportedVersion { version }.raise();
+        }
         let mut offset = 0;
         let (s,n) = u32::from_bytes(&src[offset..])?;
         offset += s;
         let root = match n%2 {
           0 => {
-            let root = $parse_branch(&src, (n/2) as usize, 0)?;
+            let root = $parse_branch(&src, (n/2) as usize, 0, version)?;
             root
           },
           1 => {
-            let (s,data) = $parse_data(&src[offset..], n as usize)?;
+            let (s,data) = $parse_data(&src[offset..], n as usize, version)?;
             offset += s;
             data
           },
@@ -28,7 +38,7 @@ macro_rules! impl_from_bytes {
       }
     }
 
-    fn $parse_branch<$($T),+,V>(src: &[u8], xoffset: usize, depth: usize)
+    fn $parse_branch<$($T),+,V>(src: &[u8], xoffset: usize, depth: usize, version: u32)
     -> Result<$Node<$($T),+,V>,Error> where $($T: Scalar),+, V: Value {
       let mut offset = xoffset;
       let mut pivots = ($($n),+);
@@ -61,11 +71,11 @@ macro_rules! impl_from_bytes {
           0 => {
             intersections.push((
               bitfield,
-              Arc::new($parse_branch(&src, (n/2) as usize, depth+1)?)
+              Arc::new($parse_branch(&src, (n/2) as usize, depth+1, version)?)
             ));
           },
           1 => {
-            let (s,data) = $parse_data(&src[offset..], n as usize)?;
+            let (s,data) = $parse_data(&src[offset..], n as usize, version)?;
             offset += s;
             intersections.push((bitfield,Arc::new(data)));
           },
@@ -78,10 +88,10 @@ macro_rules! impl_from_bytes {
         offset += s;
         match n%2 {
           0 => {
-            nodes.push(Arc::new($parse_branch(&src, (n/2) as usize, depth+1)?));
+            nodes.push(Arc::new($parse_branch(&src, (n/2) as usize, depth+1, version)?));
           },
           1 => {
-            let (s,data) = $parse_data(&src[offset..], n as usize)?;
+            let (s,data) = $parse_data(&src[offset..], n as usize, version)?;
             offset += s;
             nodes.push(Arc::new(data));
           },
@@ -95,7 +105,8 @@ macro_rules! impl_from_bytes {
       )))
     }
 
-    fn $parse_data<$($T),+,V>(src: &[u8], n: usize) -> Result<(usize,$Node<$($T),+,V>),Error>
+    fn $parse_data<$($T),+,V>(src: &[u8], n: usize, version: u32)
+    -> Result<(usize,$Node<$($T),+,V>),Error>
     where $($T: Scalar),+, V: Value {
       let mut offset = 0;
       let (data_len,ref_len) = ((n>>1)&0xffff,n>>17);
@@ -128,8 +139,18 @@ macro_rules! impl_from_bytes {
       for _i in 0..ref_len {
         let (s,r) = varint::decode(&src[offset..])?;
         offset += s;
+        // version 1 did not store record counts
+        let count = match version {
+          1 => None,
+          _ => {
+            let (s,count) = varint::decode(&src[offset..])?;
+            offset += s;
+            decode_count(count)
+          },
+        };
         let tr = TreeRef {
           id: r,
+          count,
           bounds: {
             $(let $v = {
               let (s,xmin) = $T::from_bytes(&src[offset..])?;
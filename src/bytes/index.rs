@@ -0,0 +1,62 @@
+use desert::{ToBytes,FromBytes,CountBytes,varint};
+use crate::{Error,index::IdIndex};
+use std::collections::HashMap;
+
+impl ToBytes for IdIndex {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    let mut offset = 0;
+    let mut buf = vec![0u8;self.count_bytes()];
+    buf[0..4].copy_from_slice(&self.stamp.to_be_bytes());
+    offset += 4;
+    offset += varint::encode(self.trees.len() as u64, &mut buf[offset..])?;
+    for (hash,trees) in self.trees.iter() {
+      buf[offset..offset+8].copy_from_slice(&hash.to_be_bytes());
+      offset += 8;
+      offset += varint::encode(trees.len() as u64, &mut buf[offset..])?;
+      for id in trees.iter() {
+        offset += varint::encode(*id, &mut buf[offset..])?;
+      }
+    }
+    Ok(buf)
+  }
+}
+
+impl FromBytes for IdIndex {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    let mut offset = 0;
+    let stamp = u32::from_be_bytes([src[0],src[1],src[2],src[3]]);
+    offset += 4;
+    let (s,len) = varint::decode(&src[offset..])?;
+    offset += s;
+    let mut trees = HashMap::with_capacity(len as usize);
+    for _ in 0..len {
+      let mut hash = [0u8;8];
+      hash.copy_from_slice(&src[offset..offset+8]);
+      offset += 8;
+      let (s,n) = varint::decode(&src[offset..])?;
+      offset += s;
+      let mut ids = Vec::with_capacity(n as usize);
+      for _ in 0..n {
+        let (s,id) = varint::decode(&src[offset..])?;
+        offset += s;
+        ids.push(id);
+      }
+      trees.insert(u64::from_be_bytes(hash), ids);
+    }
+    Ok((offset,Self { stamp, trees }))
+  }
+}
+
+impl CountBytes for IdIndex {
+  fn count_bytes(&self) -> usize {
+    let mut size = 4 + varint::length(self.trees.len() as u64);
+    for trees in self.trees.values() {
+      size += 8 + varint::length(trees.len() as u64);
+      size += trees.iter().map(|id| varint::length(*id)).sum::<usize>();
+    }
+    size
+  }
+  fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+    unimplemented![]
+  }
+}
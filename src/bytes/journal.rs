@@ -0,0 +1,81 @@
+use desert::{ToBytes,FromBytes,CountBytes,varint};
+use crate::{Error,EyrosErrorKind,journal::Journal,checksum::crc32c};
+
+impl ToBytes for Journal {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    let mut offset = 0;
+    let mut buf = vec![0u8;self.count_bytes()];
+    offset += varint::encode(self.meta.len() as u64, &mut buf[offset..])?;
+    buf[offset..offset+self.meta.len()].copy_from_slice(&self.meta);
+    offset += self.meta.len();
+    offset += varint::encode(self.updated.len() as u64, &mut buf[offset..])?;
+    for (id,bytes) in self.updated.iter() {
+      offset += varint::encode(*id, &mut buf[offset..])?;
+      offset += varint::encode(bytes.len() as u64, &mut buf[offset..])?;
+      buf[offset..offset+bytes.len()].copy_from_slice(bytes);
+      offset += bytes.len();
+    }
+    offset += varint::encode(self.removed.len() as u64, &mut buf[offset..])?;
+    for id in self.removed.iter() {
+      offset += varint::encode(*id, &mut buf[offset..])?;
+    }
+    crc32c(&buf[0..offset]).write_bytes(&mut buf[offset..])?;
+    Ok(buf)
+  }
+}
+
+impl FromBytes for Journal {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    if src.len() < 4 {
+      return EyrosErrorKind::JournalInvalid {}.raise();
+    }
+    let body = &src[0..src.len()-4];
+    if crc32c(body) != u32::from_bytes(&src[src.len()-4..])?.1 {
+      return EyrosErrorKind::JournalInvalid {}.raise();
+    }
+    let mut offset = 0;
+    let (s,meta_len) = varint::decode(&body[offset..])?;
+    offset += s;
+    let meta = body[offset..offset+(meta_len as usize)].to_vec();
+    offset += meta_len as usize;
+    let (s,updated_len) = varint::decode(&body[offset..])?;
+    offset += s;
+    let mut updated = Vec::with_capacity(updated_len as usize);
+    for _ in 0..updated_len {
+      let (s,id) = varint::decode(&body[offset..])?;
+      offset += s;
+      let (s,len) = varint::decode(&body[offset..])?;
+      offset += s;
+      updated.push((id,body[offset..offset+(len as usize)].to_vec()));
+      offset += len as usize;
+    }
+    let (s,removed_len) = varint::decode(&body[offset..])?;
+    offset += s;
+    let mut removed = Vec::with_capacity(removed_len as usize);
+    for _ in 0..removed_len {
+      let (s,id) = varint::decode(&body[offset..])?;
+      offset += s;
+      removed.push(id);
+    }
+    Ok((src.len(),Self { meta, updated, removed }))
+  }
+}
+
+impl CountBytes for Journal {
+  fn count_bytes(&self) -> usize {
+    let mut size = 0;
+    size += varint::length(self.meta.len() as u64) + self.meta.len();
+    size += varint::length(self.updated.len() as u64);
+    for (id,bytes) in self.updated.iter() {
+      size += varint::length(*id) + varint::length(bytes.len() as u64) + bytes.len();
+    }
+    size += varint::length(self.removed.len() as u64);
+    for id in self.removed.iter() {
+      size += varint::length(*id);
+    }
+    size + 4
+  }
+  fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+    unimplemented![]
+  }
+}
@@ -1,10 +1,16 @@
 use desert::{ToBytes,FromBytes,CountBytes,varint};
-use crate::{Point,Meta,TreeRef,Error,EyrosErrorKind};
+use crate::{Point,Meta,TreeRef,Error,EyrosErrorKind,schema::{Schema,SCHEMA_MAGIC}};
+use super::tree_ref::{encode_count,decode_count};
 
 impl<P> ToBytes for Meta<P> where P: Point, Self: CountBytes {
   fn to_bytes(&self) -> Result<Vec<u8>,Error> {
     let mut offset = 0;
     let mut buf = vec![0u8;self.count_bytes()];
+    if let Some(schema) = &self.schema {
+      buf[0..SCHEMA_MAGIC.len()].copy_from_slice(&SCHEMA_MAGIC);
+      offset += SCHEMA_MAGIC.len();
+      offset += schema.write_bytes(&mut buf[offset..])?;
+    }
     offset += varint::encode(self.next_tree as u64, &mut buf[offset..])?;
     offset += varint::encode(self.roots.len() as u64, &mut buf[offset..])?;
     for (i,r) in self.roots.iter().enumerate() {
@@ -13,10 +19,14 @@ impl<P> ToBytes for Meta<P> where P: Point, Self: CountBytes {
       }
     }
     offset += (self.roots.len()+7)/8;
+    let counts = has_counts(&self.schema);
     for root in self.roots.iter() {
       match root {
         Some(r) => {
           offset += varint::encode(r.id as u64, &mut buf[offset..])?;
+          if counts {
+            offset += varint::encode(encode_count(r.count), &mut buf[offset..])?;
+          }
           //eprintln!["meta:to bounds={:?}", r.bounds.to_bounds().unwrap()];
           offset += r.bounds.to_bounds().unwrap().write_bytes(&mut buf[offset..])?;
         },
@@ -29,7 +39,7 @@ impl<P> ToBytes for Meta<P> where P: Point, Self: CountBytes {
 
 impl<P> FromBytes for Meta<P> where P: Point {
   fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
-    let mut offset = 0;
+    let (mut offset,schema) = Schema::from_meta_bytes(src)?;
     let (n,next_tree) = varint::decode(&src[offset..])?;
     offset += n;
     let (n,len64) = varint::decode(&src[offset..])?;
@@ -41,31 +51,45 @@ impl<P> FromBytes for Meta<P> where P: Point {
     let bitfield = &src[offset..offset+(len+7)/8];
     offset += (len+7)/8;
     let mut roots = Vec::with_capacity(len);
+    let counts = has_counts(&schema);
     for i in 0..(len as usize) {
       if (bitfield[i/8]>>(i%8))&1==1 {
         let (n,id) = varint::decode(&src[offset..])?;
         offset += n;
+        let count = match counts {
+          true => {
+            let (n,count) = varint::decode(&src[offset..])?;
+            offset += n;
+            decode_count(count)
+          },
+          false => None,
+        };
         let (n,bounds) = <P::Bounds>::from_bytes(&src[offset..])?;
         //eprintln!["meta:from bounds={:?}", &bounds];
         offset += n;
-        roots.push(Some(TreeRef { id, bounds: P::from_bounds(&bounds) }));
+        roots.push(Some(TreeRef { id, bounds: P::from_bounds(&bounds), count }));
       } else {
         roots.push(None);
       }
     }
-    Ok((offset,Self { roots, next_tree }))
+    Ok((offset,Self { schema, roots, next_tree }))
   }
 }
 
 impl<P> CountBytes for Meta<P> where P: Point {
   fn count_bytes(&self) -> usize {
     let mut size = 0;
+    if let Some(schema) = &self.schema {
+      size += SCHEMA_MAGIC.len() + schema.count_bytes();
+    }
     size += varint::length(self.next_tree as u64);
     size += varint::length(self.roots.len() as u64);
     size += (self.roots.len()+7)/8;
+    let counts = has_counts(&self.schema);
     for root in self.roots.iter() {
       size += match root {
         Some(r) => varint::length(r.id as u64)
+          + if counts { varint::length(encode_count(r.count)) } else { 0 }
           + r.bounds.to_bounds().unwrap().count_bytes(),
         None => 0,
       }
@@ -76,3 +100,8 @@ impl<P> CountBytes for Meta<P> where P: Point {
     unimplemented![]
   }
 }
+
+// schema version 3 added the record count of each root
+fn has_counts(schema: &Option<Schema>) -> bool {
+  schema.as_ref().map(|s| s.version >= 3).unwrap_or(false)
+}
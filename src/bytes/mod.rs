@@ -1,5 +1,26 @@
+use crate::Error;
+
 mod to;
 mod from;
 mod count;
 mod tree_ref;
 mod meta;
+mod schema;
+mod journal;
+mod checkpoint;
+mod cursor;
+mod index;
+
+/// Version of the tree format written by `ToBytes`, as described in `docs/schema.md`. Version 2
+/// added the record count of each ref. Tree files written without a version are version 1.
+pub const TREE_VERSION: u32 = 2;
+
+/// Decode from any supported version of a format.
+pub trait FromBytesVersion: Sized {
+  fn from_bytes_version(src: &[u8], version: u32) -> Result<(usize,Self),Error>;
+}
+
+/// Encode into any supported version of a format.
+pub trait ToBytesVersion {
+  fn to_bytes_version(&self, version: u32) -> Result<Vec<u8>,Error>;
+}
@@ -0,0 +1,96 @@
+use desert::{ToBytes,FromBytes,CountBytes,varint};
+use crate::{Error,StoredFields,schema::{Schema,SCHEMA_MAGIC}};
+
+impl ToBytes for Schema {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0u8;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes(&self, buf: &mut [u8]) -> Result<usize,Error> {
+    let mut offset = 0;
+    offset += varint::encode(self.version as u64, &mut buf[offset..])?;
+    buf[offset] = self.scalars.len() as u8;
+    offset += 1;
+    buf[offset..offset+self.scalars.len()].copy_from_slice(&self.scalars);
+    offset += self.scalars.len();
+    offset += self.value.write_bytes(&mut buf[offset..])?;
+    if self.version >= 2 {
+      for x in stored_list(&self.setup).iter() {
+        offset += varint::encode(x.map(|x| x as u64 + 1).unwrap_or(0), &mut buf[offset..])?;
+      }
+    }
+    Ok(offset)
+  }
+}
+
+impl FromBytes for Schema {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    let mut offset = 0;
+    let (n,version) = varint::decode(&src[offset..])?;
+    offset += n;
+    let dims = src[offset] as usize;
+    offset += 1;
+    let scalars = src[offset..offset+dims].to_vec();
+    offset += dims;
+    let (n,value) = u32::from_bytes(&src[offset..])?;
+    offset += n;
+    let mut list = [None;7];
+    if version >= 2 {
+      for x in list.iter_mut() {
+        let (n,y) = varint::decode(&src[offset..])?;
+        offset += n;
+        *x = if y == 0 { None } else { Some((y - 1) as usize) };
+      }
+    }
+    let setup = StoredFields {
+      branch_factor: list[0],
+      max_depth: list[1],
+      max_records: list[2],
+      ext_records: list[3],
+      inline: list[4],
+      inline_max_bytes: list[5],
+      rebuild_depth: list[6],
+    };
+    Ok((offset,Self { version: version as u32, scalars, value, setup }))
+  }
+}
+
+impl CountBytes for Schema {
+  fn count_bytes(&self) -> usize {
+    let mut size = varint::length(self.version as u64) + 1 + self.scalars.len() + 4;
+    if self.version >= 2 {
+      for x in stored_list(&self.setup).iter() {
+        size += varint::length(x.map(|x| x as u64 + 1).unwrap_or(0));
+      }
+    }
+    size
+  }
+  fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
+    unimplemented![]
+  }
+}
+
+impl Schema {
+  /// Read the schema header at the front of a meta file, returning the header length and `None`
+  /// for a legacy meta file without one.
+  pub fn from_meta_bytes(src: &[u8]) -> Result<(usize,Option<Self>),Error> {
+    if !src.starts_with(&SCHEMA_MAGIC) {
+      return Ok((0,None));
+    }
+    let (n,schema) = Self::from_bytes(&src[SCHEMA_MAGIC.len()..])?;
+    Ok((SCHEMA_MAGIC.len()+n,Some(schema)))
+  }
+}
+
+fn stored_list(setup: &StoredFields) -> [Option<usize>;7] {
+  [
+    setup.branch_factor,
+    setup.max_depth,
+    setup.max_records,
+    setup.ext_records,
+    setup.inline,
+    setup.inline_max_bytes,
+    setup.rebuild_depth,
+  ]
+}
@@ -1,5 +1,6 @@
-use desert::{ToBytes,CountBytes,varint};
-use crate::{Coord,Scalar,Value,tree::TreeRef,Error};
+use desert::{ToBytes,varint};
+use crate::{Coord,Scalar,Value,tree::TreeRef,Error,EyrosErrorKind};
+use super::{ToBytesVersion,TREE_VERSION,tree_ref::encode_count};
 use std::collections::HashMap;
 
 macro_rules! impl_to_bytes {
@@ -9,25 +10,34 @@ macro_rules! impl_to_bytes {
     use crate::tree::{$Tree,$Branch,$Node};
     impl<$($T),+,V> ToBytes for $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
       fn to_bytes(&self) -> Result<Vec<u8>,Error> {
-        let hsize = self.root.count_bytes();
-        let (alloc,size) = $allocate(&self.root, hsize);
+        self.to_bytes_version(TREE_VERSION)
+      }
+    }
+
+    impl<$($T),+,V> ToBytesVersion for $Tree<$($T),+,V> where $($T: Scalar),+, V: Value {
+      fn to_bytes_version(&self, version: u32) -> Result<Vec<u8>,Error> {
+        if version != 1 && version != 2 {
+          return EyrosErrorKind::UnsupportedVersion { version }.raise();
+        }
+        let hsize = self.root.count_bytes_version(version);
+        let (alloc,size) = $allocate(&self.root, hsize, version);
         let mut buf = vec![0u8;size];
         let mut offset = 0;
         match self.root.as_ref() {
           $Node::Data(data,refs) => {
-            $write_data_bytes(data, refs, &mut buf[offset..])?;
+            $write_data_bytes(data, refs, version, &mut buf[offset..])?;
           },
           $Node::Branch(branch) => {
             offset += ((hsize*2+0) as u32).write_bytes(&mut buf[offset..])?;
-            $write_branch_bytes(branch, &alloc, offset, &mut buf)?;
+            $write_branch_bytes(branch, &alloc, offset, version, &mut buf)?;
           },
         }
         Ok(buf)
       }
     }
 
-    fn $allocate<$($T),+,V>(root: &$Node<$($T),+,V>, hsize: usize) -> (HashMap<usize,(usize,usize)>,usize)
-    where $($T: Scalar),+, V: Value {
+    fn $allocate<$($T),+,V>(root: &$Node<$($T),+,V>, hsize: usize, version: u32)
+    -> (HashMap<usize,(usize,usize)>,usize) where $($T: Scalar),+, V: Value {
       let mut alloc: HashMap<usize,(usize,usize)> = HashMap::new(); // index => (offset, size)
       let mut cursors = vec![root];
       let mut index = 0;
@@ -37,7 +47,7 @@ macro_rules! impl_to_bytes {
         match node {
           $Node::Data(_,_) => {},
           $Node::Branch(branch) => {
-            let size = branch.count_bytes();
+            let size = branch.count_bytes_version(version);
             alloc.insert(index, (offset,size));
             offset += size;
             for (_,b) in branch.intersections.iter() {
@@ -59,7 +69,7 @@ macro_rules! impl_to_bytes {
     }
 
     fn $write_branch_bytes<$($T),+,V>(root: &$Branch<$($T),+,V>, alloc: &HashMap<usize,(usize,usize)>,
-    i_offset: usize, buf: &mut [u8]) -> Result<usize,Error>
+    i_offset: usize, version: u32, buf: &mut [u8]) -> Result<usize,Error>
     where $($T: Scalar),+, V: Value {
       let mut cursors = vec![root];
       let mut offset = i_offset;
@@ -98,7 +108,7 @@ macro_rules! impl_to_bytes {
               xcursors.push(br);
             },
             $Node::Data(data, refs) => {
-              offset += $write_data_bytes(data, refs, &mut buf[offset..])?;
+              offset += $write_data_bytes(data, refs, version, &mut buf[offset..])?;
             },
           }
         }
@@ -111,7 +121,7 @@ macro_rules! impl_to_bytes {
               xcursors.push(br);
             },
             $Node::Data(data, refs) => {
-              offset += $write_data_bytes(data, refs, &mut buf[offset..])?;
+              offset += $write_data_bytes(data, refs, version, &mut buf[offset..])?;
             },
           }
         }
@@ -139,7 +149,7 @@ macro_rules! impl_to_bytes {
     }
 
     fn $write_data_bytes<$($T),+,V>(rows: &[(($(Coord<$T>),+),V)],
-    refs: &[TreeRef<($(Coord<$T>),+)>], buf: &mut [u8]) -> Result<usize,Error>
+    refs: &[TreeRef<($(Coord<$T>),+)>], version: u32, buf: &mut [u8]) -> Result<usize,Error>
     where $($T: Scalar),+, V: Value {
       let mut offset = 0;
       let n = ((rows.len()<<1) + (refs.len()<<17) + 1) as u32;
@@ -150,6 +160,9 @@ macro_rules! impl_to_bytes {
       }
       for r in refs.iter() {
         offset += varint::encode(r.id, &mut buf[offset..])?;
+        if version >= 2 {
+          offset += varint::encode(encode_count(r.count), &mut buf[offset..])?;
+        }
         $(match &r.bounds.$i {
           Coord::Interval(xmin,xmax) => {
             assert![xmin == xmin, "non-idenity serializing xmin={:?}", xmin];
@@ -1,4 +1,4 @@
-use desert::{ToBytes,FromBytes,CountBytes};
+use desert::{ToBytes,FromBytes,CountBytes,varint};
 use crate::{Point,tree::{TreeRef,TreeId},Error};
 
 impl<P> ToBytes for TreeRef<P> where P: Point+ToBytes, Self: CountBytes {
@@ -6,6 +6,7 @@ impl<P> ToBytes for TreeRef<P> where P: Point+ToBytes, Self: CountBytes {
     let mut buf = vec![0u8;self.count_bytes()];
     let mut offset = 0;
     offset += self.id.write_bytes(&mut buf[offset..])?;
+    offset += varint::encode(encode_count(self.count), &mut buf[offset..])?;
     self.bounds.write_bytes(&mut buf[offset..])?;
     Ok(buf)
   }
@@ -16,17 +17,29 @@ impl<P> FromBytes for TreeRef<P> where P: Point+FromBytes {
     let mut offset = 0;
     let (s,id) = TreeId::from_bytes(&src[offset..])?;
     offset += s;
+    let (s,count) = varint::decode(&src[offset..])?;
+    offset += s;
     let (s,bounds) = P::from_bytes(&src[offset..])?;
     offset += s;
-    Ok((offset, Self { id, bounds }))
+    Ok((offset, Self { id, bounds, count: decode_count(count) }))
   }
 }
 
 impl<P> CountBytes for TreeRef<P> where P: Point+CountBytes {
   fn count_bytes(&self) -> usize {
-    self.id.count_bytes() + self.bounds.count_bytes()
+    self.id.count_bytes() + varint::length(encode_count(self.count)) + self.bounds.count_bytes()
   }
   fn count_from_bytes(_src: &[u8]) -> Result<usize,Error> {
     unimplemented![]
   }
 }
+
+/// Record counts are stored as a varint of `count+1` so that 0 can stand for an unknown count.
+pub fn encode_count(count: Option<u64>) -> u64 {
+  count.map(|c| c+1).unwrap_or(0)
+}
+
+/// Decode a record count written by `encode_count()`.
+pub fn decode_count(x: u64) -> Option<u64> {
+  x.checked_sub(1)
+}
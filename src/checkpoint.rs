@@ -0,0 +1,204 @@
+use crate::{DB,Tree,TreeId,Point,Value,Error,EyrosErrorKind,RA,Meta,Storage,SetupFields,
+  tree,checksum,index::IdIndex,store::read_file};
+use async_std::sync::{Arc,Mutex};
+use desert::{ToBytes,FromBytes};
+use std::collections::HashSet;
+
+pub const CHECKPOINTS_FILE: &str = "checkpoints";
+// written in full before CHECKPOINTS_FILE, so an interrupted write leaves one complete copy
+pub const CHECKPOINTS_NEXT_FILE: &str = "checkpoints.next";
+pub const CHECKPOINTS_VERSION: u32 = 1;
+
+/// Every checkpoint of a database, as stored in the `checkpoints` file.
+#[derive(Debug,Clone,Default)]
+pub struct Checkpoints {
+  /// Sequence number of the next checkpoint. Copies are stored by sequence number rather than by
+  /// name so copies left behind by a replaced or dropped checkpoint never belong to a new one.
+  pub next_seq: u64,
+  pub list: Vec<Checkpoint>,
+}
+
+/// The serialized meta of the database when `DB::checkpoint()` was called, with the ids of every
+/// tree reachable from its roots.
+#[derive(Debug,Clone)]
+pub struct Checkpoint {
+  pub name: String,
+  pub seq: u64,
+  pub meta: Vec<u8>,
+  pub trees: Vec<TreeId>,
+}
+
+/// Return the file that holds the version of tree `id` from the checkpoint with sequence number
+/// `seq` once the tree file has been overwritten or removed.
+pub fn get_copy_file_from_id(seq: u64, id: &TreeId) -> String {
+  format!["c/{}/{}", seq, tree::get_file_from_id(id)]
+}
+
+impl Checkpoints {
+  /// Read the checkpoints of a database, finishing a write that was interrupted.
+  pub async fn read<S>(
+    fields: &SetupFields,
+    storage: &Arc<Mutex<Box<dyn Storage<S>>>>,
+  ) -> Result<Self,Error> where S: RA {
+    let next = read_file(storage, CHECKPOINTS_NEXT_FILE).await?;
+    if let checksum::Trailer::Valid(body,_) = checksum::check_trailer(&next) {
+      fields.log("found complete checkpoints.next").await?;
+      if !fields.read_only {
+        write_file(storage, CHECKPOINTS_FILE, &next).await?;
+        write_file(storage, CHECKPOINTS_NEXT_FILE, &[]).await?;
+      }
+      return Ok(Self::from_bytes(body)?.1);
+    }
+    let bytes = read_file(storage, CHECKPOINTS_FILE).await?;
+    if bytes.is_empty() {
+      return Ok(Self::default());
+    }
+    match checksum::check_trailer(&bytes) {
+      checksum::Trailer::Valid(body,_) => Ok(Self::from_bytes(body)?.1),
+      _ => EyrosErrorKind::ChecksumMismatch { id: None, file: CHECKPOINTS_FILE.into() }.raise(),
+    }
+  }
+  /// Write the checkpoints and wait for them to be durable.
+  pub async fn write<S>(&self, storage: &Arc<Mutex<Box<dyn Storage<S>>>>) -> Result<(),Error>
+  where S: RA {
+    let mut bytes = self.to_bytes()?;
+    checksum::append_trailer(&mut bytes, CHECKPOINTS_VERSION);
+    write_file(storage, CHECKPOINTS_NEXT_FILE, &bytes).await?;
+    write_file(storage, CHECKPOINTS_FILE, &bytes).await?;
+    write_file(storage, CHECKPOINTS_NEXT_FILE, &[]).await?;
+    Ok(())
+  }
+  fn take(&mut self, name: &str) -> Option<Checkpoint> {
+    let i = self.list.iter().position(|c| c.name == name)?;
+    Some(self.list.remove(i))
+  }
+}
+
+async fn write_file<S>(storage: &Arc<Mutex<Box<dyn Storage<S>>>>, file: &str, bytes: &[u8])
+-> Result<(),Error> where S: RA {
+  let mut s = storage.lock().await.open(file).await?;
+  if !bytes.is_empty() {
+    s.write(0, bytes).await?;
+  }
+  s.truncate(bytes.len() as u64).await?;
+  s.sync_all().await?;
+  Ok(())
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Record the current state of the database as a checkpoint called `name`, replacing any
+  /// checkpoint with the same name. Pending changes are synced first.
+  ///
+  /// A checkpoint stores a copy of the meta and pins every tree reachable from its roots. Before a
+  /// sync overwrites or removes a pinned tree file, the file is copied under `c/` so the checkpoint
+  /// can be restored with `restore()` no matter what is written afterward. The copies are removed
+  /// by `drop_checkpoint()`.
+  pub async fn checkpoint(&mut self, name: &str) -> Result<(),Error> {
+    self.check_writable("checkpoint")?;
+    let _writer = self.writer.lock().await;
+    self.sync_inner().await?;
+    let mut checkpoints = Checkpoints::read(&self.fields, &self.storage).await?;
+    let meta = self.meta.read().await.to_bytes()?;
+    let trees = self.live_trees().await?;
+    let seq = checkpoints.next_seq;
+    checkpoints.next_seq += 1;
+    let replaced = checkpoints.take(name);
+    self.fields.log(&format![
+      "checkpoint name={:?} seq={} trees={}", name, seq, trees.len()
+    ]).await?;
+    checkpoints.list.push(Checkpoint {
+      name: name.into(),
+      seq,
+      meta,
+      trees: trees.into_iter().collect(),
+    });
+    checkpoints.list.sort_by(|a,b| a.name.cmp(&b.name));
+    checkpoints.write(&self.storage).await?;
+    self.trees.set_checkpoints(&checkpoints).await;
+    if let Some(c) = replaced {
+      self.remove_copies(&c).await;
+    }
+    Ok(())
+  }
+  /// Return the names of the checkpoints of the database in sorted order.
+  pub async fn list_checkpoints(&self) -> Result<Vec<String>,Error> {
+    let checkpoints = Checkpoints::read(&self.fields, &self.storage).await?;
+    Ok(checkpoints.list.into_iter().map(|c| c.name).collect())
+  }
+  /// Roll the database back to the checkpoint called `name` and sync. Records written since the
+  /// checkpoint was taken are discarded, including pending changes. The checkpoint is kept, so
+  /// the database can be restored to it again.
+  ///
+  /// Fails with `EyrosErrorKind::CheckpointMissing` if there is no checkpoint called `name`.
+  pub async fn restore(&mut self, name: &str) -> Result<(),Error> {
+    self.check_writable("restore")?;
+    let _writer = self.writer.lock().await;
+    let checkpoints = Checkpoints::read(&self.fields, &self.storage).await?;
+    let c = match checkpoints.list.iter().find(|c| c.name == name) {
+      Some(c) => c,
+      None => return EyrosErrorKind::CheckpointMissing { name: name.into() }.raise(),
+    };
+    let (_,c_meta) = Meta::<P>::from_bytes(&c.meta)?;
+    self.fields.log(&format!["restore name={:?} seq={}", name, c.seq]).await?;
+    // sync first so every tree file the checkpoint can no longer read from has been copied
+    self.sync_inner().await?;
+    let live = self.live_trees().await?;
+    let pinned: HashSet<TreeId> = c.trees.iter().copied().collect();
+    {
+      let mut meta = self.meta.write().await;
+      for id in c.trees.iter() {
+        match self.trees.read_copy(c.seq, id).await? {
+          Some(t) => self.trees.put(id, Arc::new(Mutex::new(t))).await?,
+          None => self.trees.revive(id).await,
+        }
+      }
+      for id in live.difference(&pinned) {
+        self.trees.remove(id).await?;
+      }
+      meta.roots = c_meta.roots;
+      meta.next_tree = meta.next_tree.max(c_meta.next_tree);
+      if let Some(index) = &self.index {
+        *index.lock().await = IdIndex::rebuild(&self.trees, &meta.roots).await?;
+      }
+    }
+    self.sync_inner().await?;
+    // the tree files match the checkpoint again, so the copies are no longer needed
+    self.remove_copies(c).await;
+    Ok(())
+  }
+  /// Remove the checkpoint called `name` along with the copies of the trees it pinned.
+  ///
+  /// Fails with `EyrosErrorKind::CheckpointMissing` if there is no checkpoint called `name`.
+  pub async fn drop_checkpoint(&mut self, name: &str) -> Result<(),Error> {
+    self.check_writable("drop a checkpoint of")?;
+    let _writer = self.writer.lock().await;
+    let mut checkpoints = Checkpoints::read(&self.fields, &self.storage).await?;
+    let c = match checkpoints.take(name) {
+      Some(c) => c,
+      None => return EyrosErrorKind::CheckpointMissing { name: name.into() }.raise(),
+    };
+    self.fields.log(&format!["drop checkpoint name={:?} seq={}", name, c.seq]).await?;
+    checkpoints.write(&self.storage).await?;
+    self.trees.set_checkpoints(&checkpoints).await;
+    self.remove_copies(&c).await;
+    Ok(())
+  }
+  // ignores errors: trees that were never overwritten or removed have no copy
+  async fn remove_copies(&self, c: &Checkpoint) {
+    for id in c.trees.iter() {
+      let _ = self.storage.lock().await.remove(&get_copy_file_from_id(c.seq, id)).await;
+    }
+  }
+  async fn live_trees(&self) -> Result<HashSet<TreeId>,Error> {
+    let mut live: HashSet<TreeId> = HashSet::new();
+    let mut ids: Vec<TreeId> = self.meta.read().await.roots.iter()
+      .filter_map(|r| r.as_ref().map(|r| r.id))
+      .collect();
+    while let Some(id) = ids.pop() {
+      if !live.insert(id) { continue }
+      let refs = self.trees.get(&id).await?.lock().await.list_refs();
+      ids.extend(refs.iter().map(|r| r.id));
+    }
+    Ok(live)
+  }
+}
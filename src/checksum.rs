@@ -0,0 +1,75 @@
+// crc32c (castagnoli) checksums for detecting torn or corrupted writes
+
+const POLY: u32 = 0x82f63b78;
+const TABLE: [u32;256] = table();
+
+const fn table() -> [u32;256] {
+  let mut t = [0u32;256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 == 1 { (c >> 1) ^ POLY } else { c >> 1 };
+      k += 1;
+    }
+    t[i] = c;
+    i += 1;
+  }
+  t
+}
+
+pub fn crc32c(data: &[u8]) -> u32 {
+  let mut c = !0u32;
+  for b in data.iter() {
+    c = TABLE[((c ^ (*b as u32)) & 0xff) as usize] ^ (c >> 8);
+  }
+  !c
+}
+
+/// Marks the end of a file written with a versioned checksum trailer: a format version byte, the
+/// crc32c of the file contents and version byte as a big-endian u32, then these bytes.
+pub const TRAILER_MAGIC: [u8;4] = [0x00,b'e',b'y',b'v'];
+pub const TRAILER_LEN: usize = 1 + 4 + TRAILER_MAGIC.len();
+
+pub enum Trailer<'a> {
+  /// The contents matched the checksum in the trailer, written with this format version.
+  Valid(&'a [u8], u32),
+  /// The contents did not match the checksum in the trailer.
+  Mismatch,
+  /// The file was written without a trailer.
+  Missing(&'a [u8]),
+}
+
+/// Append a checksum trailer for the contents of `buf` written in format `version`.
+pub fn append_trailer(buf: &mut Vec<u8>, version: u32) {
+  buf.push(version as u8);
+  let sum = crc32c(buf);
+  buf.extend_from_slice(&sum.to_be_bytes());
+  buf.extend_from_slice(&TRAILER_MAGIC);
+}
+
+/// Split the contents of a file from its checksum trailer and check them.
+pub fn check_trailer(src: &[u8]) -> Trailer<'_> {
+  if src.len() >= TRAILER_LEN && src.ends_with(&TRAILER_MAGIC) {
+    let (body,trailer) = src.split_at(src.len() - TRAILER_LEN);
+    let sum = u32::from_be_bytes([trailer[1],trailer[2],trailer[3],trailer[4]]);
+    return if crc32c(&src[0..src.len()-TRAILER_LEN+1]) == sum {
+      Trailer::Valid(body, trailer[0] as u32)
+    } else {
+      Trailer::Mismatch
+    };
+  }
+  Trailer::Missing(src)
+}
+
+/// Return the format version recorded in the trailer at the end of `tail`, which holds at least
+/// the last `TRAILER_LEN` bytes of a file, or `None` for a file without a versioned trailer.
+/// The checksum is not checked.
+pub fn trailer_version(tail: &[u8]) -> Option<u32> {
+  if tail.len() >= TRAILER_LEN && tail.ends_with(&TRAILER_MAGIC) {
+    Some(tail[tail.len()-TRAILER_LEN] as u32)
+  } else {
+    None
+  }
+}
@@ -16,7 +16,20 @@ pub enum EyrosErrorKind {
   IntervalSides { dimension: usize, min: String, max: String },
   TreeRemoved { id: TreeId },
   TreeEmpty { id: TreeId, file: String },
-  RemoveIdsMissing { ids: Vec<String> }
+  RemoveIdsMissing { ids: Vec<String> },
+  JournalInvalid {},
+  SchemaMismatch { expected: String, found: String },
+  ChecksumMismatch { id: Option<TreeId>, file: String },
+  TreeCycle { id: TreeId },
+  UnsupportedVersion { version: u32 },
+  ReadOnly { operation: String },
+  Locked { file: String },
+  CheckpointMissing { name: String },
+  DimensionOutOfRange { dimension: usize, dimensions: usize },
+  CursorStale { id: TreeId },
+  CursorInvalid {},
+  PageSizeZero {},
+  IdIndexDisabled { operation: String },
 }
 
 impl EyrosErrorKind {
@@ -28,6 +41,12 @@ impl EyrosErrorKind {
   }
 }
 
+impl EyrosError {
+  pub fn kind(&self) -> &EyrosErrorKind {
+    &self.kind
+  }
+}
+
 impl std::error::Error for EyrosError {
 }
 
@@ -53,6 +72,49 @@ impl std::fmt::Display for EyrosError {
       EyrosErrorKind::RemoveIdsMissing { ids } => {
         write![f, "ids not found during remove(): {}", ids.join(", ")]
       },
+      EyrosErrorKind::JournalInvalid {} => {
+        write![f, "journal is incomplete or corrupt"]
+      },
+      EyrosErrorKind::SchemaMismatch { expected, found } => {
+        write![f, "database schema {} does not match the types it was opened with {}",
+          found, expected]
+      },
+      EyrosErrorKind::ChecksumMismatch { id: Some(id), file } => {
+        write![f, "checksum mismatch for tree with id={} located at file={}", id, file]
+      },
+      EyrosErrorKind::ChecksumMismatch { id: None, file } => {
+        write![f, "checksum mismatch for file={}", file]
+      },
+      EyrosErrorKind::TreeCycle { id } => {
+        write![f, "tree with id={} is referenced from a tree beneath it", id]
+      },
+      EyrosErrorKind::UnsupportedVersion { version } => {
+        write![f, "unsupported format version {}", version]
+      },
+      EyrosErrorKind::ReadOnly { operation } => {
+        write![f, "cannot {} a database opened read-only", operation]
+      },
+      EyrosErrorKind::Locked { file } => {
+        write![f, "database is locked by another writer (lock={})", file]
+      },
+      EyrosErrorKind::CheckpointMissing { name } => {
+        write![f, "checkpoint not found: {}", name]
+      },
+      EyrosErrorKind::DimensionOutOfRange { dimension, dimensions } => {
+        write![f, "dimension {} out of range for points with {} dimensions", dimension, dimensions]
+      },
+      EyrosErrorKind::CursorStale { id } => {
+        write![f, "query cursor refers to tree with id={} which was removed or changed", id]
+      },
+      EyrosErrorKind::CursorInvalid {} => {
+        write![f, "query cursor could not be decoded or does not describe a position"]
+      },
+      EyrosErrorKind::PageSizeZero {} => {
+        write![f, "page_size must be greater than 0"]
+      },
+      EyrosErrorKind::IdIndexDisabled { operation } => {
+        write![f, "cannot {} by id without Setup::id_index(true)", operation]
+      },
     }
   }
 }
@@ -0,0 +1,54 @@
+use crate::{DB,Tree,TreeId,Point,Value,Error,RA,tree,checkpoint::Checkpoints};
+use std::collections::HashSet;
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Remove tree files that are not reachable from the database roots, such as files left behind
+  /// when removing a replaced tree failed, and copies of trees under `c/` that belong to no
+  /// checkpoint. Pending changes are synced first. Returns the names of the removed files.
+  ///
+  /// Files are found with `Storage::list()`, so storage that can't list its files removes
+  /// nothing. A tree that can't be loaded stops the collection with an error rather than risk
+  /// removing the trees it references.
+  pub async fn gc(&mut self) -> Result<Vec<String>,Error> {
+    self.check_writable("gc")?;
+    let _writer = self.writer.lock().await;
+    self.sync_inner().await?;
+    self.fields.log("gc begin").await?;
+    let mut live: HashSet<TreeId> = HashSet::new();
+    let mut ids: Vec<TreeId> = self.meta.read().await.roots.iter()
+      .filter_map(|r| r.as_ref().map(|r| r.id))
+      .collect();
+    while let Some(id) = ids.pop() {
+      if !live.insert(id) { continue }
+      let refs = self.trees.get(&id).await?.lock().await.list_refs();
+      ids.extend(refs.iter().map(|r| r.id));
+    }
+    // files that a snapshot can still read are removed by a sync once the snapshot is dropped
+    let deferred = self.trees.removed_ids().await;
+    let live_files: HashSet<String> = live.iter().chain(deferred.iter())
+      .map(tree::get_file_from_id)
+      .collect();
+    let files = self.storage.lock().await.list("t/").await?;
+    let mut removed = vec![];
+    for file in files {
+      if live_files.contains(&file) { continue }
+      self.fields.log(&format!["gc remove file={}", &file]).await?;
+      self.storage.lock().await.remove(&file).await?;
+      removed.push(file);
+    }
+    // copies left behind when replacing or dropping a checkpoint was interrupted
+    let checkpoints = Checkpoints::read(&self.fields, &self.storage).await?;
+    let prefixes: Vec<String> = checkpoints.list.iter().map(|c| format!["c/{}/", c.seq]).collect();
+    let files = self.storage.lock().await.list("c/").await?;
+    for file in files {
+      if prefixes.iter().any(|p| file.starts_with(p)) { continue }
+      self.fields.log(&format!["gc remove file={}", &file]).await?;
+      self.storage.lock().await.remove(&file).await?;
+      removed.push(file);
+    }
+    self.fields.log(&format![
+      "gc complete: {} live trees, {} files removed", live.len(), removed.len()
+    ]).await?;
+    Ok(removed)
+  }
+}
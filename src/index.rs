@@ -0,0 +1,188 @@
+use crate::{DB,Tree,TreeId,TreeRef,Point,Value,Error,RA,Storage,SetupFields,
+  checksum,tree_file::TreeFile,store::read_file};
+use async_std::sync::{Arc,Mutex};
+use desert::{ToBytes,FromBytes};
+use std::collections::{HashMap,HashSet,hash_map::Entry};
+use std::hash::{Hash,Hasher};
+
+pub const INDEX_FILE: &str = "index";
+pub const INDEX_VERSION: u32 = 1;
+
+/// Index from the `Value::Id` of each record to the trees that may hold it, enabled by
+/// `Setup::id_index()` and stored in the `index` file.
+///
+/// Ids are kept as a stable 64-bit hash since `Value::Id` has no byte encoding, so an entry can
+/// name a tree holding a different record with the same hash. Lookups read the tree to confirm.
+/// The whole index is written after a sync that changed the meta, with a checksum of the meta it
+/// matches, and rebuilt from the trees when it was not written for the current meta.
+#[derive(Debug,Clone,Default)]
+pub struct IdIndex {
+  /// crc32c of the meta file this index was written for.
+  pub stamp: u32,
+  pub trees: HashMap<u64,Vec<TreeId>>,
+}
+
+impl IdIndex {
+  /// Read the index, or `None` if it is missing, corrupt, or does not match the meta with
+  /// checksum `stamp`.
+  pub async fn read<S>(
+    fields: &SetupFields,
+    storage: &Arc<Mutex<Box<dyn Storage<S>>>>,
+    stamp: u32,
+  ) -> Result<Option<Self>,Error> where S: RA {
+    let bytes = read_file(storage, INDEX_FILE).await?;
+    if bytes.is_empty() { return Ok(None) }
+    let index = match checksum::check_trailer(&bytes) {
+      checksum::Trailer::Valid(body,_) => Self::from_bytes(body)?.1,
+      _ => {
+        fields.log("index checksum mismatch").await?;
+        return Ok(None);
+      },
+    };
+    if index.stamp != stamp {
+      fields.log("index was written for another meta").await?;
+      return Ok(None);
+    }
+    Ok(Some(index))
+  }
+  /// Write the index for the meta with checksum `stamp` and wait for it to be durable. Nothing is
+  /// written when the index was already read or written for that meta, since every change to the
+  /// index also changes the meta.
+  pub async fn write<S>(&mut self, storage: &Arc<Mutex<Box<dyn Storage<S>>>>, stamp: u32)
+  -> Result<(),Error> where S: RA {
+    if self.stamp == stamp { return Ok(()) }
+    self.stamp = stamp;
+    let mut bytes = self.to_bytes()?;
+    checksum::append_trailer(&mut bytes, INDEX_VERSION);
+    let mut s = storage.lock().await.open(INDEX_FILE).await?;
+    s.write(0, &bytes).await?;
+    s.truncate(bytes.len() as u64).await?;
+    s.sync_all().await?;
+    Ok(())
+  }
+  /// Return the trees that may hold the record with `id`.
+  pub fn get<I: Hash>(&self, id: &I) -> Vec<TreeId> {
+    self.trees.get(&hash_id(id)).cloned().unwrap_or_default()
+  }
+  /// Record that `tree` holds the record with `id`, dropping any of the `removed` trees.
+  pub fn insert<I: Hash>(&mut self, id: &I, tree: TreeId, removed: &HashSet<TreeId>) {
+    let trees = self.trees.entry(hash_id(id)).or_default();
+    trees.retain(|t| !removed.contains(t));
+    if !trees.contains(&tree) {
+      trees.push(tree);
+    }
+  }
+  /// Return the record with `id` and the tree that holds it, from the trees the index names for it.
+  pub async fn locate<S,T,P,V>(&self, trees: &TreeFile<S,T,P,V>, id: &V::Id)
+  -> Result<Option<(TreeId,(P,V))>,Error> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+    for t in self.get(id) {
+      let (list,_) = trees.get(&t).await?.lock().await.list();
+      if let Some(pv) = list.into_iter().find(|(_,v)| v.get_id() == *id) {
+        return Ok(Some((t,pv)));
+      }
+    }
+    Ok(None)
+  }
+  /// Update the index after a batch removed the records with the ids in `deletes`, rebuilt the
+  /// `replaced` trees in place, and replaced the `removed` trees with the `created` trees.
+  ///
+  /// Other records can share the hash of a deleted id, so a tree is only dropped from the entry of
+  /// a deleted id when it was removed or its rebuilt version holds no record with that hash.
+  pub async fn update<T,P,V>(
+    &mut self,
+    deletes: &[(P,V::Id)],
+    removed: &[TreeId],
+    created: Vec<(TreeId,Arc<Mutex<T>>)>,
+    replaced: &HashMap<TreeId,Arc<Mutex<T>>>,
+  ) where P: Point, V: Value, T: Tree<P,V> {
+    let removed: HashSet<TreeId> = removed.iter().copied().collect();
+    let mut held: HashMap<TreeId,HashSet<u64>> = HashMap::new();
+    for (_,id) in deletes.iter() {
+      let h = hash_id(id);
+      let trees = match self.trees.remove(&h) {
+        Some(trees) => trees,
+        None => continue,
+      };
+      let mut keep = Vec::with_capacity(trees.len());
+      for t in trees {
+        if removed.contains(&t) { continue }
+        if let Some(x) = replaced.get(&t) {
+          let hashes = match held.entry(t) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+              e.insert(x.lock().await.list().0.iter().map(|(_,v)| hash_id(&v.get_id())).collect())
+            },
+          };
+          if !hashes.contains(&h) { continue }
+        }
+        keep.push(t);
+      }
+      if !keep.is_empty() {
+        self.trees.insert(h, keep);
+      }
+    }
+    for (id,t) in created {
+      for (_,v) in t.lock().await.list().0.iter() {
+        self.insert(&v.get_id(), id, &removed);
+      }
+    }
+  }
+  /// Build the index from every tree reachable from `roots`.
+  pub async fn rebuild<S,T,P,V>(trees: &TreeFile<S,T,P,V>, roots: &[Option<TreeRef<P>>])
+  -> Result<Self,Error> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+    let mut index = Self::default();
+    let mut ids: Vec<TreeId> = roots.iter().flatten().map(|r| r.id).collect();
+    let none = HashSet::new();
+    while let Some(id) = ids.pop() {
+      let (list,refs) = trees.get(&id).await?.lock().await.list();
+      for (_,v) in list.iter() {
+        index.insert(&v.get_id(), id, &none);
+      }
+      ids.extend(refs.iter().map(|r| r.id));
+    }
+    Ok(index)
+  }
+}
+
+/// FNV-1a with integers written in little-endian order and lengths as 64 bits, so that hashes are
+/// the same on every platform and across releases.
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+  fn write(&mut self, bytes: &[u8]) {
+    for b in bytes.iter() {
+      self.0 ^= *b as u64;
+      self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+  }
+  fn write_u16(&mut self, x: u16) { self.write(&x.to_le_bytes()) }
+  fn write_u32(&mut self, x: u32) { self.write(&x.to_le_bytes()) }
+  fn write_u64(&mut self, x: u64) { self.write(&x.to_le_bytes()) }
+  fn write_u128(&mut self, x: u128) { self.write(&x.to_le_bytes()) }
+  fn write_usize(&mut self, x: usize) { self.write_u64(x as u64) }
+  fn write_i16(&mut self, x: i16) { self.write(&x.to_le_bytes()) }
+  fn write_i32(&mut self, x: i32) { self.write(&x.to_le_bytes()) }
+  fn write_i64(&mut self, x: i64) { self.write(&x.to_le_bytes()) }
+  fn write_i128(&mut self, x: i128) { self.write(&x.to_le_bytes()) }
+  fn write_isize(&mut self, x: isize) { self.write_i64(x as i64) }
+}
+
+fn hash_id<I: Hash>(id: &I) -> u64 {
+  let mut h = IdHasher(0xcbf29ce484222325);
+  id.hash(&mut h);
+  h.finish()
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Return the record with `id`, found through the index enabled by `Setup::id_index(true)`
+  /// without knowing where the record is.
+  ///
+  /// Fails with `EyrosErrorKind::IdIndexDisabled` if the database was opened without the index.
+  pub async fn get(&self, id: &V::Id) -> Result<Option<(P,V)>,Error> {
+    let _meta = self.meta.read().await;
+    self.lookup_id(id, "get a record").await
+  }
+}
@@ -0,0 +1,108 @@
+use crate::{Storage,Error,EyrosErrorKind,RA,SetupFields,TreeId,tree,store::read_file};
+use async_std::sync::{Arc,Mutex};
+use desert::{ToBytes,FromBytes};
+use futures::future::join_all;
+
+pub const JOURNAL_FILE: &str = "journal";
+
+/// Every change made by a sync: the serialized tree files to write, the new meta, and the trees
+/// to remove. The journal is written and made durable before any tree file or the meta is
+/// touched, so an interrupted sync can be finished by replaying the journal when the database
+/// is opened again. A journal that was itself only partly written fails its checksum and is
+/// discarded, leaving the previous meta and trees in place.
+#[derive(Debug,Clone)]
+pub struct Journal {
+  pub meta: Vec<u8>,
+  pub updated: Vec<(TreeId,Vec<u8>)>,
+  pub removed: Vec<TreeId>,
+}
+
+impl Journal {
+  /// Write the journal to storage and wait for it to be durable.
+  pub async fn write<S>(&self, storage: &Arc<Mutex<Box<dyn Storage<S>>>>) -> Result<(),Error>
+  where S: RA {
+    let bytes = self.to_bytes()?;
+    let mut s = storage.lock().await.open(JOURNAL_FILE).await?;
+    s.write(0, &bytes).await?;
+    s.truncate(bytes.len() as u64).await?;
+    s.sync_all().await?;
+    Ok(())
+  }
+  /// Write updated trees, then the meta, then remove trees that are no longer referenced.
+  /// Files are truncated to the written length so a shorter rewrite leaves no stale bytes behind.
+  /// Applying the same journal more than once has the same result as applying it once.
+  pub async fn apply<S>(
+    &self,
+    fields: &SetupFields,
+    storage: &Arc<Mutex<Box<dyn Storage<S>>>>,
+    meta_store: &mut S,
+  ) -> Result<(),Error> where S: RA {
+    let work = self.updated.iter().map(|(id,bytes)| async move {
+      let file = tree::get_file_from_id(id);
+      fields.log(&format!["sync tree (updated) id={} file={}", id, &file]).await?;
+      let mut s = storage.lock().await.open(&file).await?;
+      s.write(0, bytes).await?;
+      s.truncate(bytes.len() as u64).await?;
+      s.sync_all().await?;
+      let r: Result<(),Error> = Ok(());
+      r
+    });
+    for r in join_all(work).await { r?; }
+    meta_store.write(0, &self.meta).await?;
+    meta_store.truncate(self.meta.len() as u64).await?;
+    meta_store.sync_all().await?;
+    let work = self.removed.iter().map(|id| async move {
+      let file = tree::get_file_from_id(id);
+      fields.log(&format!["sync tree (remove) id={} file={}", id, &file]).await?;
+      // ignore errors
+      match storage.lock().await.remove(&file).await {
+        Ok(()) => {},
+        Err(_err) => {}
+      }
+      let r: Result<(),Error> = Ok(());
+      r
+    });
+    for r in join_all(work).await { r?; }
+    Ok(())
+  }
+  /// Mark the journal as fully applied.
+  pub async fn clear<S>(storage: &Arc<Mutex<Box<dyn Storage<S>>>>) -> Result<(),Error>
+  where S: RA {
+    let mut s = storage.lock().await.open(JOURNAL_FILE).await?;
+    s.truncate(0).await?;
+    s.sync_all().await?;
+    Ok(())
+  }
+  /// Finish or discard a sync that was interrupted before the journal was cleared.
+  pub async fn recover<S>(
+    fields: &SetupFields,
+    storage: &Arc<Mutex<Box<dyn Storage<S>>>>,
+  ) -> Result<(),Error> where S: RA {
+    let bytes = read_file(storage, JOURNAL_FILE).await?;
+    if bytes.is_empty() { return Ok(()) }
+    match Self::from_bytes(&bytes) {
+      Ok(_) if fields.read_only => {
+        // replaying writes to the trees and meta, which may be partly written until it's done
+        EyrosErrorKind::ReadOnly { operation: "replay the journal of".into() }.raise()
+      },
+      Err(_) if fields.read_only => {
+        // the journal was not finished, so nothing else has been written yet
+        fields.log("ignoring incomplete journal (read-only)").await?;
+        Ok(())
+      },
+      Ok((_,journal)) => {
+        fields.log(&format![
+          "replaying journal with {} updated and {} removed trees",
+          journal.updated.len(), journal.removed.len()
+        ]).await?;
+        let mut meta_store = storage.lock().await.open("meta").await?;
+        journal.apply(fields, storage, &mut meta_store).await?;
+        Self::clear(storage).await
+      },
+      Err(_) => {
+        fields.log("discarding incomplete journal").await?;
+        Self::clear(storage).await
+      },
+    }
+  }
+}
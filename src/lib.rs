@@ -4,15 +4,16 @@
 mod error;
 pub use error::{EyrosError,EyrosErrorKind,Error};
 mod store;
-pub use store::Storage;
+pub use store::{Storage,StorageLock,MemoryStore,MemoryFile};
 #[cfg(not(feature="wasm"))] #[doc(hidden)] pub use store::FileStore;
 mod setup;
-pub use setup::{Setup,SetupFields};
+pub use setup::{Setup,SetupFields,StoredFields};
 pub mod tree;
 #[doc(hidden)] pub use tree::{Tree,TreeRef,TreeId,Merge};
 mod bytes;
+#[doc(hidden)] pub use bytes::{FromBytesVersion,ToBytesVersion,TREE_VERSION};
 mod query;
-pub use query::QTrace;
+pub use query::{QTrace,QueryFields,QueryOptions,QueryMode,Order,QueryCursor};
 mod unfold;
 mod tree_file;
 use tree_file::TreeFile;
@@ -24,28 +25,67 @@ mod batch;
 pub use batch::{BatchFields,BatchOptions};
 mod debugger;
 pub use debugger::Debugger;
+mod checksum;
+mod journal;
+mod schema;
+#[doc(hidden)] pub use schema::{Schema,SCHEMA_VERSION,UNCHECKED_VERSION};
+mod verify;
+pub use verify::{VerifyReport,VerifyIssue};
+mod gc;
+mod migrate;
+mod snapshot;
+pub use snapshot::Snapshot;
+mod checkpoint;
+mod nearest;
+pub use nearest::{Metric,MetricFn};
+mod aggregate;
+pub use aggregate::{Aggregate,Count,Fold};
+mod shape;
+pub use shape::{QueryShape,Ball,MultiBounds};
+#[cfg(feature="2d")] pub use shape::Polygon;
+mod index;
+use index::IdIndex;
 
 use async_std::{sync::{Arc,Mutex,RwLock}};
 use random_access_storage::RandomAccess;
 use desert::{ToBytes,FromBytes,CountBytes};
 use core::ops::{Add,Div};
 use std::fmt::Debug;
-use std::collections::VecDeque;
+use std::collections::{HashMap,VecDeque};
 
 /// All coordinate values must implement this collection of traits.
 pub trait Scalar: Clone+PartialOrd+From<u8>+Debug
   +Send+Sync+'static+PartialEq
   +ToBytes+CountBytes+FromBytes
-  +Add<Output=Self>+Div<Output=Self> {}
-impl Scalar for f32 {}
-impl Scalar for f64 {}
-impl Scalar for u8 {}
-impl Scalar for u16 {}
-impl Scalar for u32 {}
-impl Scalar for u64 {}
-impl Scalar for i16 {}
-impl Scalar for i32 {}
-impl Scalar for i64 {}
+  +Add<Output=Self>+Div<Output=Self> {
+  /// Tag stored in the database schema to detect opening a database with the wrong scalar type.
+  /// The default tag of 0 disables the check for this dimension.
+  fn type_tag() -> u8 { 0 }
+}
+/// Scalars that convert to `f64`, for measuring distances in `DB::nearest()` and the query
+/// shapes `Ball` and `Polygon`.
+pub trait Numeric: Scalar {
+  fn to_f64(&self) -> f64;
+}
+macro_rules! impl_scalar {
+  ($T:ty,$tag:expr) => {
+    impl Scalar for $T {
+      fn type_tag() -> u8 { $tag }
+    }
+    impl Numeric for $T {
+      fn to_f64(&self) -> f64 { *self as f64 }
+    }
+  }
+}
+impl_scalar![f32,1];
+impl_scalar![f64,2];
+impl_scalar![u8,3];
+impl_scalar![u16,4];
+impl_scalar![u32,5];
+impl_scalar![u64,6];
+impl_scalar![i16,7];
+impl_scalar![i32,8];
+impl_scalar![i64,9];
 
 #[doc(hidden)] pub trait RA: RandomAccess<Error=Error>+Unpin+Send+Sync+'static {}
 impl<S> RA for S where S: RandomAccess<Error=Error>+Unpin+Send+Sync+'static {}
@@ -73,18 +113,38 @@ pub trait Point: 'static+Overlap+Clone+Send+Sync+Debug{
   /// Return an Error when the current `Point` is invalid.
   /// For example, for an interval `(min,max)` it may be that `min > max`.
   fn check(&self) -> Result<(),Error>;
+  /// Return the `Scalar::type_tag()` of each dimension.
+  fn type_tags() -> Vec<u8>;
+  /// Return the number of dimensions.
+  fn dimensions() -> usize;
+  /// Compare the start of dimension `dim`, which is the `min` of a `Coord::Interval`, with the
+  /// start of the same dimension of `other`.
+  fn cmp_start(&self, other: &Self, dim: usize) -> Option<std::cmp::Ordering>;
+  /// Return the point at the `max` of every `Coord::Interval` of this point.
+  fn to_end(&self) -> Self;
 }
 
 /// Intersection tests used by `Point` and `Point::Bounds`.
 pub trait Overlap {
   /// Return whether two features intersect.
   fn overlap(&self, other: &Self) -> bool;
+  /// Return whether `other` lies entirely within this feature.
+  fn contains(&self, other: &Self) -> bool;
+}
+
+/// Coordinates of a `Point` along each dimension, for `DB::nearest()` and the query shapes `Ball`
+/// and `Polygon`. Implemented for every point of `Numeric` scalars.
+pub trait Dimensions: Point {
+  /// Return the `(min,max)` of each dimension converted with `Numeric::to_f64()`. A
+  /// `Coord::Scalar(x)` is `(x,x)`.
+  fn to_f64_intervals(&self) -> Vec<(f64,f64)>;
 }
 
 macro_rules! impl_point {
-  ($Tree:ident,$open_from_path:ident,($($T:tt),+),($($i:tt),+)) => {
+  ($Tree:ident,$open_from_path:ident,$open_in_memory:ident,($($T:tt),+),($($i:tt),+)) => {
     pub use tree::$Tree;
     #[cfg(not(feature="wasm"))] pub use store::$open_from_path;
+    pub use store::$open_in_memory;
     #[async_trait::async_trait]
     impl<$($T),+> Point for ($(Coord<$T>),+) where $($T: Scalar),+ {
       type Bounds = (($($T),+),($($T),+));
@@ -125,23 +185,60 @@ macro_rules! impl_point {
         };)+
         Ok(())
       }
+      fn type_tags() -> Vec<u8> {
+        vec![$($T::type_tag()),+]
+      }
+      fn dimensions() -> usize {
+        [$($i),+].len()
+      }
+      fn cmp_start(&self, other: &Self, dim: usize) -> Option<std::cmp::Ordering> {
+        match dim {
+          $($i => match (&self.$i, &other.$i) {
+            (Coord::Scalar(a)|Coord::Interval(a,_), Coord::Scalar(b)|Coord::Interval(b,_)) => {
+              a.partial_cmp(b)
+            },
+          },)+
+          _ => None,
+        }
+      }
+      fn to_end(&self) -> Self {
+        ($(match &self.$i {
+          Coord::Scalar(x) => Coord::Scalar(x.clone()),
+          Coord::Interval(_,max) => Coord::Scalar(max.clone()),
+        }),+)
+      }
+    }
+    impl<$($T),+> Dimensions for ($(Coord<$T>),+) where $($T: Numeric),+ {
+      fn to_f64_intervals(&self) -> Vec<(f64,f64)> {
+        vec![$(match &self.$i {
+          Coord::Scalar(x) => (x.to_f64(),x.to_f64()),
+          Coord::Interval(min,max) => (min.to_f64(),max.to_f64()),
+        }),+]
+      }
     }
   }
 }
 
-#[cfg(feature="2d")] impl_point![Tree2,open_from_path2,(P0,P1),(0,1)];
-#[cfg(feature="3d")] impl_point![Tree3,open_from_path3,(P0,P1,P2),(0,1,2)];
-#[cfg(feature="4d")] impl_point![Tree4,open_from_path4,(P0,P1,P2,P3),(0,1,2,3)];
-#[cfg(feature="5d")] impl_point![Tree5,open_from_path5,(P0,P1,P2,P3,P4),(0,1,2,3,4)];
-#[cfg(feature="6d")] impl_point![Tree6,open_from_path6,(P0,P1,P2,P3,P4,P5),(0,1,2,3,4,5)];
-#[cfg(feature="7d")] impl_point![Tree7,open_from_path7,(P0,P1,P2,P3,P4,P5,P6),(0,1,2,3,4,5,6)];
-#[cfg(feature="8d")] impl_point![Tree8,open_from_path8,(P0,P1,P2,P3,P4,P5,P6,P7),(0,1,2,3,4,5,6,7)];
+#[cfg(feature="2d")] impl_point![Tree2,open_from_path2,open_in_memory2,(P0,P1),(0,1)];
+#[cfg(feature="3d")] impl_point![Tree3,open_from_path3,open_in_memory3,(P0,P1,P2),(0,1,2)];
+#[cfg(feature="4d")] impl_point![Tree4,open_from_path4,open_in_memory4,(P0,P1,P2,P3),(0,1,2,3)];
+#[cfg(feature="5d")] impl_point![Tree5,open_from_path5,open_in_memory5,(P0,P1,P2,P3,P4),(0,1,2,3,4)];
+#[cfg(feature="6d")] impl_point![Tree6,open_from_path6,open_in_memory6,(P0,P1,P2,P3,P4,P5),(0,1,2,3,4,5)];
+#[cfg(feature="7d")] impl_point![Tree7,open_from_path7,open_in_memory7,(P0,P1,P2,P3,P4,P5,P6),(0,1,2,3,4,5,6)];
+#[cfg(feature="8d")] impl_point![Tree8,open_from_path8,open_in_memory8,(P0,P1,P2,P3,P4,P5,P6,P7),(0,1,2,3,4,5,6,7)];
 
 /// Enum container for batch operations on the database.
 #[derive(Debug,Clone)]
 pub enum Row<P,V> where P: Point, V: Value {
   Insert(P,V),
-  Delete(P,V::Id)
+  Delete(P,V::Id),
+  /// Delete the record with this id wherever it is, using the index enabled by
+  /// `Setup::id_index(true)`.
+  DeleteId(V::Id),
+  /// Replace the record with the same `Value::get_id()` wherever it is, or insert the record if
+  /// there is none, using the index enabled by `Setup::id_index(true)`. See `DB::batch()` for
+  /// other rows of the same id in a batch.
+  Upsert(P,V),
 }
 
 #[doc(hidden)]
@@ -149,6 +246,7 @@ pub type Root<P> = Option<tree::TreeRef<P>>;
 #[doc(hidden)]
 #[derive(Debug,Clone)]
 pub struct Meta<P> where P: Point {
+  pub schema: Option<Schema>,
   pub roots: Vec<Root<P>>,
   pub next_tree: TreeId,
 }
@@ -158,9 +256,13 @@ pub struct DB<S,T,P,V>
 where S: RA, P: Point, V: Value, T: Tree<P,V> {
   pub storage: Arc<Mutex<Box<dyn Storage<S>>>>,
   pub fields: Arc<SetupFields>,
-  pub meta_store: Arc<Mutex<S>>,
+  /// The meta file, which a database opened with `Setup::read_only(true)` never opens.
+  pub meta_store: Arc<Mutex<Option<S>>>,
   pub meta: Arc<RwLock<Meta<P>>>,
   pub trees: Arc<TreeFile<S,T,P,V>>,
+  writer: Arc<Mutex<()>>,
+  lock: Option<Arc<StorageLock>>,
+  index: Option<Arc<Mutex<IdIndex>>>,
 }
 
 impl<S,P,V,T> Clone for DB<S,T,P,V>
@@ -172,6 +274,9 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
       meta_store: self.meta_store.clone(),
       meta: self.meta.clone(),
       trees: self.trees.clone(),
+      writer: self.writer.clone(),
+      lock: self.lock.clone(),
+      index: self.index.clone(),
     }
   }
 }
@@ -217,33 +322,122 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
   /// # Ok(()) }
   /// ```
   ///
-  /// Always open a database with the same types. There are no runtime checks yet
-  /// to ensure a database is opened with the same types that it was created with.
+  /// Always open a database with the same types. The meta file records the scalar type of each
+  /// dimension and the value type, and opening with different types fails with
+  /// `EyrosErrorKind::SchemaMismatch`. Databases written before the schema was recorded open
+  /// without a check and have the schema added on the next sync.
+  ///
+  /// Setup parameters that determine how trees are built are stored with the database when it
+  /// is created and used when it is opened again, unless they are set on the `Setup`.
   /// It's fine to change the Setup settings on a previously-created database,
   /// but those settings will only affect new operations.
+  ///
+  /// A database opened with `Setup::read_only(true)` never writes to storage or creates files, so
+  /// any number of read-only instances can query the same files. `batch()`, `optimize()`, `sync()`, `gc()`,
+  /// `migrate()`, and the methods that change checkpoints fail with `EyrosErrorKind::ReadOnly`.
+  /// Opening read-only also fails with `EyrosErrorKind::ReadOnly` while a complete journal from an
+  /// interrupted sync is waiting to be replayed, because the trees and meta may be partly written
+  /// until a writable open replays it.
+  ///
+  /// A writable open takes an exclusive lock from the storage with `Storage::lock()`, held until
+  /// the `DB` and all of its clones are dropped, and fails with `EyrosErrorKind::Locked` while
+  /// another writer holds it. `FileStore` locks a `lock` file in the database directory with an
+  /// advisory `flock()` on unix platforms.
   pub async fn open_from_setup(setup: Setup<S>) -> Result<Self,Error> {
-    let fields = Arc::new(setup.fields);
+    let mut fields = setup.fields;
     fields.log("opening db").await?;
-    let mut meta_store = setup.storage.lock().await.open("meta").await?;
-    let meta = match meta_store.len().await? {
+    let lock = match fields.read_only {
+      true => None,
+      false => Some(Arc::new(setup.storage.lock().await.lock().await?)),
+    };
+    journal::Journal::recover(&fields, &setup.storage).await?;
+    // a read-only database reads files without opening them, which could create them
+    let meta_store = match fields.read_only {
+      true => None,
+      false => Some(setup.storage.lock().await.open("meta").await?),
+    };
+    let bytes = store::read_file(&setup.storage, "meta").await?;
+    let schema = Schema::new::<P,V>();
+    // checksum of the meta file, to match the id index to
+    let mut stamp = checksum::crc32c(&[]);
+    let mut meta = match bytes.len() {
       0 => {
         fields.log("no existing db found. initialized new meta").await?;
-        Meta { roots: vec![], next_tree: 0 }
+        Meta { schema: Some(schema.clone()), roots: vec![], next_tree: 0 }
       },
       n => {
         fields.log(&format!["existing db found. reading {} bytes from meta store", n]).await?;
-        Meta::from_bytes(&meta_store.read(0,n).await?)?.1
+        stamp = checksum::crc32c(&bytes);
+        let body = match checksum::check_trailer(&bytes) {
+          checksum::Trailer::Valid(body,_) => body,
+          checksum::Trailer::Missing(body) => {
+            fields.log("meta has no checksum").await?;
+            body
+          },
+          checksum::Trailer::Mismatch => {
+            return EyrosErrorKind::ChecksumMismatch { id: None, file: "meta".into() }.raise();
+          },
+        };
+        // check the schema before decoding bounds that may have been written with other types
+        if let (_,Some(s)) = Schema::from_meta_bytes(body)? {
+          s.check(&schema)?;
+        }
+        Meta::from_bytes(body)?.1
       },
     };
+    // a meta without a schema header is from the first format version
+    let schema = meta.schema.get_or_insert(Schema { version: 1, ..schema });
+    // tree files may lack a checksum trailer until migrate() rewrites them
+    let unchecked = schema.version < SCHEMA_VERSION;
+    if schema.version < UNCHECKED_VERSION || schema.setup.is_empty() {
+      fields.log("upgrading meta schema header on the next sync").await?;
+      schema.version = schema.version.max(UNCHECKED_VERSION);
+    }
+    fields.apply_stored(&schema.setup, &setup.explicit);
+    if schema.setup.is_empty() {
+      schema.setup = fields.stored();
+    }
+    let fields = Arc::new(fields);
     let trees = TreeFile::new(Arc::clone(&fields), Arc::clone(&setup.storage));
+    trees.set_unchecked(unchecked);
+    let checkpoints = checkpoint::Checkpoints::read(&fields, &setup.storage).await?;
+    trees.set_checkpoints(&checkpoints).await;
+    let index = match fields.id_index {
+      false => None,
+      true => Some(match IdIndex::read(&fields, &setup.storage, stamp).await? {
+        Some(index) => index,
+        None => {
+          fields.log("rebuilding index").await?;
+          IdIndex::rebuild(&trees, &meta.roots).await?
+        },
+      }),
+    };
     Ok(Self {
       storage: Arc::clone(&setup.storage),
       fields,
       meta_store: Arc::new(Mutex::new(meta_store)),
       meta: Arc::new(RwLock::new(meta)),
       trees: Arc::new(trees),
+      writer: Arc::new(Mutex::new(())),
+      lock,
+      index: index.map(|index| Arc::new(Mutex::new(index))),
     })
   }
+  /// Return the setup parameters in use by this database: the parameters stored with the database
+  /// when it was created, except for any set explicitly on the `Setup` that opened it.
+  pub fn setup_fields(&self) -> &SetupFields {
+    &self.fields
+  }
+  /// Return whether the database was opened with `Setup::read_only(true)`.
+  pub fn is_read_only(&self) -> bool {
+    self.fields.read_only
+  }
+  fn check_writable(&self, operation: &str) -> Result<(),Error> {
+    if self.fields.read_only {
+      return EyrosErrorKind::ReadOnly { operation: operation.into() }.raise();
+    }
+    Ok(())
+  }
   /// Create a database instance from `storage`, an interface for reading, writing, and removing
   /// files.
   pub async fn open_from_storage(storage: Box<dyn Storage<S>>) -> Result<Self,Error> {
@@ -254,7 +448,16 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
   /// Each update can be a `Row::Insert(point,value)` or a `Row::Delete(point,id)`
   /// (where the type of `id` is defined in `Value::Id`). For deletes, you need not
   /// have exactly the same `point` as the original record, only a point that will
-  /// intersect it.
+  /// intersect it. With `Setup::id_index(true)`, a `Row::DeleteId(id)` deletes a record
+  /// without any point and a `Row::Upsert(point,value)` replaces the record with the same id
+  /// or inserts it when there is none.
+  ///
+  /// When an id is upserted in a batch, the last row of the batch that inserts or upserts that id
+  /// wins: only its record is stored, in place of the stored record of the id, and any other
+  /// `Row::Insert` or `Row::Upsert` of the id in the batch is dropped. A `Row::Delete` or
+  /// `Row::DeleteId` of the id after that last row cancels it: the stored record is deleted and
+  /// nothing is inserted, and the delete is not reported as missing. A delete of the id before the
+  /// last upsert deletes the stored record as usual, which the upsert then replaces.
   pub async fn batch(&mut self, rows: &[Row<P,V>]) -> Result<(),Error> {
     let opts = BatchOptions::new().rebuild_depth(self.fields.rebuild_depth);
     self.batch_with_options(rows, &opts).await
@@ -269,33 +472,93 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
   }
   /// Perform a batch update with explicit batch options.
   pub async fn batch_with_options(&mut self, rows: &[Row<P,V>], opts: &BatchOptions) -> Result<(),Error> {
+    self.check_writable("batch")?;
     if rows.is_empty() { return Ok(()) }
     for row in rows.iter() {
       match row {
         Row::Insert(p,_) => p.check()?,
         Row::Delete(p,_) => p.check()?,
+        Row::DeleteId(_) => {},
+        Row::Upsert(p,_) => p.check()?,
       }
     }
-    let inserts: Vec<(&P,&V)> = rows.iter()
-      .map(|row| match row {
-        Row::Insert(p,v) => Some((p,v)),
+    // the row of the last upsert of each id, the last row that inserts or upserts the id, and
+    // whether a delete of the id after that row cancels it
+    let mut upserts: HashMap<V::Id,(usize,usize,bool)> = HashMap::new();
+    for (i,row) in rows.iter().enumerate() {
+      match row {
+        Row::Upsert(_,v) => { upserts.insert(v.get_id(), (i,i,false)); },
+        Row::Insert(_,v) => {
+          if let Some(u) = upserts.get_mut(&v.get_id()) { *u = (u.0,i,false); }
+        },
+        Row::Delete(_,id) | Row::DeleteId(id) => {
+          if let Some(u) = upserts.get_mut(id) { u.2 = true; }
+        },
+      }
+    }
+    let is_upsert = |i: usize, v: &V| upserts.get(&v.get_id()).map(|u| u.0) == Some(i);
+    // only the last row that inserts or upserts an upserted id is kept, unless a delete cancels it
+    let is_kept = |i: usize, v: &V| match upserts.get(&v.get_id()) {
+      Some(u) => u.1 == i && !u.2,
+      None => true,
+    };
+    // deletes after the last upsert of their id cancel it instead
+    let after_upsert = |i: usize, id: &V::Id| matches![upserts.get(id), Some(u) if i > u.0];
+    let inserts: Vec<(&P,&V)> = rows.iter().enumerate()
+      .map(|(i,row)| match row {
+        Row::Insert(p,v) if is_kept(i,v) => Some((p,v)),
+        Row::Upsert(p,v) if is_kept(i,v) => Some((p,v)),
         _ => None
       })
       .filter(|row| !row.is_none())
       .map(|x| x.unwrap())
       .collect();
-    let deletes: Vec<(P,V::Id)> = rows.iter()
-      .map(|row| match row {
-        Row::Delete(p,x) => Some((p.clone(),x.clone())),
+    let mut deletes: Vec<(P,V::Id)> = rows.iter().enumerate()
+      .map(|(i,row)| match row {
+        Row::Delete(p,x) if !after_upsert(i,x) => Some((p.clone(),x.clone())),
         _ => None
       })
       .filter(|row| !row.is_none())
       .map(|x| x.unwrap())
       .collect();
 
-    let mut meta = self.meta.write().await;
+    let _writer = self.writer.lock().await;
+    // look up the point and tree of each record deleted or replaced by id, so only that tree and
+    // the trees above it are rewritten. the writer lock keeps the index current
+    let mut missing = vec![];
+    let mut owners = HashMap::new();
+    for (i,row) in rows.iter().enumerate() {
+      match row {
+        Row::DeleteId(id) if after_upsert(i,id) => {},
+        Row::DeleteId(id) => match self.locate_id(id, "delete a record").await? {
+          Some((t,(p,_))) => {
+            deletes.push((p,id.clone()));
+            owners.insert(id.clone(), t);
+          },
+          None => missing.push(format!["{:?}",id]),
+        },
+        Row::Upsert(_,v) if is_upsert(i,v) => {
+          let id = v.get_id();
+          if let Some((t,(p,_))) = self.locate_id(&id, "upsert a record").await? {
+            deletes.push((p,id.clone()));
+            owners.insert(id, t);
+          }
+        },
+        _ => {},
+      }
+    }
+    if !missing.is_empty() && opts.fields.error_if_missing {
+      return EyrosErrorKind::RemoveIdsMissing { ids: missing }.raise();
+    }
+    let deletes = Arc::new(deletes);
+    // queries read the previous roots and trees until the merged and rebuilt trees are ready. the
+    // writer lock keeps anything else from changing the meta in the meantime
+    let (roots,mut next_tree) = {
+      let meta = self.meta.read().await;
+      (meta.roots.clone(), meta.next_tree)
+    };
     let merge_trees = Arc::new(
-      meta.roots.iter()
+      roots.iter()
         .take_while(|r| r.is_some())
         .map(|r| r.as_ref().unwrap().clone())
         .collect::<Vec<TreeRef<P>>>()
@@ -303,26 +566,49 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
     let mut m = Merge {
       fields: Arc::clone(&self.fields),
       inserts: inserts.as_slice(),
-      deletes: Arc::new(deletes),
+      deletes: Arc::clone(&deletes),
       inputs: merge_trees.clone(),
-      roots: meta.roots.clone(),
+      roots,
       trees: self.trees.clone(),
-      next_tree: &mut meta.next_tree,
+      next_tree: &mut next_tree,
       rebuild_depth: opts.fields.rebuild_depth,
       error_if_missing: opts.fields.error_if_missing,
+      owners,
+      replaced: HashMap::new(),
     };
     if inserts.is_empty() {
       m.remove().await?;
+      let mut meta = self.meta.write().await;
+      for (r,t) in m.replaced.iter() {
+        self.trees.put(r,Arc::clone(t)).await?;
+      }
+      // remove() lowers the record counts of the roots it removed records from
+      meta.roots = m.roots;
+      if let Some(index) = &self.index {
+        index.lock().await.update(&deletes, &[], vec![], &m.replaced).await;
+      }
       return Ok(());
     }
     let (tr,rm_trees,create_trees) = m.merge().await?;
+    let roots = m.roots;
+    let replaced = m.replaced;
     //eprintln!["root {}={} bytes", t.count_bytes(), t.to_bytes()?.len()];
+    let mut meta = self.meta.write().await;
+    meta.next_tree = next_tree;
+    meta.roots = roots;
+    for (r,t) in replaced.iter() {
+      self.trees.put(r,Arc::clone(t)).await?;
+    }
     for r in rm_trees.iter() {
       self.trees.remove(r).await?;
     }
     for (r,t) in create_trees.iter() {
       self.trees.put(r,Arc::clone(t)).await?;
     }
+    if let Some(index) = &self.index {
+      let created = create_trees.into_iter().collect();
+      index.lock().await.update(&deletes, &rm_trees, created, &replaced).await;
+    }
     for i in 0..merge_trees.len() {
       if i < meta.roots.len() {
         meta.roots[i] = None;
@@ -337,10 +623,25 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
     }
     Ok(())
   }
+  // find a record by id through the index, failing for an `operation` that needs the index
+  async fn lookup_id(&self, id: &V::Id, operation: &str) -> Result<Option<(P,V)>,Error> {
+    Ok(self.locate_id(id, operation).await?.map(|(_,pv)| pv))
+  }
+  // find a record and the tree holding it by id through the index
+  async fn locate_id(&self, id: &V::Id, operation: &str)
+  -> Result<Option<(TreeId,(P,V))>,Error> {
+    match &self.index {
+      Some(index) => index.lock().await.locate(&self.trees, id).await,
+      None => EyrosErrorKind::IdIndexDisabled { operation: operation.into() }.raise(),
+    }
+  }
   /// Improve query performance by rebuilding the first `rebuild_depth` levels of the tree.
   /// A higher value for `rebuild_depth` will use more memory, as the trees are read into memory
   /// during rebuilding and not written back out again until `sync()` is called.
   pub async fn optimize(&mut self, rebuild_depth: usize) -> Result<(),Error> {
+    self.check_writable("optimize")?;
+    let writer = Arc::clone(&self.writer);
+    let _writer = writer.lock().await;
     let mut refs = VecDeque::new();
     for root in self.meta.read().await.roots.iter() {
       if let Some(r) = root {
@@ -349,7 +650,7 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
     }
     while let Some(tree_ref) = refs.pop_front() {
       self.optimize_tree(&tree_ref, rebuild_depth).await?;
-      self.sync().await?;
+      self.sync_inner().await?;
       //refs.extend(self.optimize_get_depth_refs(tree_ref.id, rebuild_depth).await?);
       let rs = self.optimize_get_depth_refs(tree_ref.id, rebuild_depth).await?;
       refs.extend(rs);
@@ -380,18 +681,25 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
       next_tree: &mut meta.next_tree,
       rebuild_depth,
       error_if_missing: true,
+      owners: HashMap::new(),
+      replaced: HashMap::new(),
     };
     let (tr,rm_trees,create_trees) = m.merge().await?;
     let tr_id = tr.map(|r| r.id);
     for r in rm_trees.iter() {
       self.trees.remove(r).await?;
     }
-    for (r,t) in create_trees.iter() {
-      if tr_id == Some(*r) {
-        self.trees.put(&tree_ref.id,Arc::clone(t)).await?;
-      } else {
-        self.trees.put(r,Arc::clone(t)).await?;
-      }
+    let mut created = Vec::with_capacity(create_trees.len());
+    for (r,t) in create_trees.into_iter() {
+      let id = if tr_id == Some(r) { tree_ref.id } else { r };
+      self.trees.put(&id,Arc::clone(&t)).await?;
+      created.push((id,t));
+    }
+    if let Some(index) = &self.index {
+      // the records of the tree that was copied to n_ref have moved
+      let mut removed = rm_trees;
+      removed.push(tree_ref.id);
+      index.lock().await.update::<T,P,V>(&[], &removed, created, &HashMap::new()).await;
     }
     Ok(())
   }
@@ -415,53 +723,92 @@ where S: RA, P: Point, V: Value, T: Tree<P,V> {
   }
 
   /// Write the changes made to the database to file storage.
+  /// The changes are first written to a journal, so a sync interrupted partway through is either
+  /// completed or discarded the next time the database is opened, never left half applied.
   pub async fn sync(&mut self) -> Result<(),Error> {
-    self.trees.sync().await?;
-    let rbytes = self.meta.read().await.to_bytes()?;
-    self.meta_store.lock().await.write(0, &rbytes).await?;
+    self.check_writable("sync")?;
+    let _writer = self.writer.lock().await;
+    self.sync_inner().await
+  }
+  // callers hold the writer lock so the meta and trees are not changed partway through a sync
+  async fn sync_inner(&self) -> Result<(),Error> {
+    let mut rbytes = self.meta.read().await.to_bytes()?;
+    checksum::append_trailer(&mut rbytes, SCHEMA_VERSION);
+    let stamp = checksum::crc32c(&rbytes);
+    let mut meta_store = self.meta_store.lock().await;
+    let meta_store = match meta_store.as_mut() {
+      Some(meta_store) => meta_store,
+      None => return EyrosErrorKind::ReadOnly { operation: "sync".into() }.raise(),
+    };
+    self.trees.sync(rbytes, meta_store).await?;
+    // written after the meta, so an interrupted sync leaves an index that doesn't match it
+    if let Some(index) = &self.index {
+      index.lock().await.write(&self.storage, stamp).await?;
+    }
     Ok(())
   }
   /// Query the database for every feature that intersects `bbox`. Results are provided as a
   /// readable stream of `(point,value)` records.
-  pub async fn query(&mut self, bbox: &P::Bounds) -> Result<query::QStream<P,V>,Error> {
-    self.fields.log(&format!["query bbox={:?}", bbox]).await?;
-    let mut queries = vec![];
-    for (i,root) in self.meta.read().await.roots.iter().enumerate() {
-      if let Some(r) = root {
-        self.fields.log(&format!["query root i={} id={}", i, r.id]).await?;
-        let t = self.trees.get(&r.id).await?;
-        queries.push(t.lock().await.query(
-          self.trees.clone(), bbox, Arc::clone(&self.fields), i, r
-        ));
-      }
-    }
-    query::from_queries(queries)
+  ///
+  /// Queries only need a shared reference, so any number can run at once, including from clones
+  /// of the same `DB` while another clone runs a `batch()`. Each query reads a `snapshot()` of the
+  /// database as it was when the query was called, held until the stream is dropped. A batch only
+  /// replaces the roots once its trees are built, including the trees it rebuilds without deleted
+  /// records, so new queries wait only while the roots are swapped.
+  pub async fn query(&self, bbox: &P::Bounds) -> Result<query::QStream<P,V>,Error> {
+    self.snapshot().await?.query(bbox).await
+  }
+  /// Query the database for the features that match `bbox` with explicit query options, such as
+  /// `QueryOptions::new().limit(500)` to end the stream after 500 results or
+  /// `QueryOptions::new().mode(QueryMode::Within)` for only the features inside `bbox`.
+  /// Dropping the stream before it ends stops loading any trees that were not already being read.
+  pub async fn query_with_options(&self, bbox: &P::Bounds, opts: &QueryOptions)
+  -> Result<query::QStream<P,V>,Error> {
+    self.snapshot().await?.query_with_options(bbox, opts).await
+  }
+  /// Query the database for every feature that matches `shape`, such as a `Polygon` or a `Ball`,
+  /// for queries that don't fit an axis-aligned bounding box.
+  pub async fn query_shape<Q>(&self, shape: &Q) -> Result<query::QStream<P,V>,Error>
+  where Q: QueryShape<P>+Clone {
+    self.snapshot().await?.query_shape(shape).await
+  }
+  /// Query the database for the features that intersect each box in `bboxes`, walking the trees
+  /// once for the whole batch instead of once for each box. Results are provided as a stream of
+  /// `(query_index,(point,value))` records, where `query_index` is the index of a box in `bboxes`
+  /// that the record intersects.
+  pub async fn query_many(&self, bboxes: &[P::Bounds]) -> Result<query::QManyStream<P,V>,Error> {
+    self.snapshot().await?.query_many(bboxes).await
+  }
+  /// Query the database for every feature that intersects `bbox`, sorted by the start of each
+  /// record along dimension `dim` in the direction of `order`. Trees are loaded only once the
+  /// stream reaches their bounds, so reading the first results of a large query loads few trees.
+  pub async fn query_ordered(&self, bbox: &P::Bounds, dim: usize, order: query::Order)
+  -> Result<query::QStream<P,V>,Error> {
+    self.snapshot().await?.query_ordered(bbox, dim, order).await
+  }
+  /// Return up to `page_size` features that intersect `bbox`, starting from `cursor` or from the
+  /// beginning when `cursor` is `None`, with the cursor for the next page or `None` after the last
+  /// page. Each page reads the database as it is when the page is requested, so a cursor fails
+  /// with `EyrosErrorKind::CursorStale` once a tree it refers to was removed, or the tree the last
+  /// page ended in was changed, by a later `batch()`. Pages from a `Snapshot` can always be
+  /// resumed while the snapshot is kept. A `page_size` of 0 fails with
+  /// `EyrosErrorKind::PageSizeZero`.
+  pub async fn query_page(
+    &self,
+    bbox: &P::Bounds,
+    cursor: Option<&query::QueryCursor<P>>,
+    page_size: usize,
+  ) -> Result<(Vec<(P,V)>,Option<query::QueryCursor<P>>),Error> {
+    self.snapshot().await?.query_page(bbox, cursor, page_size).await
   }
   /// Query the database for every feature that intersects `bbox`.
   /// The provided `trace` will be called right before a tree file is opened with the corresponding
   /// `TreeRef` for the given tree.
   pub async fn query_trace(
-    &mut self,
+    &self,
     bbox: &P::Bounds,
     trace: Box<dyn query::QTrace<P>>,
   ) -> Result<query::QStream<P,V>,Error> {
-    self.fields.log(&format!["query bbox={:?}", bbox]).await?;
-    let mut queries = vec![];
-    let trace_r = Arc::new(Mutex::new(trace));
-    for (i,root) in self.meta.read().await.roots.iter().enumerate() {
-      if let Some(r) = root {
-        self.fields.log(&format!["query root i={} id={}", i, r.id]).await?;
-        let t = self.trees.get(&r.id).await?;
-        queries.push(t.lock().await.query_trace(
-          self.trees.clone(),
-          bbox,
-          Arc::clone(&self.fields),
-          i,
-          r,
-          Some(trace_r.clone()),
-        ));
-      }
-    }
-    query::from_queries(queries)
+    self.snapshot().await?.query_trace(bbox, trace).await
   }
 }
@@ -0,0 +1,94 @@
+use crate::{DB,Tree,TreeId,Point,Value,Error,EyrosErrorKind,RA,tree,TREE_VERSION,SCHEMA_VERSION,
+  checksum};
+use async_std::sync::{Arc,Mutex};
+use std::collections::{HashMap,HashSet};
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Rewrite every tree reachable from the roots that was written in an older format version, or
+  /// without a versioned checksum trailer, into the current format in place under the same ids.
+  /// Trees with references that lack a record count, as written before counts were stored, are
+  /// rewritten with the counts filled in. The meta is written in the current format as well, with
+  /// the record count of each root. Once every tree has a trailer, tree files without one fail to
+  /// read with `EyrosErrorKind::ChecksumMismatch`. Returns the number of trees that were rewritten.
+  ///
+  /// Rewritten trees are synced in groups of `tree_cache_size` so a migration of a large database
+  /// does not hold every tree in memory at once.
+  pub async fn migrate(&mut self) -> Result<usize,Error> {
+    self.check_writable("migrate")?;
+    let _writer = self.writer.lock().await;
+    self.sync_inner().await?;
+    self.fields.log("migrate begin").await?;
+    // walk depth first so every tree comes after the trees it references, including a tree that
+    // is referenced more than once
+    let mut seen: HashSet<TreeId> = HashSet::new();
+    let mut stack: Vec<(TreeId,bool)> = self.meta.read().await.roots.iter()
+      .filter_map(|r| r.as_ref().map(|r| (r.id,false)))
+      .collect();
+    let mut order = vec![];
+    while let Some((id,visited)) = stack.pop() {
+      if visited {
+        order.push(id);
+        continue;
+      }
+      if !seen.insert(id) { continue }
+      stack.push((id,true));
+      let t = self.trees.get(&id).await?;
+      stack.extend(t.lock().await.list_refs().iter().map(|r| (r.id,false)));
+    }
+    let mut counts: HashMap<TreeId,u64> = HashMap::new();
+    let mut pending = 0;
+    let mut count = 0;
+    for id in order.iter() {
+      let t = self.trees.get(id).await?;
+      let (records,refs) = t.lock().await.list();
+      let mut total = records.len() as u64;
+      for r in refs.iter() {
+        // only a tree that references itself through its subtrees is not counted yet
+        match counts.get(&r.id) {
+          Some(n) => total += n,
+          None => return EyrosErrorKind::TreeCycle { id: r.id }.raise(),
+        }
+      }
+      counts.insert(*id, total);
+      let stale = refs.iter().any(|r| r.count != counts.get(&r.id).copied());
+      let version = self.tree_version(id).await?;
+      if version == Some(TREE_VERSION) && !stale { continue }
+      self.fields.log(&format![
+        "migrate tree id={} from version {:?} to {}", id, version, TREE_VERSION
+      ]).await?;
+      let t = match stale {
+        true => {
+          let u = t.lock().await.map_refs(&mut |r| r.count = counts.get(&r.id).copied());
+          Arc::new(Mutex::new(u))
+        },
+        false => t,
+      };
+      self.trees.put(id, t).await?;
+      count += 1;
+      pending += 1;
+      if pending >= self.fields.tree_cache_size {
+        self.sync_inner().await?;
+        pending = 0;
+      }
+    }
+    {
+      let mut meta = self.meta.write().await;
+      for r in meta.roots.iter_mut().flatten() {
+        r.count = counts.get(&r.id).copied();
+      }
+      if let Some(schema) = meta.schema.as_mut() {
+        schema.version = SCHEMA_VERSION;
+      }
+    }
+    self.sync_inner().await?;
+    self.trees.set_unchecked(false);
+    self.fields.log(&format!["migrate complete: {} trees rewritten", count]).await?;
+    Ok(count)
+  }
+  async fn tree_version(&self, id: &TreeId) -> Result<Option<u32>,Error> {
+    let mut s = self.storage.lock().await.open(&tree::get_file_from_id(id)).await?;
+    let len = s.len().await?;
+    let start = len.saturating_sub(checksum::TRAILER_LEN as u64);
+    Ok(checksum::trailer_version(&s.read(start, len - start).await?))
+  }
+}
@@ -0,0 +1,107 @@
+use crate::{DB,Tree,Point,Dimensions,Value,Error,RA};
+use async_std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Distance used by `DB::nearest()`. The distance between the query point and a record is
+/// measured along each dimension first, where a `Coord::Interval` is at distance 0 from any value
+/// it contains, and the distances along each dimension are combined by the metric.
+#[derive(Clone)]
+pub enum Metric {
+  /// Square root of the sum of the squared distances along each dimension.
+  Euclidean,
+  /// Sum of the distances along each dimension.
+  Manhattan,
+  /// Combine the distances along each dimension, given in dimension order, with a function. The
+  /// result must not decrease when any of the distances increase, since whole trees are skipped
+  /// based on the distance to their bounds.
+  Custom(Arc<MetricFn>),
+}
+
+/// Function of the distances along each dimension for `Metric::Custom`.
+pub type MetricFn = dyn Fn(&[f64]) -> f64+Send+Sync;
+
+impl Metric {
+  /// Create a `Metric::Custom` from a function of the distances along each dimension.
+  pub fn custom<F>(f: F) -> Self where F: Fn(&[f64]) -> f64+Send+Sync+'static {
+    Metric::Custom(Arc::new(f))
+  }
+  /// Combine the distances along each dimension.
+  pub fn distance(&self, deltas: &[f64]) -> f64 {
+    match self {
+      Metric::Euclidean => deltas.iter().map(|d| d*d).sum::<f64>().sqrt(),
+      Metric::Manhattan => deltas.iter().sum(),
+      Metric::Custom(f) => f(deltas),
+    }
+  }
+  /// Distance between `(min,max)` intervals of each dimension, as from
+  /// `Dimensions::to_f64_intervals()`.
+  pub fn interval_distance(&self, a: &[(f64,f64)], b: &[(f64,f64)]) -> f64 {
+    let deltas: Vec<f64> = a.iter().zip(b.iter()).map(|((a0,a1),(b0,b1))| {
+      (b0 - a1).max(a0 - b1).max(0.0)
+    }).collect();
+    self.distance(&deltas)
+  }
+}
+
+impl std::fmt::Debug for Metric {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Metric::Euclidean => write![f, "Euclidean"],
+      Metric::Manhattan => write![f, "Manhattan"],
+      Metric::Custom(_) => write![f, "Custom"],
+    }
+  }
+}
+
+/// An item ordered by its distance alone.
+pub struct Dist<X>(pub f64, pub X);
+
+impl<X> PartialEq for Dist<X> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+impl<X> Eq for Dist<X> {}
+impl<X> PartialOrd for Dist<X> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl<X> Ord for Dist<X> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+  }
+}
+
+/// Keep the `k` nearest records in a max-heap so the farthest of them is at the top.
+pub fn push_nearest<X>(best: &mut BinaryHeap<Dist<X>>, k: usize, d: f64, x: X) {
+  if best.len() < k {
+    best.push(Dist(d,x));
+  } else if best.peek().map(|w| d < w.0).unwrap_or(false) {
+    best.pop();
+    best.push(Dist(d,x));
+  }
+}
+
+/// Distance beyond which nothing can be one of the `k` nearest records.
+pub fn nearest_cutoff<X>(best: &BinaryHeap<Dist<X>>, k: usize) -> f64 {
+  match (best.len() >= k, best.peek()) {
+    (true,Some(w)) => w.0,
+    _ => f64::INFINITY,
+  }
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Return the `k` records nearest to `point` by `metric` in order of increasing distance, each
+  /// with its distance. `point` may hold intervals, which are at distance 0 from every record
+  /// they intersect.
+  ///
+  /// Trees are searched best-first across all roots: the nearest unvisited tree by its bounds is
+  /// loaded next, and trees that are farther than the `k`th nearest record found so far are
+  /// never loaded.
+  pub async fn nearest(&self, point: &P, k: usize, metric: &Metric)
+  -> Result<Vec<(P,V,f64)>,Error> where P: Dimensions {
+    self.snapshot().await?.nearest(point, k, metric).await
+  }
+}
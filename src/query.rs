@@ -1,8 +1,10 @@
-use crate::{Error,Point,Value,tree::TreeRef};
-use async_std::{stream::Stream};
+use crate::{Error,Point,Value,tree::TreeRef,QueryShape};
+use async_std::{stream::Stream,sync::{Arc,Mutex}};
 use std::marker::Unpin;
+use std::sync::atomic::{AtomicUsize,Ordering};
 
 pub type QStream<P,V> = Box<dyn Stream<Item=Result<(P,V),Error>>+Send+Unpin>;
+pub type QManyStream<P,V> = Box<dyn Stream<Item=Result<(usize,(P,V)),Error>>+Send+Unpin>;
 pub trait QTrace<P: Point>: Send+Sync+'static {
   fn trace(&mut self, tr: TreeRef<P>);
 }
@@ -15,3 +17,103 @@ impl<F,P> QTrace<P> for F where F: FnMut(TreeRef<P>)+Send+Sync+'static, P: Point
 pub fn from_queries<P:Point,V:Value>(queries: Vec<QStream<P,V>>) -> Result<QStream<P,V>,Error> {
   Ok(Box::new(futures::stream::select_all(queries.into_iter())))
 }
+
+/// How the records returned by a query relate to its bounding box.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum QueryMode {
+  /// Records that intersect the bounding box.
+  #[default]
+  Intersects,
+  /// Records that lie entirely inside the bounding box.
+  Within,
+  /// Records that cover the whole bounding box, such as the time ranges that include an instant.
+  Contains,
+}
+
+/// Direction of the results of `DB::query_ordered()`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Order {
+  /// From the lowest start along the dimension to the highest.
+  Ascending,
+  /// From the highest start along the dimension to the lowest.
+  Descending,
+}
+
+#[derive(Default)]
+pub struct QueryFields {
+  pub limit: Option<usize>,
+  pub mode: QueryMode,
+}
+
+pub struct QueryOptions {
+  pub fields: QueryFields,
+}
+
+impl QueryOptions {
+  pub fn new() -> Self {
+    Self { fields: QueryFields::default() }
+  }
+  /// Stop the query after `limit` results. Trees that have not been dispatched for loading by then
+  /// are never loaded.
+  pub fn limit(mut self, limit: usize) -> Self {
+    self.fields.limit = Some(limit);
+    self
+  }
+  /// Select which records the query returns by how they relate to the bounding box.
+  pub fn mode(mut self, mode: QueryMode) -> Self {
+    self.fields.mode = mode;
+    self
+  }
+}
+
+impl Default for QueryOptions {
+  fn default() -> Self { Self::new() }
+}
+
+/// Position of `DB::query_page()` between pages. Encode it with `desert::ToBytes` to hand it to a
+/// client and decode it with `desert::FromBytes` to resume the query from where the page ended.
+/// Bytes that are not an encoded cursor fail to decode with `EyrosErrorKind::CursorInvalid`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct QueryCursor<P: Point> {
+  /// Trees that have not been read yet.
+  pub(crate) refs: Vec<TreeRef<P>>,
+  /// Tree that the page ended partway through, with the number of its matching records already
+  /// provided, the number of matching records it held, and the crc32c of its encoded bytes.
+  pub(crate) tree: Option<(TreeRef<P>,u64,u64,u32)>,
+}
+
+/// Which records of each tree a query returns.
+pub enum QFilter<P: Point> {
+  Bounds(P::Bounds,QueryMode),
+  Shape(Arc<dyn QueryShape<P>>),
+}
+
+/// State shared by the streams of every root of one query.
+pub struct QShared<P: Point> {
+  pub trace: Option<Mutex<Box<dyn QTrace<P>>>>,
+  pub filter: QFilter<P>,
+  remaining: Option<AtomicUsize>,
+}
+
+impl<P: Point> QShared<P> {
+  pub fn new(trace: Option<Box<dyn QTrace<P>>>, filter: QFilter<P>, limit: Option<usize>) -> Self {
+    Self {
+      trace: trace.map(Mutex::new),
+      filter,
+      remaining: limit.map(AtomicUsize::new),
+    }
+  }
+  /// Claim one result, returning false once the limit has been reached.
+  pub fn take(&self) -> bool {
+    match &self.remaining {
+      None => true,
+      Some(n) => n.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1)).is_ok(),
+    }
+  }
+  pub fn is_done(&self) -> bool {
+    match &self.remaining {
+      None => false,
+      Some(n) => n.load(Ordering::SeqCst) == 0,
+    }
+  }
+}
@@ -0,0 +1,52 @@
+use crate::{Point,Value,Error,EyrosErrorKind,StoredFields,checksum::crc32c};
+
+/// Leading bytes of a meta file that carries a schema header. A legacy meta file starts with a
+/// canonical varint, which never begins with `0xff,0x00`.
+pub const SCHEMA_MAGIC: [u8;7] = [0xff,0x00,b'e',b'y',b'r',b'o',b's'];
+/// Version of the on-disk format described by the schema header.
+/// Version 2 added the stored setup parameters and version 3 the record count of each root.
+/// Version 4 marks a database whose tree files all end in a checksum trailer, as created since
+/// trailers were added or upgraded by `migrate()`.
+pub const SCHEMA_VERSION: u32 = 4;
+/// Version that a database written before version 4 is upgraded to when it is opened. Its tree
+/// files may still lack a checksum trailer until `migrate()` rewrites them.
+pub const UNCHECKED_VERSION: u32 = 3;
+
+/// Types and setup parameters a database was created with, stored at the front of the meta file.
+/// A scalar tag or value fingerprint of 0 means the type did not identify itself and is not
+/// checked.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Schema {
+  pub version: u32,
+  pub scalars: Vec<u8>,
+  pub value: u32,
+  pub setup: StoredFields,
+}
+
+impl Schema {
+  pub fn new<P,V>() -> Self where P: Point, V: Value {
+    let name = V::type_name();
+    Self {
+      version: SCHEMA_VERSION,
+      scalars: P::type_tags(),
+      value: if name.is_empty() { 0 } else { crc32c(name.as_bytes()) },
+      setup: StoredFields::default(),
+    }
+  }
+  /// Return `SchemaMismatch` if a database stored with this schema can't be opened with the
+  /// `expected` types.
+  pub fn check(&self, expected: &Self) -> Result<(),Error> {
+    let ok = self.version <= expected.version
+      && self.scalars.len() == expected.scalars.len()
+      && self.scalars.iter().zip(expected.scalars.iter())
+        .all(|(a,b)| *a == 0 || *b == 0 || a == b)
+      && (self.value == 0 || expected.value == 0 || self.value == expected.value);
+    if !ok {
+      return EyrosErrorKind::SchemaMismatch {
+        expected: format!["{:?}", expected],
+        found: format!["{:?}", self],
+      }.raise();
+    }
+    Ok(())
+  }
+}
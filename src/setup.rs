@@ -15,6 +15,8 @@ pub struct SetupFields {
   pub inline_max_bytes: usize,
   pub tree_cache_size: usize,
   pub rebuild_depth: usize,
+  pub read_only: bool,
+  pub id_index: bool,
   pub debug: Option<Sender<String>>,
 }
 
@@ -29,6 +31,8 @@ impl std::fmt::Debug for SetupFields {
       .field("inline_max_bytes", &self.inline_max_bytes)
       .field("tree_cache_size", &self.tree_cache_size)
       .field("rebuild_depth", &self.rebuild_depth)
+      .field("read_only", &self.read_only)
+      .field("id_index", &self.id_index)
       .field("debug", &format_args!["{}", match &self.debug {
         Some(_) => "[enabled]",
         None => "[not enabled]",
@@ -37,6 +41,26 @@ impl std::fmt::Debug for SetupFields {
   }
 }
 
+/// Setup parameters that determine how trees are built. These are stored in the database meta
+/// when the database is created and used in place of any parameter that a later `Setup` does not
+/// set explicitly. A `None` field is not set.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct StoredFields {
+  pub branch_factor: Option<usize>,
+  pub max_depth: Option<usize>,
+  pub max_records: Option<usize>,
+  pub ext_records: Option<usize>,
+  pub inline: Option<usize>,
+  pub inline_max_bytes: Option<usize>,
+  pub rebuild_depth: Option<usize>,
+}
+
+impl StoredFields {
+  pub fn is_empty(&self) -> bool {
+    *self == Self::default()
+  }
+}
+
 impl SetupFields {
   pub fn default() -> Self {
     Self {
@@ -48,9 +72,38 @@ impl SetupFields {
       inline_max_bytes: 20_000,
       tree_cache_size: 1000,
       rebuild_depth: 2,
+      read_only: false,
+      id_index: false,
       debug: None,
     }
   }
+  /// Return the parameters to store in the database meta.
+  pub fn stored(&self) -> StoredFields {
+    StoredFields {
+      branch_factor: Some(self.branch_factor),
+      max_depth: Some(self.max_depth),
+      max_records: Some(self.max_records),
+      ext_records: Some(self.ext_records),
+      inline: Some(self.inline),
+      inline_max_bytes: Some(self.inline_max_bytes),
+      rebuild_depth: Some(self.rebuild_depth),
+    }
+  }
+  /// Use the `stored` parameters for any parameter not set in `explicit`.
+  pub fn apply_stored(&mut self, stored: &StoredFields, explicit: &StoredFields) {
+    fn pick(field: &mut usize, stored: Option<usize>, explicit: Option<usize>) {
+      if let (Some(x),None) = (stored,explicit) {
+        *field = x;
+      }
+    }
+    pick(&mut self.branch_factor, stored.branch_factor, explicit.branch_factor);
+    pick(&mut self.max_depth, stored.max_depth, explicit.max_depth);
+    pick(&mut self.max_records, stored.max_records, explicit.max_records);
+    pick(&mut self.ext_records, stored.ext_records, explicit.ext_records);
+    pick(&mut self.inline, stored.inline, explicit.inline);
+    pick(&mut self.inline_max_bytes, stored.inline_max_bytes, explicit.inline_max_bytes);
+    pick(&mut self.rebuild_depth, stored.rebuild_depth, explicit.rebuild_depth);
+  }
   pub async fn log(&self, msg: &str) -> Result<(),Error> {
     if let Some(d) = &self.debug {
       d.send(msg.into()).await?;
@@ -84,15 +137,22 @@ impl SetupFields {
 ///   .inline_max_bytes(20_000)
 ///   .tree_cache_size(1000)
 ///   .rebuild_depth(2)
+///   .read_only(false)
+///   .id_index(false)
 ///   .debug(|msg: &str| eprintln!["[debug] {}", msg])
 ///   .build()
 ///   .await?;
 /// # Ok(()) }
 /// ```
+///
+/// Parameters that determine how trees are built are stored with a new database.
+/// When an existing database is opened, the stored parameters are used for any of
+/// these parameters that were not set on the `Setup`.
 #[derive(Clone)]
 pub struct Setup<S> where S: RA {
   pub storage: Arc<Mutex<Box<dyn Storage<S>>>>,
-  pub fields: SetupFields
+  pub fields: SetupFields,
+  pub explicit: StoredFields,
 }
 
 impl<S> Setup<S> where S: RA {
@@ -100,31 +160,38 @@ impl<S> Setup<S> where S: RA {
   pub fn from_storage(storage: Box<dyn Storage<S>>) -> Self {
     Self {
       storage: Arc::new(Mutex::new(storage)),
-      fields: SetupFields::default()
+      fields: SetupFields::default(),
+      explicit: StoredFields::default(),
     }
   }
   pub fn branch_factor(mut self, bf: usize) -> Self {
     self.fields.branch_factor = bf;
+    self.explicit.branch_factor = Some(bf);
     self
   }
   pub fn max_depth(mut self, md: usize) -> Self {
     self.fields.max_depth = md;
+    self.explicit.max_depth = Some(md);
     self
   }
   pub fn max_records(mut self, mr: usize) -> Self {
     self.fields.max_records = mr;
+    self.explicit.max_records = Some(mr);
     self
   }
   pub fn ext_records(mut self, er: usize) -> Self {
     self.fields.ext_records = er;
+    self.explicit.ext_records = Some(er);
     self
   }
   pub fn inline(mut self, n: usize) -> Self {
     self.fields.inline = n;
+    self.explicit.inline = Some(n);
     self
   }
   pub fn inline_max_bytes(mut self, n: usize) -> Self {
     self.fields.inline_max_bytes = n;
+    self.explicit.inline_max_bytes = Some(n);
     self
   }
   pub fn tree_cache_size(mut self, n: usize) -> Self {
@@ -133,6 +200,22 @@ impl<S> Setup<S> where S: RA {
   }
   pub fn rebuild_depth(mut self, n: usize) -> Self {
     self.fields.rebuild_depth = n;
+    self.explicit.rebuild_depth = Some(n);
+    self
+  }
+  /// Open the database without writing to storage. Calls that would modify the database fail with
+  /// `EyrosErrorKind::ReadOnly`. Files are read with `Storage::read()`, so a `FileStore` opens a
+  /// database in a directory without write permission and creates no files.
+  pub fn read_only(mut self, ro: bool) -> Self {
+    self.fields.read_only = ro;
+    self
+  }
+  /// Keep an index from the `Value::Id` of each record to the tree that holds it, for
+  /// `DB::get()`, `Row::DeleteId` and `Row::Upsert`. The index is stored in the `index` file on
+  /// each sync and rebuilt from the trees when the database is opened with an index that is
+  /// missing or out of date.
+  pub fn id_index(mut self, x: bool) -> Self {
+    self.fields.id_index = x;
     self
   }
   pub fn debug(mut self, d: impl Debugger+Send+Sync+'static) -> Self {
@@ -0,0 +1,132 @@
+use crate::{Point,Dimensions,Overlap,nearest::Metric};
+#[cfg(feature="2d")] use crate::{Numeric,Coord};
+
+/// Geometry for `DB::query_shape()`, to query by more than an axis-aligned bounding box.
+///
+/// Whole trees and branches of trees are skipped when `overlaps_bounds()` is false for the region
+/// their records lie within, so it may return true for a region without any matching records but
+/// must not return false for a region that holds one. `matches()` decides which records are
+/// returned.
+pub trait QueryShape<P: Point>: Send+Sync+'static {
+  /// Return whether records within `bounds` may match this shape.
+  fn overlaps_bounds(&self, bounds: &P::Bounds) -> bool;
+  /// Return whether the record at `point` matches this shape.
+  fn matches(&self, point: &P) -> bool;
+}
+
+/// Records within `radius` of `center` by `metric`, measured to the nearest value of each
+/// `Coord::Interval` as in `DB::nearest()`. A `Metric::Euclidean` ball in 2 dimensions is a
+/// circle.
+#[derive(Clone,Debug)]
+pub struct Ball {
+  center: Vec<(f64,f64)>,
+  radius: f64,
+  metric: Metric,
+}
+
+impl Ball {
+  pub fn new<P: Dimensions>(center: &P, radius: f64, metric: Metric) -> Self {
+    Self { center: center.to_f64_intervals(), radius, metric }
+  }
+}
+
+impl<P: Dimensions> QueryShape<P> for Ball {
+  fn overlaps_bounds(&self, bounds: &P::Bounds) -> bool {
+    let b = P::from_bounds(bounds).to_f64_intervals();
+    self.metric.interval_distance(&self.center, &b) <= self.radius
+  }
+  fn matches(&self, point: &P) -> bool {
+    self.metric.interval_distance(&self.center, &point.to_f64_intervals()) <= self.radius
+  }
+}
+
+/// Records that intersect any of a list of bounding boxes, each returned once. A corridor along a
+/// route can be covered with a box for each segment.
+#[derive(Clone,Debug)]
+pub struct MultiBounds<P: Point> {
+  bounds: Vec<P::Bounds>,
+  points: Vec<P>,
+}
+
+impl<P: Point> MultiBounds<P> {
+  pub fn new(bounds: Vec<P::Bounds>) -> Self {
+    let points = bounds.iter().map(|b| P::from_bounds(b)).collect();
+    Self { bounds, points }
+  }
+}
+
+impl<P: Point> QueryShape<P> for MultiBounds<P> {
+  fn overlaps_bounds(&self, bounds: &P::Bounds) -> bool {
+    self.bounds.iter().any(|b| b.overlap(bounds))
+  }
+  fn matches(&self, point: &P) -> bool {
+    self.points.iter().any(|p| p.overlap(point))
+  }
+}
+
+/// Records in 2 dimensions that intersect a polygon, including its edges. The polygon is given
+/// by its vertices in order, with an edge from the last vertex back to the first, and may be
+/// concave.
+#[cfg(feature="2d")]
+#[derive(Clone,Debug)]
+pub struct Polygon {
+  vertices: Vec<(f64,f64)>,
+}
+
+#[cfg(feature="2d")]
+impl Polygon {
+  pub fn new(vertices: Vec<(f64,f64)>) -> Self {
+    Self { vertices }
+  }
+  fn edges(&self) -> impl Iterator<Item=(&(f64,f64),&(f64,f64))> {
+    self.vertices.iter().zip(self.vertices.iter().cycle().skip(1))
+  }
+  // even-odd rule. points on an edge are found by `overlaps_box()` instead
+  fn contains_point(&self, x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for ((x0,y0),(x1,y1)) in self.edges() {
+      if (*y0 > y) != (*y1 > y) && x < x0 + (y-y0)/(y1-y0)*(x1-x0) {
+        inside = !inside;
+      }
+    }
+    inside
+  }
+  fn overlaps_box(&self, (xmin,xmax): (f64,f64), (ymin,ymax): (f64,f64)) -> bool {
+    // a box that no edge crosses is either inside the polygon or outside of it
+    self.contains_point(xmin, ymin) || self.edges().any(|((x0,y0),(x1,y1))| {
+      segment_overlaps_box((*x0,*y0), (*x1,*y1), (xmin,xmax), (ymin,ymax))
+    })
+  }
+}
+
+#[cfg(feature="2d")]
+impl<X,Y> QueryShape<(Coord<X>,Coord<Y>)> for Polygon where X: Numeric, Y: Numeric {
+  fn overlaps_bounds(&self, bounds: &((X,Y),(X,Y))) -> bool {
+    self.overlaps_box(
+      ((bounds.0).0.to_f64(), (bounds.1).0.to_f64()),
+      ((bounds.0).1.to_f64(), (bounds.1).1.to_f64()),
+    )
+  }
+  fn matches(&self, point: &(Coord<X>,Coord<Y>)) -> bool {
+    let iv = point.to_f64_intervals();
+    self.overlaps_box(iv[0], iv[1])
+  }
+}
+
+// clip the segment from `a` to `b` to the box (Liang-Barsky) and return whether any of it is left
+#[cfg(feature="2d")]
+fn segment_overlaps_box(a: (f64,f64), b: (f64,f64), xs: (f64,f64), ys: (f64,f64)) -> bool {
+  let (dx,dy) = (b.0-a.0, b.1-a.1);
+  let (mut t0, mut t1) = (0.0f64, 1.0f64);
+  for (p,q) in [(-dx,a.0-xs.0),(dx,xs.1-a.0),(-dy,a.1-ys.0),(dy,ys.1-a.1)].iter() {
+    if *p == 0.0 {
+      if *q < 0.0 { return false }
+    } else if *p < 0.0 {
+      t0 = t0.max(q/p);
+    } else {
+      t1 = t1.min(q/p);
+    }
+    if t0 > t1 { return false }
+  }
+  true
+}
@@ -0,0 +1,392 @@
+use crate::{DB,Tree,TreeRef,Point,Dimensions,Value,Error,RA,Root,SetupFields,Overlap,EyrosError,EyrosErrorKind,
+  QueryShape,query::{self,QueryOptions,QueryMode,QShared,QFilter,Order,QueryCursor},
+  tree_file::{TreeFile,TreePin},nearest::{Metric,Dist,push_nearest,nearest_cutoff},
+  aggregate::{Aggregate,Count},unfold::unfold,checksum};
+use async_std::{sync::Arc,stream::StreamExt};
+use std::{cmp::{Ordering,Reverse},collections::{BinaryHeap,VecDeque}};
+
+/// Read handle for the database as it was when `DB::snapshot()` was called.
+///
+/// Queries from a snapshot see the same records no matter what is written to the database after
+/// the snapshot was taken. The trees a snapshot can read are kept, in memory or as files that
+/// `sync()` does not remove, until the snapshot and all of its clones and query streams are
+/// dropped.
+pub struct Snapshot<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  fields: Arc<SetupFields>,
+  roots: Arc<Vec<Root<P>>>,
+  trees: Arc<TreeFile<S,T,P,V>>,
+  pin: Arc<TreePin>,
+}
+
+impl<S,T,P,V> Clone for Snapshot<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  fn clone(&self) -> Self {
+    Self {
+      fields: self.fields.clone(),
+      roots: self.roots.clone(),
+      trees: self.trees.clone(),
+      pin: self.pin.clone(),
+    }
+  }
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Take a snapshot of the database for reading a consistent view while other clones of this
+  /// `DB` keep writing.
+  pub async fn snapshot(&self) -> Result<Snapshot<S,T,P,V>,Error> {
+    let meta = self.meta.read().await;
+    let (trees,pin) = self.trees.pin(meta.next_tree);
+    self.fields.log(&format!["snapshot roots={}", meta.roots.len()]).await?;
+    Ok(Snapshot {
+      fields: Arc::clone(&self.fields),
+      roots: Arc::new(meta.roots.clone()),
+      trees: Arc::new(trees),
+      pin: Arc::new(pin),
+    })
+  }
+}
+
+impl<S,T,P,V> Snapshot<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Query the snapshot for every feature that intersects `bbox`. Results are provided as a
+  /// readable stream of `(point,value)` records. The stream keeps the snapshot's trees until it
+  /// is dropped.
+  pub async fn query(&self, bbox: &P::Bounds) -> Result<query::QStream<P,V>,Error> {
+    self.query_with_options(bbox, &QueryOptions::default()).await
+  }
+  /// Query the snapshot for the features that match `bbox` in the mode given by `opts`.
+  /// Dropping the stream stops loading any trees that were not already being read.
+  pub async fn query_with_options(&self, bbox: &P::Bounds, opts: &QueryOptions)
+  -> Result<query::QStream<P,V>,Error> {
+    self.fields.log(&format![
+      "query bbox={:?} limit={:?} mode={:?}", bbox, opts.fields.limit, opts.fields.mode
+    ]).await?;
+    let filter = QFilter::Bounds(bbox.clone(), opts.fields.mode);
+    self.query_shared(QShared::new(None, filter, opts.fields.limit)).await
+  }
+  /// Query the snapshot for every feature that intersects `bbox`.
+  /// The provided `trace` will be called right before a tree file is opened with the corresponding
+  /// `TreeRef` for the given tree.
+  pub async fn query_trace(
+    &self,
+    bbox: &P::Bounds,
+    trace: Box<dyn query::QTrace<P>>,
+  ) -> Result<query::QStream<P,V>,Error> {
+    self.fields.log(&format!["query bbox={:?}", bbox]).await?;
+    let filter = QFilter::Bounds(bbox.clone(), QueryMode::Intersects);
+    self.query_shared(QShared::new(Some(trace), filter, None)).await
+  }
+  /// Query the snapshot for every feature that matches `shape`.
+  pub async fn query_shape<Q>(&self, shape: &Q) -> Result<query::QStream<P,V>,Error>
+  where Q: QueryShape<P>+Clone {
+    self.fields.log("query shape").await?;
+    let filter = QFilter::Shape(Arc::new(shape.clone()));
+    self.query_shared(QShared::new(None, filter, None)).await
+  }
+  /// Query the snapshot for the features that intersect each box in `bboxes`. Results are
+  /// provided as a stream of `(query_index,(point,value))` records, where `query_index` is the
+  /// index of a box in `bboxes` that the record intersects. A record that intersects several boxes
+  /// is provided once for each of them. Every tree is loaded at most once for the whole batch.
+  pub async fn query_many(&self, bboxes: &[P::Bounds]) -> Result<query::QManyStream<P,V>,Error> {
+    self.fields.log(&format!["query_many bboxes={}", bboxes.len()]).await?;
+    let boxes: Vec<P> = bboxes.iter().map(|b| P::from_bounds(b)).collect();
+    let refs: Vec<(TreeRef<P>,Vec<usize>)> = self.roots.iter().flatten().filter_map(|r| {
+      let active: Vec<usize> = (0..boxes.len()).filter(|k| r.bounds.overlap(&boxes[*k])).collect();
+      if active.is_empty() { None } else { Some((r.clone(),active)) }
+    }).collect();
+    // the stream holds a clone of the snapshot to keep its trees
+    let state = (self.clone(), bboxes.to_vec(), refs, VecDeque::new());
+    Ok(Box::new(unfold(state, async move |(snap,bboxes,mut refs,mut results)| {
+      loop {
+        if let Some(res) = results.pop_front() {
+          return Some((Ok(res),(snap,bboxes,refs,results)));
+        }
+        let (r,active) = refs.pop()?;
+        match snap.trees.get(&r.id).await {
+          Err(e) => return Some((Err(e),(snap,bboxes,vec![],results))),
+          Ok(t) => {
+            let (rows,xrefs) = t.lock().await.many_local(&bboxes, &active);
+            results.extend(rows);
+            refs.extend(xrefs);
+          },
+        }
+      }
+    })))
+  }
+  /// Query the snapshot for every feature that intersects `bbox`, sorted by the start of each
+  /// record along dimension `dim` in the direction of `order`.
+  ///
+  /// Records and trees are merged across every root in a single priority queue. A tree is keyed
+  /// by the lowest start (or highest end, for `Order::Descending`) that a record within its bounds
+  /// could have, so it is loaded only once every record before it has been provided.
+  pub async fn query_ordered(&self, bbox: &P::Bounds, dim: usize, order: Order)
+  -> Result<query::QStream<P,V>,Error> {
+    let dimensions = P::dimensions();
+    if dim >= dimensions {
+      return EyrosErrorKind::DimensionOutOfRange { dimension: dim, dimensions }.raise();
+    }
+    self.fields.log(&format![
+      "query_ordered bbox={:?} dim={} order={:?}", bbox, dim, order
+    ]).await?;
+    let q = P::from_bounds(bbox);
+    let mut heap = BinaryHeap::new();
+    for r in self.roots.iter().flatten().filter(|r| r.bounds.overlap(&q)) {
+      heap.push(OrderedEntry::tree(r.clone(), dim, order));
+    }
+    let state = (self.clone(), (bbox.clone(),dim,order), heap);
+    Ok(Box::new(unfold(state, async move |(snap,q,mut heap)| {
+      let (bbox,dim,order) = &q;
+      loop {
+        let r = match heap.pop()?.item {
+          Ordered::Record(p,v) => return Some((Ok((p,v)),(snap,q,heap))),
+          Ordered::Tree(r,_) => r,
+        };
+        match snap.trees.get(&r.id).await {
+          Err(e) => return Some((Err(e),(snap,q,BinaryHeap::new()))),
+          Ok(t) => {
+            let (records,xrefs) = t.lock().await.query_local(bbox, QueryMode::Intersects);
+            for (p,v) in records {
+              heap.push(OrderedEntry { dim: *dim, order: *order, item: Ordered::Record(p,v) });
+            }
+            for r in xrefs {
+              heap.push(OrderedEntry::tree(r, *dim, *order));
+            }
+          },
+        }
+      }
+    })))
+  }
+  /// Return up to `page_size` features of the snapshot that intersect `bbox`, starting from
+  /// `cursor` or from the beginning when `cursor` is `None`, with the cursor for the next page or
+  /// `None` after the last page. Every page of one query must be requested with the same `bbox`.
+  /// A `page_size` of 0 fails with `EyrosErrorKind::PageSizeZero`.
+  pub async fn query_page(
+    &self,
+    bbox: &P::Bounds,
+    cursor: Option<&QueryCursor<P>>,
+    page_size: usize,
+  ) -> Result<(Vec<(P,V)>,Option<QueryCursor<P>>),Error> {
+    self.fields.log(&format![
+      "query_page bbox={:?} cursor={} page_size={}", bbox, cursor.is_some(), page_size
+    ]).await?;
+    if page_size == 0 {
+      return EyrosErrorKind::PageSizeZero {}.raise();
+    }
+    let (mut refs,mut tree) = match cursor {
+      // a page only ends partway through a tree after providing some but not all of its records
+      Some(QueryCursor { tree: Some((_,skip,total,_)), .. }) if *skip == 0 || skip >= total => {
+        return EyrosErrorKind::CursorInvalid {}.raise();
+      },
+      Some(c) => (c.refs.clone(), c.tree.clone()),
+      None => {
+        let q = P::from_bounds(bbox);
+        (self.roots.iter().flatten().filter(|r| r.bounds.overlap(&q)).cloned().collect(), None)
+      },
+    };
+    let mut records = vec![];
+    while records.len() < page_size {
+      let (r,skip,total,crc) = match tree.take() {
+        Some(x) => x,
+        None => match refs.pop() {
+          Some(r) => (r,0,0,0),
+          None => break,
+        },
+      };
+      let tm = match self.trees.get(&r.id).await {
+        Ok(t) => t,
+        Err(e) if cursor.is_some() && is_missing(&e) => {
+          return EyrosErrorKind::CursorStale { id: r.id }.raise();
+        },
+        Err(e) => return Err(e),
+      };
+      let mut t = tm.lock().await;
+      let (rows,xrefs) = t.query_local(bbox, QueryMode::Intersects);
+      let len = rows.len() as u64;
+      // optimize() and remove() rewrite trees under the same id, which can leave as many matching
+      // records in another order, so the contents are compared as well
+      if skip > 0 && (len != total || checksum::crc32c(&t.to_bytes()?) != crc) {
+        return EyrosErrorKind::CursorStale { id: r.id }.raise();
+      }
+      // refs of a tree are added when the tree is first read
+      if skip == 0 {
+        refs.extend(xrefs);
+      }
+      let n = ((page_size - records.len()) as u64).min(len - skip);
+      records.extend(rows.into_iter().skip(skip as usize).take(n as usize));
+      if skip + n < len {
+        tree = Some((r,skip+n,len,checksum::crc32c(&t.to_bytes()?)));
+      }
+    }
+    let next = match (refs.is_empty(),&tree) {
+      (true,None) => None,
+      _ => Some(QueryCursor { refs, tree }),
+    };
+    Ok((records,next))
+  }
+  async fn query_shared(&self, shared: QShared<P>)
+  -> Result<query::QStream<P,V>,Error> {
+    let shared = Arc::new(shared);
+    let mut queries = vec![];
+    for (i,root) in self.roots.iter().enumerate() {
+      if let Some(r) = root {
+        self.fields.log(&format!["query root i={} id={}", i, r.id]).await?;
+        let t = self.trees.get(&r.id).await?;
+        queries.push(t.lock().await.query_trace(
+          self.trees.clone(),
+          Arc::clone(&self.fields),
+          i,
+          r,
+          Arc::clone(&shared),
+        ));
+      }
+    }
+    self.pin_stream(query::from_queries(queries)?)
+  }
+  /// Return the `k` records of the snapshot nearest to `point` by `metric` in order of increasing
+  /// distance, each with its distance.
+  pub async fn nearest(&self, point: &P, k: usize, metric: &Metric)
+  -> Result<Vec<(P,V,f64)>,Error> where P: Dimensions {
+    point.check()?;
+    self.fields.log(&format!["nearest point={:?} k={} metric={:?}", point, k, metric]).await?;
+    if k == 0 { return Ok(vec![]) }
+    let q = point.to_f64_intervals();
+    let mut refs: BinaryHeap<Reverse<Dist<TreeRef<P>>>> = BinaryHeap::new();
+    for r in self.roots.iter().flatten() {
+      let d = metric.interval_distance(&q, &r.bounds.to_f64_intervals());
+      refs.push(Reverse(Dist(d,r.clone())));
+    }
+    let mut best: BinaryHeap<Dist<(P,V)>> = BinaryHeap::new();
+    while let Some(Reverse(Dist(d,r))) = refs.pop() {
+      if d > nearest_cutoff(&best, k) { break }
+      self.fields.log(&format!["nearest tree id={} distance={}", r.id, d]).await?;
+      let t = self.trees.get(&r.id).await?;
+      let (records,xrefs) = t.lock().await.nearest_local(&q, k, metric, &r.bounds);
+      for (d,pv) in records {
+        push_nearest(&mut best, k, d, pv);
+      }
+      let cutoff = nearest_cutoff(&best, k);
+      for (d,r) in xrefs {
+        if d <= cutoff {
+          refs.push(Reverse(Dist(d,r)));
+        }
+      }
+    }
+    Ok(best.into_sorted_vec().into_iter().map(|Dist(d,(p,v))| (p,v,d)).collect())
+  }
+  /// Fold every record of the snapshot that intersects `bbox` into `agg` and return its result.
+  pub async fn aggregate<A>(&self, bbox: &P::Bounds, agg: A) -> Result<A::Output,Error>
+  where A: Aggregate<P,V> {
+    self.fields.log(&format!["aggregate bbox={:?}", bbox]).await?;
+    let refs = self.roots.iter().flatten().cloned().collect();
+    self.aggregate_refs(refs, bbox, agg).await
+  }
+  /// Return the number of records in the snapshot.
+  pub async fn len(&self) -> Result<u64,Error> {
+    let mut len = 0;
+    for r in self.roots.iter().flatten() {
+      len += match r.count {
+        Some(count) => count,
+        None => {
+          let bbox = r.bounds.to_bounds()?;
+          self.aggregate_refs(vec![r.clone()], &bbox, Count::new()).await?
+        },
+      };
+    }
+    Ok(len)
+  }
+  /// Return whether the snapshot holds no records.
+  pub async fn is_empty(&self) -> Result<bool,Error> {
+    Ok(self.len().await? == 0)
+  }
+  async fn aggregate_refs<A>(&self, mut refs: Vec<TreeRef<P>>, bbox: &P::Bounds, mut agg: A)
+  -> Result<A::Output,Error> where A: Aggregate<P,V> {
+    while let Some(r) = refs.pop() {
+      // a tree entirely within bbox can be added from its record count without loading it
+      if let Some(count) = r.count {
+        if bbox.contains(&r.bounds.to_bounds()?) && agg.add_count(count) { continue }
+      }
+      let t = self.trees.get(&r.id).await?;
+      let xrefs = t.lock().await.visit_local(bbox, QueryMode::Intersects, &mut |p,v| agg.add(p,v));
+      refs.extend(xrefs);
+    }
+    Ok(agg.finish())
+  }
+  fn pin_stream(&self, stream: query::QStream<P,V>) -> Result<query::QStream<P,V>,Error> {
+    let pin = Arc::clone(&self.pin);
+    Ok(Box::new(stream.map(move |x| {
+      let _ = &pin;
+      x
+    })))
+  }
+}
+
+// whether a tree could not be loaded because it was removed
+fn is_missing(e: &Error) -> bool {
+  match e.downcast_ref::<EyrosError>() {
+    Some(e) => matches![
+      e.kind(),
+      EyrosErrorKind::TreeRemoved { .. } | EyrosErrorKind::TreeEmpty { .. }
+    ],
+    None => false,
+  }
+}
+
+// entries of the priority queue of `Snapshot::query_ordered()`. A tree is keyed by a point whose
+// start is the first start in the direction of the order that a record within its bounds could have
+enum Ordered<P: Point,V: Value> {
+  Record(P,V),
+  Tree(TreeRef<P>,P),
+}
+
+struct OrderedEntry<P: Point,V: Value> {
+  dim: usize,
+  order: Order,
+  item: Ordered<P,V>,
+}
+
+impl<P: Point,V: Value> OrderedEntry<P,V> {
+  fn tree(r: TreeRef<P>, dim: usize, order: Order) -> Self {
+    let key = match order {
+      Order::Ascending => r.bounds.clone(),
+      Order::Descending => r.bounds.to_end(),
+    };
+    Self { dim, order, item: Ordered::Tree(r,key) }
+  }
+  fn key(&self) -> &P {
+    match &self.item {
+      Ordered::Record(p,_) => p,
+      Ordered::Tree(_,key) => key,
+    }
+  }
+  // keys that can't be compared, such as NaN, put trees before everything else to be loaded
+  // right away and records after everything else
+  fn rank(&self) -> u8 {
+    match (self.key().cmp_start(self.key(), self.dim), &self.item) {
+      (Some(_),_) => 1,
+      (None,Ordered::Tree(..)) => 2,
+      (None,Ordered::Record(..)) => 0,
+    }
+  }
+}
+
+// the entry to provide first is the greatest
+impl<P: Point,V: Value> Ord for OrderedEntry<P,V> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.rank().cmp(&other.rank()).then_with(|| {
+      let o = self.key().cmp_start(other.key(), self.dim).unwrap_or(Ordering::Equal);
+      match self.order {
+        Order::Ascending => o.reverse(),
+        Order::Descending => o,
+      }
+    })
+  }
+}
+impl<P: Point,V: Value> PartialOrd for OrderedEntry<P,V> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl<P: Point,V: Value> PartialEq for OrderedEntry<P,V> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+impl<P: Point,V: Value> Eq for OrderedEntry<P,V> {}
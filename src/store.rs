@@ -1,21 +1,53 @@
-#[cfg(not(feature="wasm"))]
-use crate::{DB,Tree,Point,Value,Error,Setup,SetupFields,Scalar,Coord};
+use crate::{DB,Tree,Point,Value,Error,EyrosErrorKind,Setup,Scalar,Coord,RA};
 #[cfg(not(feature="wasm"))]
 use std::path::{Path,PathBuf};
 #[cfg(not(feature="wasm"))]
 type S = RandomAccessDisk;
 #[cfg(not(feature="wasm"))]
 use random_access_disk::RandomAccessDisk;
-#[cfg(not(feature="wasm"))] use async_std::sync::{Arc,Mutex};
+#[cfg(not(feature="wasm"))]
+use async_std::prelude::*;
+use async_std::sync::{Arc,Mutex};
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool,Ordering};
 
-#[cfg(feature="wasm")]
-use crate::Error;
+/// Guard returned by `Storage::lock()`. The lock is released when the guard is dropped.
+pub type StorageLock = Box<dyn std::any::Any+Send+Sync>;
 
 /// Return random access storage adaptors for files by a string name
 #[async_trait::async_trait]
 pub trait Storage<S>: Send+Sync+Unpin {
   async fn open(&mut self, name: &str) -> Result<S,Error>;
   async fn remove(&mut self, name: &str) -> Result<(),Error>;
+  /// Read the whole contents of the file `name`, or `None` if there is no such file. A database
+  /// opened with `Setup::read_only(true)` reads every file this way, so storage that can read
+  /// without creating the file or opening it for writing should override this. The default
+  /// reads a file returned by `open()`, which returns an empty file as `None`.
+  async fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>,Error> where S: RA {
+    let mut s = self.open(name).await?;
+    let len = s.len().await?;
+    if len == 0 { return Ok(None) }
+    Ok(Some(s.read(0, len).await?))
+  }
+  /// List the names of every file under the directory `prefix` (such as `"t/"`), in a form that
+  /// can be passed to `open()`. Storage that can't list its files returns an empty list, which
+  /// skips the checks that depend on a listing.
+  async fn list(&mut self, _prefix: &str) -> Result<Vec<String>,Error> {
+    Ok(vec![])
+  }
+  /// Take an exclusive lock for a writer, held until the returned guard is dropped. Storage that
+  /// is already locked fails with `EyrosErrorKind::Locked`. Storage without a way to lock returns
+  /// a guard that holds nothing.
+  async fn lock(&mut self) -> Result<StorageLock,Error> {
+    Ok(Box::new(()))
+  }
+}
+
+// read the whole file `name` through `Storage::read()`, with a missing file read as empty
+pub(crate) async fn read_file<S>(storage: &Arc<Mutex<Box<dyn Storage<S>>>>, name: &str)
+-> Result<Vec<u8>,Error> where S: RA {
+  Ok(storage.lock().await.read(name).await?.unwrap_or_default())
 }
 
 #[cfg(not(feature="wasm"))]
@@ -47,8 +79,64 @@ impl Storage<S> for FileStore {
     async_std::fs::remove_file(file).await?;
     Ok(())
   }
+  async fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>,Error> {
+    match async_std::fs::read(self.path.join(name)).await {
+      Ok(bytes) => Ok(Some(bytes)),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e.into()),
+    }
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    let mut files = vec![];
+    let mut dirs: Vec<PathBuf> = vec![self.path.join(prefix)];
+    while let Some(dir) = dirs.pop() {
+      let mut entries = match async_std::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+        Err(e) => return Err(e.into()),
+      };
+      while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let path: PathBuf = entry.path().into();
+        if entry.file_type().await?.is_dir() {
+          dirs.push(path);
+        } else {
+          let name = path.strip_prefix(&self.path)?.iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+          files.push(name);
+        }
+      }
+    }
+    files.sort_unstable();
+    Ok(files)
+  }
+  #[cfg(unix)]
+  async fn lock(&mut self) -> Result<StorageLock,Error> {
+    use std::os::unix::io::AsRawFd;
+    async_std::fs::create_dir_all(&self.path).await?;
+    let file = std::fs::OpenOptions::new()
+      .create(true)
+      .write(true)
+      .truncate(false)
+      .open(self.path.join(LOCK_FILE))?;
+    // the lock is released when the file is closed, including when the process exits
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+      let err = std::io::Error::last_os_error();
+      if err.kind() == std::io::ErrorKind::WouldBlock {
+        let file = self.path.join(LOCK_FILE).display().to_string();
+        return EyrosErrorKind::Locked { file }.raise();
+      }
+      return Err(err.into());
+    }
+    Ok(Box::new(file))
+  }
 }
 
+#[cfg(not(feature="wasm"))]
+const LOCK_FILE: &str = "lock";
+
 #[cfg(not(feature="wasm"))]
 impl<T,P,V> DB<S,T,P,V> where P: Point, V: Value, T: Tree<P,V> {
   pub async fn open_from_path(path: &Path) -> Result<Self,Error> {
@@ -60,16 +148,150 @@ impl<T,P,V> DB<S,T,P,V> where P: Point, V: Value, T: Tree<P,V> {
 impl Setup<S> {
   /// Create a new `Setup` builder from a string file path.
   pub fn from_path(path: &Path) -> Self {
-    Self {
-      storage: Arc::new(Mutex::new(Box::new(FileStore::new(Path::new(path))))),
-      fields: SetupFields::default()
+    Self::from_storage(Box::new(FileStore::new(Path::new(path))))
+  }
+}
+
+type MemoryFiles = HashMap<String,Arc<Mutex<Vec<u8>>>>;
+
+/// Storage that keeps every file in memory. Clones share the same files, so a database can be
+/// closed and opened again from a clone of the same `MemoryStore`.
+#[derive(Debug,Clone,Default)]
+pub struct MemoryStore {
+  files: Arc<Mutex<MemoryFiles>>,
+  locked: Arc<AtomicBool>,
+}
+
+impl MemoryStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for MemoryStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    let mut files = self.files.lock().await;
+    let data = files.entry(name.to_string()).or_insert_with(|| Arc::new(Mutex::new(vec![])));
+    Ok(MemoryFile { data: Arc::clone(data) })
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    match self.files.lock().await.remove(name) {
+      Some(_) => Ok(()),
+      None => Err(Box::new(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!["file not found: {}", name]
+      ))),
+    }
+  }
+  async fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>,Error> {
+    let data = match self.files.lock().await.get(name) {
+      Some(data) => Arc::clone(data),
+      None => return Ok(None),
+    };
+    let bytes = data.lock().await.clone();
+    Ok(Some(bytes))
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    let mut files: Vec<String> = self.files.lock().await.keys()
+      .filter(|name| name.starts_with(prefix))
+      .cloned()
+      .collect();
+    files.sort_unstable();
+    Ok(files)
+  }
+  async fn lock(&mut self) -> Result<StorageLock,Error> {
+    if self.locked.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+      return EyrosErrorKind::Locked { file: "[memory]".into() }.raise();
+    }
+    Ok(Box::new(MemoryLock { locked: Arc::clone(&self.locked) }))
+  }
+}
+
+struct MemoryLock {
+  locked: Arc<AtomicBool>,
+}
+
+impl Drop for MemoryLock {
+  fn drop(&mut self) {
+    self.locked.store(false, Ordering::SeqCst);
+  }
+}
+
+/// Random access adaptor for a file held by a `MemoryStore`.
+#[derive(Debug,Clone)]
+pub struct MemoryFile {
+  data: Arc<Mutex<Vec<u8>>>,
+}
+
+#[async_trait::async_trait]
+impl RandomAccess for MemoryFile {
+  type Error = Error;
+  async fn write(&mut self, offset: u64, data: &[u8]) -> Result<(),Self::Error> {
+    let mut buf = self.data.lock().await;
+    let (start,end) = (offset as usize, offset as usize + data.len());
+    if end > buf.len() {
+      buf.resize(end, 0);
+    }
+    buf[start..end].copy_from_slice(data);
+    Ok(())
+  }
+  async fn read(&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Self::Error> {
+    let buf = self.data.lock().await;
+    if offset + length > buf.len() as u64 {
+      return Err(Box::new(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        format!["Read bounds exceeded. {} < {}..{}", buf.len(), offset, offset + length]
+      )));
     }
+    Ok(buf[offset as usize..(offset+length) as usize].to_vec())
+  }
+  // the writer may not be Unpin and is only borrowed, so it can't be pinned to write to it
+  async fn read_to_writer(&mut self, _offset: u64, _length: u64,
+  _buf: &mut (impl async_std::io::Write + Send)) -> Result<(),Self::Error> {
+    Err(Box::new(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "read_to_writer is not supported by MemoryFile"
+    )))
+  }
+  async fn del(&mut self, offset: u64, length: u64) -> Result<(),Self::Error> {
+    let mut buf = self.data.lock().await;
+    let start = (offset as usize).min(buf.len());
+    let end = ((offset + length) as usize).min(buf.len());
+    buf[start..end].fill(0);
+    Ok(())
+  }
+  async fn truncate(&mut self, length: u64) -> Result<(),Self::Error> {
+    self.data.lock().await.resize(length as usize, 0);
+    Ok(())
+  }
+  async fn len(&self) -> Result<u64,Self::Error> {
+    Ok(self.data.lock().await.len() as u64)
+  }
+  async fn is_empty(&mut self) -> Result<bool,Self::Error> {
+    Ok(self.data.lock().await.is_empty())
+  }
+  async fn sync_all(&mut self) -> Result<(),Self::Error> {
+    Ok(())
+  }
+}
+
+impl<T,P,V> DB<MemoryFile,T,P,V> where P: Point, V: Value, T: Tree<P,V> {
+  /// Open a new, empty database held entirely in memory.
+  pub async fn open_in_memory() -> Result<Self,Error> {
+    Setup::in_memory().build().await
+  }
+}
+
+impl Setup<MemoryFile> {
+  /// Create a new `Setup` builder backed by a fresh `MemoryStore`.
+  pub fn in_memory() -> Self {
+    Self::from_storage(Box::new(MemoryStore::new()))
   }
 }
 
 macro_rules! impl_open {
-  ($Tree:ident,$open_from_path:ident,($($T:tt),+)) => {
-    #[cfg(not(feature="wasm"))]
+  ($Tree:ident,$open_from_path:ident,$open_in_memory:ident,($($T:tt),+)) => {
     use crate::$Tree;
     #[cfg(not(feature="wasm"))]
     /// Open a database from a `path` in N dimensions.
@@ -79,13 +301,20 @@ macro_rules! impl_open {
     where $($T: Scalar),+, V: Value {
       <DB<S,$Tree<$($T),+,V>,($(Coord<$T>),+),V>>::open_from_path(path).await
     }
+    /// Open a new in-memory database in N dimensions.
+    /// This function helps in selecting the appropriate `Tree{N}` type.
+    pub async fn $open_in_memory<$($T),+,V>()
+    -> Result<DB<MemoryFile,$Tree<$($T),+,V>,($(Coord<$T>),+),V>,Error>
+    where $($T: Scalar),+, V: Value {
+      <DB<MemoryFile,$Tree<$($T),+,V>,($(Coord<$T>),+),V>>::open_in_memory().await
+    }
   }
 }
 
-#[cfg(feature="2d")] impl_open![Tree2,open_from_path2,(P0,P1)];
-#[cfg(feature="3d")] impl_open![Tree3,open_from_path3,(P0,P1,P2)];
-#[cfg(feature="4d")] impl_open![Tree4,open_from_path4,(P0,P1,P2,P3)];
-#[cfg(feature="5d")] impl_open![Tree5,open_from_path5,(P0,P1,P2,P3,P4)];
-#[cfg(feature="6d")] impl_open![Tree6,open_from_path6,(P0,P1,P2,P3,P4,P5)];
-#[cfg(feature="7d")] impl_open![Tree7,open_from_path7,(P0,P1,P2,P3,P4,P5,P6)];
-#[cfg(feature="8d")] impl_open![Tree8,open_from_path8,(P0,P1,P2,P3,P4,P5,P6,P7)];
+#[cfg(feature="2d")] impl_open![Tree2,open_from_path2,open_in_memory2,(P0,P1)];
+#[cfg(feature="3d")] impl_open![Tree3,open_from_path3,open_in_memory3,(P0,P1,P2)];
+#[cfg(feature="4d")] impl_open![Tree4,open_from_path4,open_in_memory4,(P0,P1,P2,P3)];
+#[cfg(feature="5d")] impl_open![Tree5,open_from_path5,open_in_memory5,(P0,P1,P2,P3,P4)];
+#[cfg(feature="6d")] impl_open![Tree6,open_from_path6,open_in_memory6,(P0,P1,P2,P3,P4,P5)];
+#[cfg(feature="7d")] impl_open![Tree7,open_from_path7,open_in_memory7,(P0,P1,P2,P3,P4,P5,P6)];
+#[cfg(feature="8d")] impl_open![Tree8,open_from_path8,open_in_memory8,(P0,P1,P2,P3,P4,P5,P6,P7)];
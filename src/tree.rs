@@ -1,11 +1,13 @@
 use desert::{ToBytes,FromBytes,CountBytes};
-use crate::{Scalar,Point,Value,Coord,Error,EyrosErrorKind,Overlap,RA,Root,
-  query::{QStream,QTrace}, tree_file::TreeFile, SetupFields};
+use crate::{Scalar,Point,Dimensions,Value,Coord,Error,EyrosErrorKind,Overlap,RA,Root,
+  query::{QStream,QShared,QFilter,QueryMode}, QueryShape, tree_file::TreeFile, SetupFields, FromBytesVersion,
+  nearest::{Metric,Dist,push_nearest,nearest_cutoff}};
 use async_std::{sync::{Arc,Mutex},channel};
 #[cfg(not(feature="wasm"))] use async_std::task::spawn;
 #[cfg(feature="wasm")] use async_std::task::{spawn_local as spawn};
 use crate::unfold::unfold;
-use std::collections::{HashMap,HashSet,VecDeque};
+use std::collections::{HashMap,HashSet,VecDeque,BinaryHeap};
+use std::cmp::Reverse;
 use futures::future::join_all;
 
 pub type TreeId = u64;
@@ -13,6 +15,9 @@ pub type TreeId = u64;
 pub struct TreeRef<P> {
   pub id: TreeId,
   pub bounds: P,
+  /// Number of records in the referenced tree and every tree beneath it, or `None` when a tree
+  /// beneath it was written before counts were stored.
+  pub count: Option<u64>,
 }
 
 pub struct Build {
@@ -126,6 +131,10 @@ macro_rules! impl_tree {
               self.sorted[build.range.0..build.range.1].iter().map(|i| *i),
               self.inserts
             ),
+            count: count_inserts(
+              self.sorted[build.range.0..build.range.1].iter().map(|i| *i),
+              self.inserts
+            ),
           };
           self.next_tree += 1;
           let inserts = &self.inserts;
@@ -144,6 +153,10 @@ macro_rules! impl_tree {
               self.sorted[build.range.0..build.range.1].iter().map(|i| *i),
               self.inserts
             ),
+            count: count_inserts(
+              self.sorted[build.range.0..build.range.1].iter().map(|i| *i),
+              self.inserts
+            ),
           };
           self.next_tree += 1;
           let t = $Tree::new(Arc::new(self.build(&build.ext(), is_rm)));
@@ -351,6 +364,7 @@ macro_rules! impl_tree {
         let tr = TreeRef {
           id: *next_tree,
           bounds,
+          count: count_inserts(0..inserts.len(), inserts),
         };
         *next_tree += 1;
         mstate.ext_trees.insert(tr.id, Arc::new(Mutex::new($Tree {
@@ -431,9 +445,19 @@ macro_rules! impl_tree {
         refs
       }
       fn query_local(
-        &mut self, bbox: &(($($T),+),($($T),+))
+        &mut self, bbox: &(($($T),+),($($T),+)), mode: QueryMode,
       ) -> (Vec<(($(Coord<$T>),+),V)>,Vec<TreeRef<($(Coord<$T>),+)>>) {
         let mut rows = vec![];
+        let refs = self.visit_local(bbox, mode, &mut |p,v| rows.push((p.clone(),v.clone())));
+        (rows,refs)
+      }
+
+      fn visit_local(
+        &mut self,
+        bbox: &(($($T),+),($($T),+)),
+        mode: QueryMode,
+        visit: &mut dyn FnMut(&($(Coord<$T>),+),&V),
+      ) -> Vec<TreeRef<($(Coord<$T>),+)>> {
         let mut refs = vec![];
         let mut cursors = VecDeque::new();
         cursors.push_back((0,self.root.clone()));
@@ -446,22 +470,11 @@ macro_rules! impl_tree {
                   let pivots = branch.pivots.$i.as_ref().unwrap();
 
                   {
-                    let mut matching: u32 = 0;
-                    if &(bbox.0).$i <= pivots.first().unwrap() {
-                      matching |= (1<<0);
-                    }
-                    let ranges = pivots.iter().zip(pivots.iter().skip(1));
-                    for (i,(start,end)) in ranges.enumerate() {
-                      if intersect_iv(start, end, &(bbox.0).$i, &(bbox.1).$i) {
-                        matching |= (1<<i);
-                        matching |= (1<<(i+1));
-                      }
-                    }
-                    if &(bbox.1).$i >= pivots.last().unwrap() {
-                      matching |= (1<<(pivots.len()-1));
-                    }
+                    let matching = pivot_matching(pivots, &(bbox.0).$i, &(bbox.1).$i);
                     for (bitfield,b) in branch.intersections.iter() {
-                      if (matching & bitfield) > 0 {
+                      if (matching & bitfield) > 0 && match_intersection(
+                        pivots, *bitfield, &(bbox.0).$i, &(bbox.1).$i, mode
+                      ) {
                         cursors.push_back((level+1,Arc::clone(b)));
                       }
                     }
@@ -469,16 +482,17 @@ macro_rules! impl_tree {
 
                   {
                     let xs = &branch.nodes;
+                    let (low,high) = (&(bbox.0).$i, &(bbox.1).$i);
                     let ranges = pivots.iter().zip(pivots.iter().skip(1));
-                    if &(bbox.0).$i <= pivots.first().unwrap() {
+                    if match_range(None, pivots.first(), low, high, mode) {
                       cursors.push_back((level+1,Arc::clone(xs.first().unwrap())));
                     }
                     for ((start,end),b) in ranges.zip(xs.iter().skip(1)) {
-                      if intersect_iv(start, end, &(bbox.0).$i, &(bbox.1).$i) {
+                      if match_range(Some(start), Some(end), low, high, mode) {
                         cursors.push_back((level+1,Arc::clone(b)));
                       }
                     }
-                    if &(bbox.1).$i >= pivots.last().unwrap() {
+                    if match_range(pivots.last(), None, low, high, mode) {
                       cursors.push_back((level+1,Arc::clone(xs.last().unwrap())));
                     }
                   }
@@ -487,16 +501,14 @@ macro_rules! impl_tree {
               }
             },
             $Node::Data(data,rs) => {
-              rows.extend(data.iter()
-                .filter(|pv| {
-                  true $(&& intersect_coord(&(pv.0).$i, &(bbox.0).$i, &(bbox.1).$i))+
-                })
-                .cloned()
-                .collect::<Vec<_>>()
-              );
+              for (p,v) in data.iter() {
+                if true $(&& match_coord(&p.$i, &(bbox.0).$i, &(bbox.1).$i, mode))+ {
+                  visit(p,v);
+                }
+              }
               refs.extend(rs.iter()
                 .filter(|r| {
-                  true $(&& intersect_coord(&r.bounds.$i, &(bbox.0).$i, &(bbox.1).$i))+
+                  true $(&& match_bounds(&r.bounds.$i, &(bbox.0).$i, &(bbox.1).$i, mode))+
                 })
                 .cloned()
                 .collect::<Vec<TreeRef<($(Coord<$T>),+)>>>()
@@ -504,28 +516,204 @@ macro_rules! impl_tree {
             },
           }
         }
+        refs
+      }
+
+      fn many_local(
+        &mut self, bboxes: &[(($($T),+),($($T),+))], active: &[usize],
+      ) -> ManyLocal<($(Coord<$T>),+),V> {
+        let mut rows = vec![];
+        let mut refs = vec![];
+        // nodes with the indexes of the boxes that may match their records
+        let mut cursors = vec![(0,self.root.clone(),active.to_vec())];
+        while let Some((level,c,active)) = cursors.pop() {
+          match c.as_ref() {
+            $Node::Branch(branch) => {
+              match level % $dim {
+                $($i => {
+                  let pivots = branch.pivots.$i.as_ref().unwrap();
+                  let matching: Vec<u32> = active.iter().map(|k| {
+                    pivot_matching(pivots, &(bboxes[*k].0).$i, &(bboxes[*k].1).$i)
+                  }).collect();
+                  for (bitfield,b) in branch.intersections.iter() {
+                    let xs: Vec<usize> = active.iter().zip(matching.iter())
+                      .filter(|(_,m)| (*m & bitfield) > 0)
+                      .map(|(k,_)| *k)
+                      .collect();
+                    if !xs.is_empty() {
+                      cursors.push((level+1,Arc::clone(b),xs));
+                    }
+                  }
+                  for (j,b) in branch.nodes.iter().enumerate() {
+                    let (min,max) = (j.checked_sub(1).map(|j| &pivots[j]), pivots.get(j));
+                    let xs: Vec<usize> = active.iter().filter(|k| {
+                      let (low,high) = (&(bboxes[**k].0).$i, &(bboxes[**k].1).$i);
+                      match_range(min, max, low, high, QueryMode::Intersects)
+                    }).copied().collect();
+                    if !xs.is_empty() {
+                      cursors.push((level+1,Arc::clone(b),xs));
+                    }
+                  }
+                }),+
+                _ => panic!["unexpected level modulo dimension"]
+              }
+            },
+            $Node::Data(data,rs) => {
+              for (p,v) in data.iter() {
+                for k in active.iter() {
+                  let bbox = &bboxes[*k];
+                  if true $(&& intersect_coord(&p.$i, &(bbox.0).$i, &(bbox.1).$i))+ {
+                    rows.push((*k,(p.clone(),v.clone())));
+                  }
+                }
+              }
+              for r in rs.iter() {
+                let xs: Vec<usize> = active.iter().filter(|k| {
+                  let bbox = &bboxes[**k];
+                  true $(&& intersect_coord(&r.bounds.$i, &(bbox.0).$i, &(bbox.1).$i))+
+                }).copied().collect();
+                if !xs.is_empty() {
+                  refs.push((r.clone(),xs));
+                }
+              }
+            },
+          }
+        }
         (rows,refs)
       }
 
+      fn shape_local(
+        &mut self,
+        shape: &dyn QueryShape<($(Coord<$T>),+)>,
+        bounds: &($(Coord<$T>),+),
+      ) -> (Vec<(($(Coord<$T>),+),V)>,Vec<TreeRef<($(Coord<$T>),+)>>) {
+        let mut rows = vec![];
+        let mut refs = vec![];
+        // nodes with the region their records lie within, narrowed by the pivots above them
+        let region = (($(coord_start(&bounds.$i)),+),($(coord_end(&bounds.$i)),+));
+        let mut cursors = vec![(0,self.root.clone(),region)];
+        while let Some((level,c,region)) = cursors.pop() {
+          match c.as_ref() {
+            $Node::Branch(branch) => {
+              match level % $dim {
+                $($i => {
+                  let pivots = branch.pivots.$i.as_ref().unwrap();
+                  let clip = |min: Option<&_>, max: Option<&_>| {
+                    let mut r = region.clone();
+                    if let Some(m) = min { if *m > (r.0).$i { (r.0).$i = Clone::clone(m) } }
+                    if let Some(m) = max { if *m < (r.1).$i { (r.1).$i = Clone::clone(m) } }
+                    if (r.0).$i <= (r.1).$i && shape.overlaps_bounds(&r) { Some(r) } else { None }
+                  };
+                  // records in an intersection touch the pivots in its bitfield and no others
+                  for (bitfield,b) in branch.intersections.iter() {
+                    let lo = bitfield.trailing_zeros() as usize;
+                    let hi = 31 - bitfield.leading_zeros() as usize;
+                    if let Some(r) = clip(lo.checked_sub(1).map(|j| &pivots[j]), pivots.get(hi+1)) {
+                      cursors.push((level+1,Arc::clone(b),r));
+                    }
+                  }
+                  for (j,b) in branch.nodes.iter().enumerate() {
+                    if let Some(r) = clip(j.checked_sub(1).map(|j| &pivots[j]), pivots.get(j)) {
+                      cursors.push((level+1,Arc::clone(b),r));
+                    }
+                  }
+                }),+
+                _ => panic!["unexpected level modulo dimension"]
+              }
+            },
+            $Node::Data(data,rs) => {
+              for (p,v) in data.iter() {
+                if shape.matches(p) {
+                  rows.push((p.clone(),v.clone()));
+                }
+              }
+              for r in rs.iter() {
+                let b = (($(coord_start(&r.bounds.$i)),+),($(coord_end(&r.bounds.$i)),+));
+                if shape.overlaps_bounds(&b) {
+                  refs.push(r.clone());
+                }
+              }
+            },
+          }
+        }
+        (rows,refs)
+      }
+
+      fn nearest_local(
+        &mut self, point: &[(f64,f64)], k: usize, metric: &Metric, bounds: &($(Coord<$T>),+),
+      ) -> NearestLocal<($(Coord<$T>),+),V> where ($(Coord<$T>),+): Dimensions {
+        let mut best = BinaryHeap::new();
+        let mut refs = vec![];
+        if k == 0 { return (vec![],refs) }
+        let distance = |r: &(($($T),+),($($T),+))| {
+          let p = ($(Coord::Interval((r.0).$i.clone(),(r.1).$i.clone())),+);
+          metric.interval_distance(point, &p.to_f64_intervals())
+        };
+        // nodes by the distance to the region their records lie within, nearest first
+        let mut cursors = BinaryHeap::new();
+        let region = (($(coord_start(&bounds.$i)),+),($(coord_end(&bounds.$i)),+));
+        cursors.push(Reverse(Dist(distance(&region),(0,self.root.clone(),region))));
+        while let Some(Reverse(Dist(d,(level,c,region)))) = cursors.pop() {
+          if d > nearest_cutoff(&best, k) { break }
+          match c.as_ref() {
+            $Node::Branch(branch) => {
+              match level % $dim {
+                $($i => {
+                  let pivots = branch.pivots.$i.as_ref().unwrap();
+                  let mut push = |b: &Arc<_>, min: Option<&_>, max: Option<&_>| {
+                    let mut r = region.clone();
+                    if let Some(m) = min { if *m > (r.0).$i { (r.0).$i = Clone::clone(m) } }
+                    if let Some(m) = max { if *m < (r.1).$i { (r.1).$i = Clone::clone(m) } }
+                    cursors.push(Reverse(Dist(distance(&r),(level+1,Arc::clone(b),r))));
+                  };
+                  // records in an intersection touch the pivots in its bitfield and no others
+                  for (bitfield,b) in branch.intersections.iter() {
+                    let lo = bitfield.trailing_zeros() as usize;
+                    let hi = 31 - bitfield.leading_zeros() as usize;
+                    push(b, lo.checked_sub(1).map(|j| &pivots[j]), pivots.get(hi+1));
+                  }
+                  for (j,b) in branch.nodes.iter().enumerate() {
+                    push(b, j.checked_sub(1).map(|j| &pivots[j]), pivots.get(j));
+                  }
+                }),+
+                _ => panic!["unexpected level modulo dimension"]
+              }
+            },
+            $Node::Data(data,rs) => {
+              for (p,v) in data.iter() {
+                let d = metric.interval_distance(point, &p.to_f64_intervals());
+                push_nearest(&mut best, k, d, (p.clone(),v.clone()));
+              }
+              for r in rs.iter() {
+                let d = metric.interval_distance(point, &r.bounds.to_f64_intervals());
+                refs.push((d,r.clone()));
+              }
+            },
+          }
+        }
+        let cutoff = nearest_cutoff(&best, k);
+        refs.retain(|(d,_)| *d <= cutoff);
+        (best.into_vec().into_iter().map(|Dist(d,pv)| (d,pv)).collect(), refs)
+      }
+
       fn query<S>(
         &mut self,
         trees: Arc<TreeFile<S,Self,($(Coord<$T>),+),V>>,
-        bbox: &(($($T),+),($($T),+)),
         fields: Arc<SetupFields>,
         root_index: usize,
         root: &TreeRef<($(Coord<$T>),+)>,
+        shared: Arc<QShared<($(Coord<$T>),+)>>,
       ) -> QStream<($(Coord<$T>),+),V> where S: RA {
-        self.query_trace(trees, bbox, fields, root_index, root, None)
+        self.query_trace(trees, fields, root_index, root, shared)
       }
 
       fn query_trace<S>(
         &mut self,
         trees: Arc<TreeFile<S,Self,($(Coord<$T>),+),V>>,
-        bbox: &(($($T),+),($($T),+)),
         fields: Arc<SetupFields>,
         root_index: usize,
         root: &TreeRef<($(Coord<$T>),+)>,
-        o_trace: Option<Arc<Mutex<Box<dyn QTrace<($(Coord<$T>),+)>>>>>,
+        shared: Arc<QShared<($(Coord<$T>),+)>>,
       ) -> QStream<($(Coord<$T>),+),V> where S: RA {
         let nproc = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
         let (refs_sender,refs_receiver) = channel::unbounded::<TreeRef<($(Coord<$T>),+)>>();
@@ -535,10 +723,11 @@ macro_rules! impl_tree {
         ),Error>
         >(nproc);
         let (trace_sender,trace_receiver) = channel::unbounded::<TreeRef<($(Coord<$T>),+)>>();
-        if let Some(trace_r) = &o_trace {
-          let trace = trace_r.clone();
+        if shared.trace.is_some() {
+          let shared_c = shared.clone();
           let root_c = root.clone();
           spawn(async move {
+            let trace = shared_c.trace.as_ref().unwrap();
             trace.lock().await.trace(root_c);
             while let Ok(tr) = trace_receiver.recv().await {
               trace.lock().await.trace(tr);
@@ -549,20 +738,21 @@ macro_rules! impl_tree {
         for _ in 0..nproc {
           let refs_r = refs_receiver.clone();
           let queue_s = queue_sender.clone();
-          let bbox_c = bbox.clone();
+          let shared_c = shared.clone();
           let trees_c = trees.clone();
-          let is_tracing = o_trace.is_some();
+          let is_tracing = shared.trace.is_some();
           let trace_s = trace_sender.clone();
           spawn(async move {
+            // the stream closes the channels when it ends or is dropped. refs still buffered in
+            // the channel are skipped instead of loaded
             while let Ok(r) = refs_r.recv().await {
-              if is_tracing { trace_s.send(r.clone()).await.unwrap(); }
-              match trees_c.get(&r.id).await {
-                Err(e) => queue_s.send(Err(e.into())).await.unwrap(),
-                Ok(t) => {
-                  let (rows,refs) = t.lock().await.query_local(&bbox_c);
-                  queue_s.send(Ok((rows,refs))).await.unwrap();
-                }
+              if refs_r.is_closed() { break }
+              if is_tracing && trace_s.send(r.clone()).await.is_err() { break }
+              let res = match trees_c.get(&r.id).await {
+                Err(e) => Err(e.into()),
+                Ok(t) => Ok(t.lock().await.filter_local(&shared_c.filter, &r.bounds)),
               };
+              if queue_s.send(res).await.is_err() { break }
             }
             trace_s.close();
           });
@@ -582,9 +772,17 @@ macro_rules! impl_tree {
           refs_s: channel::Sender<TreeRef<($(Coord<$T>),+)>>,
           fields: Arc<SetupFields>,
           root: TreeRef<($(Coord<$T>),+)>,
+          shared: Arc<QShared<($(Coord<$T>),+)>>,
+        }
+        impl<$($T: Scalar),+, V: Value> Drop for QState<$($T),+,V> {
+          fn drop(&mut self) {
+            // stop the workers from loading more trees once the stream is dropped
+            self.refs_s.close();
+            self.queue_r.close();
+          }
         }
         let istate = {
-          let (v_results,v_refs) = self.query_local(bbox);
+          let (v_results,v_refs) = self.filter_local(&shared.filter, &root.bounds);
           let mut refs = VecDeque::with_capacity(v_refs.len());
           let mut results = VecDeque::with_capacity(v_results.len());
           for r in v_results { results.push_back(r); }
@@ -598,11 +796,15 @@ macro_rules! impl_tree {
             refs_s: refs_sender.clone(),
             fields: fields.clone(),
             root: root.clone(),
+            shared,
           }
         };
         Box::new(unfold(istate, async move |mut state| {
           loop {
-            if let Some(res) = state.results.pop_front() {
+            if state.shared.is_done() {
+              break;
+            } else if let Some(res) = state.results.pop_front() {
+              if !state.shared.take() { break }
               return Some((Ok(res),state));
             } else if state.active > 0 {
               match state.queue_r.recv().await.unwrap() {
@@ -644,7 +846,7 @@ macro_rules! impl_tree {
       }
 
       async fn remove<S>(&mut self, xids: Arc<Mutex<HashMap<V::Id,($(Coord<$T>),+)>>>)
-      -> (Option<(Vec<(($(Coord<$T>),+),V)>,Vec<TreeRef<($(Coord<$T>),+)>>)>,Vec<TreeId>) where S: RA {
+      -> RemoveLocal<($(Coord<$T>),+),V> where S: RA {
         let (mut list, refs) = self.list();
         let len = list.len();
         let mut ids = xids.lock().await;
@@ -668,11 +870,35 @@ macro_rules! impl_tree {
           .map(|r| { r.id })
           .collect::<Vec<TreeId>>();
         if len == list.len() {
-          (None,rs)
+          (None,rs,0)
         } else {
-          (Some((list,refs)),rs)
+          let removed = (len - list.len()) as u64;
+          (Some((list,refs)),rs,removed)
         }
       }
+
+      fn map_refs(&mut self, f: &mut dyn FnMut(&mut TreeRef<($(Coord<$T>),+)>)) -> Self {
+        fn map_node<$($T),+,V>(
+          node: &$Node<$($T),+,V>,
+          f: &mut dyn FnMut(&mut TreeRef<($(Coord<$T>),+)>),
+        ) -> $Node<$($T),+,V> where $($T: Scalar),+, V: Value {
+          match node {
+            $Node::Branch(branch) => $Node::Branch($Branch::new(
+              branch.pivots.clone(),
+              branch.intersections.iter()
+                .map(|(bitfield,b)| (*bitfield,Arc::new(map_node(b, f))))
+                .collect(),
+              branch.nodes.iter().map(|b| Arc::new(map_node(b, f))).collect(),
+            )),
+            $Node::Data(data,rs) => {
+              let mut rs = rs.clone();
+              for r in rs.iter_mut() { f(r) }
+              $Node::Data(data.clone(),rs)
+            },
+          }
+        }
+        Self::new(Arc::new(map_node(&self.root, f)))
+      }
     }
 
     fn $get_bounds<$($T),+,V,I>(mut indexes: I,
@@ -706,12 +932,18 @@ macro_rules! impl_tree {
       fn overlap(&self, other: &Self) -> bool {
         true $(&& intersect_iv(&(self.0).$i, &(self.1).$i, &(other.0).$i, &(other.1).$i))+
       }
+      fn contains(&self, other: &Self) -> bool {
+        true $(&& (self.0).$i <= (other.0).$i && (other.1).$i <= (self.1).$i)+
+      }
     }
 
     impl<$($T),+> Overlap for ($(Coord<$T>),+) where $($T: Scalar),+ {
       fn overlap(&self, other: &Self) -> bool {
         true $(&& intersect_coord_coord(&self.$i, &other.$i))+
       }
+      fn contains(&self, other: &Self) -> bool {
+        true $(&& contain_coord_coord(&self.$i, &other.$i))+
+      }
     }
   }
 }
@@ -743,9 +975,17 @@ macro_rules! impl_tree {
 ];
 
 type CreateTrees<T> = HashMap<TreeId,Arc<Mutex<T>>>;
+// (nearest records, references to trees that may hold nearer records), each with its distance
+type NearestLocal<P,V> = (Vec<(f64,(P,V))>,Vec<(f64,TreeRef<P>)>);
+// (records with the index of a box they intersect, references to trees with the indexes of the
+// boxes they intersect)
+type ManyLocal<P,V> = (Vec<(usize,(P,V))>,Vec<(TreeRef<P>,Vec<usize>)>);
+// (remaining records and references if any records were removed, references to trees that may
+// hold records to remove, number of records removed)
+type RemoveLocal<P,V> = (Option<(Vec<(P,V)>,Vec<TreeRef<P>>)>,Vec<TreeId>,u64);
 
 #[async_trait::async_trait]
-pub trait Tree<P,V>: Send+Sync+ToBytes+FromBytes+CountBytes+std::fmt::Debug+'static
+pub trait Tree<P,V>: Send+Sync+ToBytes+FromBytes+FromBytesVersion+CountBytes+std::fmt::Debug+'static
 where P: Point, V: Value {
   fn empty() -> Self;
   fn build<'a>(
@@ -756,26 +996,51 @@ where P: Point, V: Value {
   ) -> (Option<TreeRef<P>>,CreateTrees<Self>) where Self: Sized;
   fn list(&mut self) -> (Vec<(P,V)>,Vec<TreeRef<P>>);
   fn list_refs(&mut self) -> Vec<TreeRef<P>>;
-  fn query_local(&mut self, bbox: &P::Bounds) -> (Vec<(P,V)>,Vec<TreeRef<P>>);
+  fn query_local(&mut self, bbox: &P::Bounds, mode: QueryMode) -> (Vec<(P,V)>,Vec<TreeRef<P>>);
+  /// Call `visit` with every record of this tree that matches `bbox` in `mode` and return the
+  /// references to other trees that may hold matching records.
+  fn visit_local(&mut self, bbox: &P::Bounds, mode: QueryMode, visit: &mut dyn FnMut(&P,&V))
+    -> Vec<TreeRef<P>>;
+  /// Return the records of this tree that intersect any of the boxes in `bboxes` at the indexes in
+  /// `active`, once for each box with its index, and the references to other trees with the
+  /// indexes of the boxes that they intersect.
+  fn many_local(&mut self, bboxes: &[P::Bounds], active: &[usize]) -> ManyLocal<P,V>;
+  /// Return the records of this tree, whose records lie within `bounds`, that match `shape` and the
+  /// references to other trees that may hold matching records.
+  fn shape_local(&mut self, shape: &dyn QueryShape<P>, bounds: &P) -> (Vec<(P,V)>,Vec<TreeRef<P>>);
+  /// Return the records of this tree, whose records lie within `bounds`, that match `filter` and
+  /// the references to other trees that may hold matching records.
+  fn filter_local(&mut self, filter: &QFilter<P>, bounds: &P) -> (Vec<(P,V)>,Vec<TreeRef<P>>) {
+    match filter {
+      QFilter::Bounds(bbox,mode) => self.query_local(bbox, *mode),
+      QFilter::Shape(shape) => self.shape_local(shape.as_ref(), bounds),
+    }
+  }
+  /// Return the `k` records of this tree, whose records lie within `bounds`, nearest to `point`,
+  /// given as the `(min,max)` of each dimension, and the references to other trees that may hold
+  /// records nearer than the `k`th, each with its distance.
+  fn nearest_local(&mut self, point: &[(f64,f64)], k: usize, metric: &Metric, bounds: &P)
+    -> NearestLocal<P,V> where P: Dimensions;
   fn query<S>(
     &mut self,
     trees: Arc<TreeFile<S,Self,P,V>>,
-    bbox: &P::Bounds,
     fields: Arc<SetupFields>,
     root_index: usize,
     root: &TreeRef<P>,
+    shared: Arc<QShared<P>>,
   ) -> QStream<P,V> where S: RA;
   fn query_trace<S>(
     &mut self,
     trees: Arc<TreeFile<S,Self,P,V>>,
-    bbox: &P::Bounds,
     fields: Arc<SetupFields>,
     root_index: usize,
     root: &TreeRef<P>,
-    o_trace: Option<Arc<Mutex<Box<dyn QTrace<P>>>>>,
+    shared: Arc<QShared<P>>,
   ) -> QStream<P,V> where S: RA;
   async fn remove<S>(&mut self, ids: Arc<Mutex<HashMap<V::Id,P>>>)
-    -> (Option<(Vec<(P,V)>,Vec<TreeRef<P>>)>,Vec<TreeId>) where S: RA;
+    -> RemoveLocal<P,V> where S: RA;
+  /// Return a copy of this tree with `f` applied to every reference to another tree.
+  fn map_refs(&mut self, f: &mut dyn FnMut(&mut TreeRef<P>)) -> Self where Self: Sized;
 }
 
 pub struct Merge<'a,S,T,P,V>
@@ -789,6 +1054,13 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
   pub next_tree: &'a mut TreeId,
   pub rebuild_depth: usize,
   pub error_if_missing: bool,
+  /// The tree holding each deleted record that was found through the id index. These records are
+  /// removed from that tree without searching every tree that intersects their point.
+  pub owners: HashMap<V::Id,TreeId>,
+  /// Trees that `remove()` rebuilt without the deleted records, under their existing ids. They
+  /// are not put into `trees` until the caller swaps in the new roots, so queries read the
+  /// previous versions until then.
+  pub replaced: HashMap<TreeId,Arc<Mutex<T>>>,
 }
 
 // return value: (tree, remove_trees, create_trees)
@@ -807,7 +1079,9 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
     for _ in 0..self.rebuild_depth {
       let mut n_refs = vec![];
       for r in l_refs.iter() {
-        let (list,xrefs) = self.trees.get(&r.id).await?.lock().await.list();
+        let (list,xrefs) = self.get(&r.id).await?.lock().await.list();
+        // rebuilt into the merged tree, so the version without deleted records is not kept
+        self.replaced.remove(&r.id);
         lists.push(list);
         n_refs.extend(xrefs);
         rm_trees.push(r.id);
@@ -836,13 +1110,28 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
     );
     Ok((tr, rm_trees, create_trees))
   }
+  // read a tree, including a version rebuilt by remove()
+  async fn get(&self, id: &TreeId) -> Result<Arc<Mutex<T>>,Error> {
+    match self.replaced.get(id) {
+      Some(t) => Ok(Arc::clone(t)),
+      None => self.trees.get(id).await,
+    }
+  }
+  /// Rebuild every tree holding a deleted record into `replaced`, and lower the record counts of
+  /// `roots`, `inputs`, and the refs pointing at those trees.
   pub async fn remove(&mut self) -> Result<(),Error> {
     if self.deletes.is_empty() { return Ok(()) }
     let mut work = vec![];
+    let mut owned: HashMap<TreeId,(P,HashSet<V::Id>)> = HashMap::new();
     let ids = {
       let mut map = HashMap::new();
       for d in self.deletes.iter() {
-        map.insert(d.1.clone(), d.0.clone());
+        match self.owners.get(&d.1) {
+          Some(t) => {
+            owned.entry(*t).or_insert_with(|| (d.0.clone(),HashSet::new())).1.insert(d.1.clone());
+          },
+          None => { map.insert(d.1.clone(), d.0.clone()); },
+        }
       }
       Arc::new(Mutex::new(map))
     };
@@ -852,8 +1141,9 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
       f.max_depth = usize::MAX;
       Arc::new(f)
     };
+    let searching = !ids.lock().await.is_empty();
     for ro in self.roots.iter() {
-      if ro.is_none() { continue }
+      if ro.is_none() || !searching { continue }
       let r = ro.as_ref().unwrap();
       // TODO: remove the delete when found
       let trees = self.trees.clone();
@@ -861,55 +1151,94 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
       let id = r.id;
       let xfields = Arc::clone(&fields);
       work.push(async move {
-        let mut refs = vec![id];
-        while !refs.is_empty() {
-          let r = refs.pop().unwrap();
+        // (tree, parent tree, records removed from the tree itself) for every tree visited
+        let mut visited = vec![];
+        let mut replaced = vec![];
+        let mut refs = vec![(id,None)];
+        while let Some((r,parent)) = refs.pop() {
           let tm = trees.get(&r).await?;
           let mut t = tm.lock().await;
-          let (built,nrefs) = t.remove::<S>(
+          let (built,nrefs,removed) = t.remove::<S>(
             Arc::clone(&xids),
           ).await;
-          refs.extend(nrefs);
+          refs.extend(nrefs.into_iter().map(|x| (x,Some(r))));
+          visited.push((r,parent,removed));
           if let Some((list,refs)) = built {
-            let mut rows = Vec::with_capacity(list.len() + refs.len());
-            rows.extend(list.iter().map(|(p,v)| {
-              (p.clone(),InsertValue::Value(v))
-            }).collect::<Vec<_>>());
-            rows.extend(refs.iter().map(|r| {
-              (r.bounds.clone(),InsertValue::Ref(r.clone()))
-            }).collect::<Vec<_>>());
-            let mut next_tree = r;
-            if rows.is_empty() {
-              trees.put(&r, Arc::new(Mutex::new(T::empty()))).await?;
-            } else {
-              let (tr, create_trees) = T::build(
-                Arc::clone(&xfields),
-                &rows,
-                &mut next_tree,
-                true
-              );
-              let tr_id = tr.map(|x| x.id);
-              assert![tr_id == Some(r),
-                "unexpected id constructing replacement tree for remove(). \
-                expected: {:?}, received: {:?}", Some(r), tr_id
-              ];
-              assert![create_trees.len() == 1, "unexpected external sub-trees during remove()"];
-              for (r,t) in create_trees {
-                trees.put(&r, t).await?;
-              }
-            }
+            replaced.push((r, rebuild(Arc::clone(&xfields), r, &list, &refs)));
           }
         }
-        let r: Result<(),Error> = Ok(());
+        let r: Result<_,Error> = Ok((visited,replaced));
         r
       });
     }
-    for r in join_all(work).await { r?; }
+    let mut parents = HashMap::new();
+    let mut removed = vec![];
+    for r in join_all(work).await {
+      let (visited,replaced) = r?;
+      for (id,parent,n) in visited {
+        if let Some(p) = parent { parents.insert(id, p); }
+        if n > 0 { removed.push((id,n)); }
+      }
+      self.replaced.extend(replaced);
+    }
+    let mut missing = vec![];
+    for (t,(p,xids)) in owned.iter() {
+      // find the trees above `t` along the refs that hold its records
+      let mut found = false;
+      let mut refs: Vec<(TreeId,Option<TreeId>)> = self.roots.iter().flatten()
+        .filter(|r| r.bounds.overlap(p))
+        .map(|r| (r.id,None))
+        .collect();
+      while let Some((id,parent)) = refs.pop() {
+        if let Some(parent) = parent { parents.insert(id, parent); }
+        if id == *t {
+          found = true;
+          break;
+        }
+        let xrefs = self.get(&id).await?.lock().await.list_refs();
+        refs.extend(xrefs.iter().filter(|r| r.bounds.overlap(p)).map(|r| (r.id,Some(id))));
+      }
+      if !found {
+        missing.extend(xids.iter().cloned());
+        continue;
+      }
+      let (mut list,xrefs) = self.get(t).await?.lock().await.list();
+      let len = list.len();
+      let mut gone = xids.clone();
+      list.retain(|(_,v)| !gone.remove(&v.get_id()));
+      missing.extend(gone);
+      if len == list.len() { continue }
+      self.replaced.insert(*t, rebuild(Arc::clone(&fields), *t, &list, &xrefs));
+      removed.push((*t,(len - list.len()) as u64));
+    }
+    // records removed beneath each tree, to lower the counts of the refs pointing at it
+    let mut counts: HashMap<TreeId,u64> = HashMap::new();
+    for (id,n) in removed {
+      let mut x = Some(id);
+      while let Some(id) = x {
+        *counts.entry(id).or_default() += n;
+        x = parents.get(&id).copied();
+      }
+    }
+    let update: HashSet<TreeId> = counts.keys().filter_map(|id| parents.get(id).copied()).collect();
+    let mut lower = |r: &mut TreeRef<P>| {
+      if let Some(n) = counts.get(&r.id) {
+        r.count = r.count.map(|c| c.saturating_sub(*n));
+      }
+    };
+    for id in update {
+      let t = self.get(&id).await?.lock().await.map_refs(&mut lower);
+      self.replaced.insert(id, Arc::new(Mutex::new(t)));
+    }
+    for r in self.roots.iter_mut().flatten() {
+      lower(r);
+    }
+    self.inputs = Arc::new(self.inputs.iter().cloned().map(|mut r| { lower(&mut r); r }).collect());
     if self.error_if_missing {
       let xids = ids.lock().await;
-      if !xids.is_empty() {
+      if !xids.is_empty() || !missing.is_empty() {
         return EyrosErrorKind::RemoveIdsMissing {
-          ids: xids.keys().map(|id| format!["{:?}",id]).collect()
+          ids: xids.keys().chain(missing.iter()).map(|id| format!["{:?}",id]).collect()
         }.raise();
       }
     }
@@ -917,6 +1246,30 @@ where P: Point, V: Value, T: Tree<P,V>, S: RA {
   }
 }
 
+// build the replacement for tree `id` from the records and refs it keeps after a removal
+fn rebuild<T,P,V>(fields: Arc<SetupFields>, id: TreeId, list: &[(P,V)], refs: &[TreeRef<P>])
+-> Arc<Mutex<T>> where P: Point, V: Value, T: Tree<P,V> {
+  let mut rows = Vec::with_capacity(list.len() + refs.len());
+  rows.extend(list.iter().map(|(p,v)| {
+    (p.clone(),InsertValue::Value(v))
+  }).collect::<Vec<_>>());
+  rows.extend(refs.iter().map(|r| {
+    (r.bounds.clone(),InsertValue::Ref(r.clone()))
+  }).collect::<Vec<_>>());
+  if rows.is_empty() {
+    return Arc::new(Mutex::new(T::empty()));
+  }
+  let mut next_tree = id;
+  let (tr, create_trees) = T::build(fields, &rows, &mut next_tree, true);
+  let tr_id = tr.map(|x| x.id);
+  assert![tr_id == Some(id),
+    "unexpected id constructing replacement tree for remove(). \
+    expected: {:?}, received: {:?}", Some(id), tr_id
+  ];
+  assert![create_trees.len() == 1, "unexpected external sub-trees during remove()"];
+  create_trees.into_iter().next().unwrap().1
+}
+
 /// Get the string path for a given TreeId.
 pub fn get_file_from_id(id: &TreeId) -> String {
   format![
@@ -932,6 +1285,15 @@ pub fn get_file_from_id(id: &TreeId) -> String {
   ]
 }
 
+// number of records the rows at the given indexes hold, counting the records beneath each ref
+fn count_inserts<P,V,I>(indexes: I, rows: &[(P,InsertValue<'_,P,V>)]) -> Option<u64>
+where P: Point, V: Value, I: Iterator<Item=usize> {
+  indexes.map(|i| match &rows[i].1 {
+    InsertValue::Value(_) => Some(1),
+    InsertValue::Ref(r) => r.count,
+  }).sum()
+}
+
 fn find_separation<X>(amin: &X, amax: &X, bmin: &X, bmax: &X, is_min: bool) -> X where X: Scalar {
   if is_min && intersect_iv(amin, amax, bmin, bmax) {
     amin.clone()/2.into() + bmin.clone()/2.into()
@@ -962,6 +1324,15 @@ fn intersect_coord_coord<X>(a: &Coord<X>, b: &Coord<X>) -> bool where X: Scalar
   }
 }
 
+fn contain_coord_coord<X>(a: &Coord<X>, b: &Coord<X>) -> bool where X: Scalar {
+  match (a,b) {
+    (Coord::Scalar(x),Coord::Scalar(y)) => *x == *y,
+    (Coord::Scalar(x),Coord::Interval(y0,y1)) => *x == *y0 && *x == *y1,
+    (Coord::Interval(x0,x1),Coord::Scalar(y)) => *x0 <= *y && *y <= *x1,
+    (Coord::Interval(x0,x1),Coord::Interval(y0,y1)) => *x0 <= *y0 && *y1 <= *x1,
+  }
+}
+
 fn intersect_coord<X>(c: &Coord<X>, low: &X, high: &X) -> bool where X: Scalar {
   match c {
     Coord::Scalar(x) => low <= x && x <= high,
@@ -969,6 +1340,74 @@ fn intersect_coord<X>(c: &Coord<X>, low: &X, high: &X) -> bool where X: Scalar {
   }
 }
 
+// bitfield of the pivots on either side of each range between pivots that `low..=high` intersects,
+// to compare against the bitfields of intersection nodes
+fn pivot_matching<X>(pivots: &[X], low: &X, high: &X) -> u32 where X: Scalar {
+  let mut matching: u32 = 0;
+  if low <= pivots.first().unwrap() {
+    matching |= 1<<0;
+  }
+  let ranges = pivots.iter().zip(pivots.iter().skip(1));
+  for (i,(start,end)) in ranges.enumerate() {
+    if intersect_iv(start, end, low, high) {
+      matching |= 1<<i;
+      matching |= 1<<(i+1);
+    }
+  }
+  if high >= pivots.last().unwrap() {
+    matching |= 1<<(pivots.len()-1);
+  }
+  matching
+}
+
+// whether a record with coordinate `c` matches the range `low..=high` of a query in `mode`
+fn match_coord<X>(c: &Coord<X>, low: &X, high: &X, mode: QueryMode) -> bool where X: Scalar {
+  match (mode,c) {
+    (QueryMode::Intersects,_) => intersect_coord(c, low, high),
+    (QueryMode::Within,Coord::Scalar(x)) => low <= x && x <= high,
+    (QueryMode::Within,Coord::Interval(x,y)) => low <= x && y <= high,
+    (QueryMode::Contains,Coord::Scalar(x)) => low == x && x == high,
+    (QueryMode::Contains,Coord::Interval(x,y)) => x <= low && high <= y,
+  }
+}
+
+// whether a tree with bounds `c` may hold records that match the range `low..=high` in `mode`
+fn match_bounds<X>(c: &Coord<X>, low: &X, high: &X, mode: QueryMode) -> bool where X: Scalar {
+  match mode {
+    QueryMode::Contains => match_coord(c, low, high, mode),
+    _ => intersect_coord(c, low, high),
+  }
+}
+
+// whether a node with records between `min` and `max`, unbounded where `None`, may hold records
+// that match the range `low..=high` in `mode`
+fn match_range<X>(min: Option<&X>, max: Option<&X>, low: &X, high: &X, mode: QueryMode) -> bool
+where X: Scalar {
+  match mode {
+    QueryMode::Intersects | QueryMode::Within => {
+      min.into_iter().all(|m| m <= high) && max.into_iter().all(|m| low <= m)
+    },
+    QueryMode::Contains => {
+      min.into_iter().all(|m| m <= low) && max.into_iter().all(|m| high <= m)
+    },
+  }
+}
+
+// whether an intersection node for `bitfield` may hold records that match the range `low..=high`
+// in `mode`. the records and refs of the node touch the pivots in its bitfield and no others, but
+// the records beneath a ref need not touch any pivot, so only the pivots on either side bound them
+fn match_intersection<X>(pivots: &[X], bitfield: u32, low: &X, high: &X, mode: QueryMode) -> bool
+where X: Scalar {
+  let lo = bitfield.trailing_zeros() as usize;
+  let hi = 31 - bitfield.leading_zeros() as usize;
+  match mode {
+    QueryMode::Intersects | QueryMode::Within => true,
+    QueryMode::Contains => {
+      match_range(lo.checked_sub(1).map(|j| &pivots[j]), pivots.get(hi+1), low, high, mode)
+    },
+  }
+}
+
 fn coord_cmp<X>(x: &Coord<X>, y: &Coord<X>) -> Option<std::cmp::Ordering> where X: Scalar {
   match (x,y) {
     (Coord::Scalar(a),Coord::Scalar(b)) => a.partial_cmp(b),
@@ -978,6 +1417,20 @@ fn coord_cmp<X>(x: &Coord<X>, y: &Coord<X>) -> Option<std::cmp::Ordering> where
   }
 }
 
+fn coord_start<X>(x: &Coord<X>) -> X where X: Scalar {
+  match x {
+    Coord::Scalar(a) => a.clone(),
+    Coord::Interval(a,_) => a.clone(),
+  }
+}
+
+fn coord_end<X>(x: &Coord<X>) -> X where X: Scalar {
+  match x {
+    Coord::Scalar(a) => a.clone(),
+    Coord::Interval(_,a) => a.clone(),
+  }
+}
+
 fn coord_min<X>(x: &Coord<X>, r: &X) -> X where X: Scalar {
   match x {
     Coord::Scalar(a) => cmp_min(a,r),
@@ -1,6 +1,8 @@
 use lru::{LruCache as LRU};
-use crate::{Tree,TreeId,tree,Error,Point,Value,Storage,RA,SetupFields,EyrosErrorKind};
-use std::collections::{HashMap,HashSet};
+use crate::{Tree,TreeId,tree,Error,Point,Value,Storage,RA,SetupFields,EyrosErrorKind,journal::Journal,
+  checksum::{self,Trailer},TREE_VERSION,checkpoint::{self,Checkpoints},store};
+use std::collections::{BTreeMap,HashMap,HashSet};
+use std::sync::atomic::{AtomicBool,Ordering};
 use async_std::{sync::{Arc,Mutex,RwLock}};
 #[cfg(not(feature="wasm"))] use async_std::task::spawn;
 #[cfg(feature="wasm")] use async_std::task::{spawn_local as spawn};
@@ -12,9 +14,64 @@ pub struct TreeFile<S,T,P,V> where T: Tree<P,V>, P: Point, V: Value, S: RA {
   storage: Arc<Mutex<Box<dyn Storage<S>>>>,
   updated: Arc<RwLock<HashMap<TreeId,Arc<Mutex<T>>>>>,
   removed: Arc<RwLock<HashSet<TreeId>>>,
+  pins: Arc<std::sync::Mutex<Pins>>,
+  retained: Arc<RwLock<RetainedTrees<T>>>,
+  deferred: Arc<RwLock<HashSet<TreeId>>>,
+  checkpoints: Arc<RwLock<Vec<CheckpointTrees>>>,
+  // whether tree files may lack a checksum trailer, as written before trailers were added
+  unchecked: Arc<AtomicBool>,
+  at: Option<u64>,
   _marker: std::marker::PhantomData<(P,V)>,
 }
 
+// versions of each tree with the generation they were replaced or removed in, oldest first
+type RetainedTrees<T> = HashMap<TreeId,Vec<(u64,Retained<T>)>>;
+
+// (checkpoint sequence number, ids of the trees it pins)
+type CheckpointTrees = (u64,HashSet<TreeId>);
+
+// a version of a tree replaced or removed while a snapshot that can read it was pinned
+enum Retained<T> {
+  Tree(Arc<Mutex<T>>),
+  // the tree file on storage, which is not removed while this version is retained
+  File,
+}
+
+#[derive(Default)]
+struct Pins {
+  generation: u64,
+  // generation => (number of snapshots, next_tree when the snapshot was taken)
+  pinned: BTreeMap<u64,(usize,TreeId)>,
+}
+
+impl Pins {
+  // generation to retain the current version of `id` until, if any snapshot can read it
+  fn retain(&self, id: &TreeId) -> Option<u64> {
+    match self.pinned.values().any(|(_,next_tree)| id < next_tree) {
+      true => Some(self.generation),
+      false => None,
+    }
+  }
+}
+
+/// Keeps the trees readable from a snapshot from being removed while the snapshot is alive.
+pub struct TreePin {
+  pins: Arc<std::sync::Mutex<Pins>>,
+  generation: u64,
+}
+
+impl Drop for TreePin {
+  fn drop(&mut self) {
+    let mut pins = self.pins.lock().unwrap();
+    if let Some((count,_)) = pins.pinned.get_mut(&self.generation) {
+      *count -= 1;
+      if *count == 0 {
+        pins.pinned.remove(&self.generation);
+      }
+    }
+  }
+}
+
 impl<S,T,P,V> Clone for TreeFile<S,T,P,V> where T: Tree<P,V>, P: Point, V: Value, S: RA {
   fn clone(&self) -> Self {
     Self {
@@ -23,6 +80,12 @@ impl<S,T,P,V> Clone for TreeFile<S,T,P,V> where T: Tree<P,V>, P: Point, V: Value
       storage: self.storage.clone(),
       updated: self.updated.clone(),
       removed: self.removed.clone(),
+      pins: self.pins.clone(),
+      retained: self.retained.clone(),
+      deferred: self.deferred.clone(),
+      checkpoints: self.checkpoints.clone(),
+      unchecked: self.unchecked.clone(),
+      at: self.at,
       _marker: std::marker::PhantomData,
     }
   }
@@ -37,10 +100,50 @@ impl<S,T,P,V> TreeFile<S,T,P,V> where T: Tree<P,V>, P: Point, V: Value, S: RA {
       storage,
       updated: Arc::new(RwLock::new(HashMap::new())),
       removed: Arc::new(RwLock::new(HashSet::new())),
+      pins: Arc::new(std::sync::Mutex::new(Pins::default())),
+      retained: Arc::new(RwLock::new(HashMap::new())),
+      deferred: Arc::new(RwLock::new(HashSet::new())),
+      checkpoints: Arc::new(RwLock::new(vec![])),
+      unchecked: Arc::new(AtomicBool::new(false)),
+      at: None,
       _marker: std::marker::PhantomData,
     }
   }
+  /// Pin the current version of every tree with an id below `next_tree`. The returned view reads
+  /// those versions until the `TreePin` is dropped, even after they are replaced or removed.
+  /// Call this while holding the meta lock so the pinned trees match the roots.
+  pub fn pin(&self, next_tree: TreeId) -> (Self,TreePin) {
+    let mut pins = self.pins.lock().unwrap();
+    let generation = pins.generation;
+    pins.generation += 1;
+    let entry = pins.pinned.entry(generation).or_insert((0,next_tree));
+    entry.0 += 1;
+    let mut view = self.clone();
+    view.at = Some(generation);
+    (view, TreePin { pins: Arc::clone(&self.pins), generation })
+  }
   pub async fn get(&self, id: &TreeId) -> Result<Arc<Mutex<T>>,Error> {
+    let generation = match self.at {
+      None => return self.get_current(id).await,
+      Some(generation) => generation,
+    };
+    // trees are only replaced or removed while holding the retained lock for writing, so the
+    // current version can't be replaced between looking for a retained version and reading it
+    let retained = self.retained.read().await;
+    match retained.get(id).and_then(|vs| vs.iter().find(|(until,_)| *until > generation)) {
+      None => self.get_current(id).await,
+      Some((_,Retained::Tree(t))) => Ok(Arc::clone(t)),
+      Some((_,Retained::File)) => {
+        self.fields.log(&format!["get tree id={}: retained file", id]).await?;
+        match self.read(id).await? {
+          Some(t) => Ok(Arc::new(Mutex::new(t))),
+          None => EyrosErrorKind::TreeEmpty { id: *id, file: tree::get_file_from_id(id) }.raise(),
+        }
+      },
+    }
+  }
+  // the latest version of a tree
+  async fn get_current(&self, id: &TreeId) -> Result<Arc<Mutex<T>>,Error> {
     if let Some(t) = self.updated.read().await.get(id) {
       self.fields.log(&format![
         "get tree id={} file={}: updated", id, tree::get_file_from_id(id)
@@ -62,79 +165,227 @@ impl<S,T,P,V> TreeFile<S,T,P,V> where T: Tree<P,V>, P: Point, V: Value, S: RA {
         return Ok(Arc::clone(t));
       }
     }
-    {
-      let file = tree::get_file_from_id(id);
-      self.fields.log(&format![
-        "get tree id={} file={}: not cached", id, &file
-      ]).await?;
-      let mut s = self.storage.lock().await.open(&file).await?;
-      let len = s.len().await?;
-      if len == 0 {
-        return EyrosErrorKind::TreeEmpty { id: *id, file }.raise();
+    self.fields.log(&format![
+      "get tree id={} file={}: not cached", id, tree::get_file_from_id(id)
+    ]).await?;
+    match self.read(id).await? {
+      Some(t) => {
+        let t = Arc::new(Mutex::new(t));
+        self.cache.lock().await.put(*id, Arc::clone(&t));
+        Ok(t)
+      },
+      None => EyrosErrorKind::TreeEmpty { id: *id, file: tree::get_file_from_id(id) }.raise(),
+    }
+  }
+  // read a tree from its file, or None if the file is empty
+  async fn read(&self, id: &TreeId) -> Result<Option<T>,Error> {
+    let file = tree::get_file_from_id(id);
+    let bytes = self.read_file(&file).await?;
+    if bytes.is_empty() {
+      return Ok(None);
+    }
+    self.fields.log(&format!["read {} bytes from tree id={}", bytes.len(), id]).await?;
+    let unchecked = self.unchecked.load(Ordering::SeqCst);
+    Ok(Some(self.decode(id, file, &bytes, unchecked).await?))
+  }
+  async fn read_file(&self, file: &str) -> Result<Vec<u8>,Error> {
+    store::read_file(&self.storage, file).await
+  }
+  // decode a tree file, which is only read as format version 1 without a trailer when `unchecked`
+  async fn decode(&self, id: &TreeId, file: String, bytes: &[u8], unchecked: bool)
+  -> Result<T,Error> {
+    let (body,version) = match checksum::check_trailer(bytes) {
+      Trailer::Valid(body,version) => (body,version),
+      Trailer::Missing(body) if unchecked => {
+        self.fields.log(&format!["tree id={} has no checksum", id]).await?;
+        (body,1)
+      },
+      Trailer::Missing(_) | Trailer::Mismatch => {
+        return EyrosErrorKind::ChecksumMismatch { id: Some(*id), file }.raise();
+      },
+    };
+    Ok(T::from_bytes_version(body, version)?.1)
+  }
+  /// Read the version of tree `id` that the checkpoint with sequence number `seq` was taken with,
+  /// or `None` if the tree file has not been overwritten or removed since then.
+  pub async fn read_copy(&self, seq: u64, id: &TreeId) -> Result<Option<T>,Error> {
+    let file = checkpoint::get_copy_file_from_id(seq, id);
+    let bytes = self.read_file(&file).await?;
+    if bytes.is_empty() {
+      return Ok(None);
+    }
+    self.fields.log(&format!["read {} bytes from copy of tree id={}", bytes.len(), id]).await?;
+    // copies wrap the tree file with a trailer of their own to detect an interrupted copy, so the
+    // copied file may be from before tree files had trailers
+    match checksum::check_trailer(&bytes) {
+      Trailer::Valid(body,_) => Ok(Some(self.decode(id, file, body, true).await?)),
+      _ => EyrosErrorKind::ChecksumMismatch { id: Some(*id), file }.raise(),
+    }
+  }
+  /// Set whether tree files may lack a checksum trailer, for a database written before tree files
+  /// had trailers that `migrate()` has not upgraded yet.
+  pub fn set_unchecked(&self, unchecked: bool) {
+    self.unchecked.store(unchecked, Ordering::SeqCst);
+  }
+  /// Set the trees pinned by each checkpoint.
+  pub async fn set_checkpoints(&self, checkpoints: &Checkpoints) {
+    *self.checkpoints.write().await = checkpoints.list.iter()
+      .map(|c| (c.seq, c.trees.iter().copied().collect()))
+      .collect();
+  }
+  /// Keep the tree file for `id` when it is scheduled for removal without having been changed, as
+  /// when a removed tree is referenced again by a restored checkpoint.
+  pub async fn revive(&self, id: &TreeId) {
+    self.removed.write().await.remove(id);
+    self.deferred.write().await.remove(id);
+  }
+  // copy the files of trees pinned by a checkpoint before they are overwritten or removed
+  async fn preserve(&self, ids: &[TreeId]) -> Result<(),Error> {
+    let checkpoints = self.checkpoints.read().await;
+    for id in ids.iter() {
+      for (seq,pinned) in checkpoints.iter() {
+        if !pinned.contains(id) { continue }
+        let file = checkpoint::get_copy_file_from_id(*seq, id);
+        if let Trailer::Valid(_,_) = checksum::check_trailer(&self.read_file(&file).await?) {
+          continue;
+        }
+        let mut bytes = self.read_file(&tree::get_file_from_id(id)).await?;
+        if bytes.is_empty() { continue }
+        self.fields.log(&format!["copy tree id={} file={}", id, &file]).await?;
+        checksum::append_trailer(&mut bytes, checkpoint::CHECKPOINTS_VERSION);
+        let mut s = self.storage.lock().await.open(&file).await?;
+        s.write(0, &bytes).await?;
+        s.truncate(bytes.len() as u64).await?;
+        s.sync_all().await?;
       }
-      let bytes = s.read(0, len).await?;
-      self.fields.log(&format!["read {} bytes from tree id={}", len, id]).await?;
-      let t = Arc::new(Mutex::new(T::from_bytes(&bytes)?.1));
-      self.cache.lock().await.put(*id, Arc::clone(&t));
-      Ok(t)
     }
+    Ok(())
+  }
+  // drop retained versions that no pinned snapshot can read
+  async fn prune(&self) {
+    let oldest = self.pins.lock().unwrap().pinned.keys().next().copied();
+    let mut retained = self.retained.write().await;
+    if retained.is_empty() { return }
+    retained.retain(|_,vs| {
+      vs.retain(|(until,_)| oldest.map(|g| *until > g).unwrap_or(false));
+      !vs.is_empty()
+    });
   }
   pub async fn put(&self, id: &TreeId, t: Arc<Mutex<T>>) -> Result<(),Error> {
     self.fields.log(&format!["put tree id={}", id]).await?;
+    self.prune().await;
+    let until = self.pins.lock().unwrap().retain(id);
+    // held until the tree is replaced so pinned views find either this version or the one it
+    // replaces in `retained`
+    let mut retained = self.retained.write().await;
+    if let Some(until) = until {
+      // keep the version this replaces. versions on storage are read before the file is overwritten
+      let is_removed = self.removed.read().await.contains(id);
+      let updated = self.updated.read().await.get(id).cloned();
+      let cached = self.cache.lock().await.get(id).cloned();
+      let prev = match (updated, cached) {
+        (Some(t),_) => Some(t),
+        (None,_) if is_removed => None,
+        (None,Some(t)) => Some(t),
+        (None,None) => self.read(id).await?.map(|t| Arc::new(Mutex::new(t))),
+      };
+      let is_file = matches![retained.get(id).and_then(|vs| vs.last()), Some((_,Retained::File))];
+      let file = match is_file {
+        true => self.read(id).await?.map(|t| Arc::new(Mutex::new(t))),
+        false => None,
+      };
+      let vs = retained.entry(*id).or_insert_with(Vec::new);
+      if let (Some(t),Some((_,last))) = (file, vs.last_mut()) {
+        *last = Retained::Tree(t);
+      }
+      if let Some(t) = prev {
+        vs.push((until, Retained::Tree(t)));
+      }
+    }
     let mut cache = self.cache.lock().await;
     let mut updated = self.updated.write().await;
     let mut removed = self.removed.write().await;
     cache.put(*id, Arc::clone(&t));
     updated.insert(*id, Arc::clone(&t));
     removed.remove(id);
+    self.deferred.write().await.remove(id);
     Ok(())
   }
   pub async fn remove(&self, id: &TreeId) -> Result<(),Error> {
     self.fields.log(&format!["remove tree id={}", id]).await?;
+    self.prune().await;
+    let until = self.pins.lock().unwrap().retain(id);
+    // taken before the other locks, in the same order as get()
+    let mut retained = self.retained.write().await;
     let mut cache = self.cache.lock().await;
     let mut updated = self.updated.write().await;
     let mut removed = self.removed.write().await;
+    if let (Some(until),false) = (until, removed.contains(id)) {
+      // keep the version this removes. trees that were not changed since the last sync are read
+      // from their file, which sync does not remove while it's retained
+      let prev = match updated.get(id) {
+        Some(t) => Retained::Tree(Arc::clone(t)),
+        None => Retained::File,
+      };
+      retained.entry(*id).or_insert_with(Vec::new).push((until, prev));
+    }
     cache.pop(id);
     updated.remove(id);
     removed.insert(*id);
     Ok(())
   }
-  pub async fn sync(&self) -> Result<(),Error> {
+  /// Return the ids of trees scheduled for removal on a sync, including the trees that are not
+  /// removed yet because a snapshot can still read them.
+  pub async fn removed_ids(&self) -> HashSet<TreeId> {
+    let mut ids = self.removed.read().await.clone();
+    ids.extend(self.deferred.read().await.iter());
+    ids
+  }
+  /// Write every updated tree and the serialized `meta` through the journal, then remove trees
+  /// scheduled for removal. Files that a pinned snapshot can still read are removed by a later
+  /// sync. Files of trees pinned by a checkpoint are copied before they are overwritten or removed.
+  pub async fn sync(&self, meta: Vec<u8>, meta_store: &mut S) -> Result<(),Error> {
     self.fields.log("sync begin").await?;
+    self.prune().await;
+    // taken before the other locks, in the same order as get()
+    let retained = self.retained.read().await;
     let mut updated = self.updated.write().await;
     let mut removed = self.removed.write().await;
+    let mut deferred = self.deferred.write().await;
+    let (keep,remove): (HashSet<TreeId>,HashSet<TreeId>) = {
+      let retained = retained;
+      removed.iter().chain(deferred.iter()).partition(|id| {
+        retained.get(id).map(|vs| vs.iter().any(|(_,r)| matches![r, Retained::File]))
+          .unwrap_or(false)
+      })
+    };
     let mut work = vec![];
     for (id,t) in updated.iter() {
-      let file = tree::get_file_from_id(id);
+      let id = *id;
       let tree = Arc::clone(t);
-      let storage = Arc::clone(&self.storage);
-      self.fields.log(&format!["sync tree (updated) id={} file={}", id, &file]).await?;
       work.push(spawn(async move {
-        let bytes = tree.lock().await.to_bytes()?;
-        let mut s = storage.lock().await.open(&file).await?;
-        s.write(0, &bytes).await?;
-        s.sync_all().await?;
-        let res: Result<(),Error> = Ok(());
+        let mut bytes = tree.lock().await.to_bytes()?;
+        checksum::append_trailer(&mut bytes, TREE_VERSION);
+        let res: Result<(TreeId,Vec<u8>),Error> = Ok((id,bytes));
         res
       }));
     }
-    for id in removed.iter() {
-      let file = tree::get_file_from_id(id);
-      let storage = self.storage.clone();
-      self.fields.log(&format!["sync tree (remove) id={} file={}", id, &file]).await?;
-      work.push(spawn(async move {
-        // ignore errors
-        match storage.lock().await.remove(&file).await {
-          Ok(()) => {},
-          Err(_err) => {}
-        }
-        let r: Result<(),Error> = Ok(());
-        r
-      }));
-    }
-    for r in join_all(work).await { r?; }
+    let mut journal = Journal {
+      meta,
+      updated: Vec::with_capacity(work.len()),
+      removed: remove.into_iter().collect(),
+    };
+    for r in join_all(work).await { journal.updated.push(r?); }
+    let ids: Vec<TreeId> = journal.updated.iter().map(|(id,_)| *id)
+      .chain(journal.removed.iter().copied())
+      .collect();
+    self.preserve(&ids).await?;
+    journal.write(&self.storage).await?;
+    journal.apply(&self.fields, &self.storage, meta_store).await?;
+    Journal::clear(&self.storage).await?;
     updated.clear();
     removed.clear();
+    *deferred = keep;
     self.fields.log("sync complete").await?;
     Ok(())
   }
@@ -6,6 +6,9 @@ use core::{fmt::Debug,hash::Hash};
 pub trait Value: Clone+Hash+Debug+Send+Sync+'static+ToBytes+CountBytes+FromBytes {
   type Id: Clone+Hash+Eq+Debug+Send+Sync+'static;
   fn get_id(&self) -> Self::Id;
+  /// Name stored in the database schema to detect opening a database with the wrong value type.
+  /// The default empty name disables the check.
+  fn type_name() -> String { String::new() }
 }
 
 macro_rules! def_value {
@@ -13,6 +16,7 @@ macro_rules! def_value {
     impl Value for $T {
       type Id = $T;
       fn get_id(&self) -> $T { self.clone() }
+      fn type_name() -> String { stringify![$T].to_string() }
     }
   }
 }
@@ -29,4 +33,8 @@ def_value![i64];
 impl<T> Value for Vec<T> where T: Value+Clone+Eq {
   type Id = Vec<T>;
   fn get_id(&self) -> Self { self.clone() }
+  fn type_name() -> String {
+    let name = T::type_name();
+    if name.is_empty() { name } else { format!["Vec<{}>", name] }
+  }
 }
@@ -0,0 +1,149 @@
+use crate::{DB,Tree,TreeRef,TreeId,Point,Value,Error,EyrosError,EyrosErrorKind,RA,tree};
+use futures::future::FutureExt;
+use std::collections::{HashMap,HashSet,VecDeque};
+use std::panic::AssertUnwindSafe;
+
+/// A problem found by `DB::verify()`.
+#[derive(Debug,Clone,PartialEq)]
+pub enum VerifyIssue {
+  /// A referenced tree file is missing or empty.
+  Empty { id: TreeId, file: String },
+  /// A referenced tree file could not be read or decoded.
+  Undecodable { id: TreeId, file: String, error: String },
+  /// A record or tree reference lies outside the bounds of the `TreeRef` that points at the tree
+  /// holding it.
+  OutOfBounds { id: TreeId, file: String, item: String, bounds: String },
+  /// A tree is referenced more than once.
+  DuplicateRef { id: TreeId, file: String },
+  /// The record count of the `TreeRef` that points at a tree differs from the number of records
+  /// found in the tree and every tree beneath it.
+  CountMismatch { id: TreeId, file: String, count: u64, found: u64 },
+  /// A file under `t/` is not referenced by any tree.
+  Orphan { file: String },
+}
+
+/// Results of walking every tree reachable from the database roots.
+#[derive(Debug,Clone,Default)]
+pub struct VerifyReport {
+  /// Number of trees that were loaded.
+  pub trees: usize,
+  /// Number of records found in the loaded trees.
+  pub records: usize,
+  pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+  /// Return whether no issues were found.
+  pub fn is_ok(&self) -> bool {
+    self.issues.is_empty()
+  }
+}
+
+impl<S,T,P,V> DB<S,T,P,V> where S: RA, P: Point, V: Value, T: Tree<P,V> {
+  /// Check the integrity of the database. Every tree reachable from the roots is loaded and its
+  /// records and references are checked against the bounds and record count of the `TreeRef`
+  /// pointing at it.
+  /// Files under `t/` that no tree references are reported as orphans when the storage can list
+  /// its files. Problems are collected into the returned report instead of stopping at the first
+  /// one, so this is safe to run on a damaged database.
+  pub async fn verify(&self) -> Result<VerifyReport,Error> {
+    self.fields.log("verify begin").await?;
+    let mut report = VerifyReport::default();
+    let mut seen: HashSet<TreeId> = HashSet::new();
+    let mut refs: VecDeque<TreeRef<P>> = self.meta.read().await.roots.iter()
+      .filter_map(|r| r.clone())
+      .collect();
+    // (ref, records in the tree, ids of the trees it references) in the order trees were loaded
+    let mut loaded = vec![];
+    while let Some(r) = refs.pop_front() {
+      let file = tree::get_file_from_id(&r.id);
+      if !seen.insert(r.id) {
+        report.issues.push(VerifyIssue::DuplicateRef { id: r.id, file });
+        continue;
+      }
+      // a corrupted file can panic while decoding, so catch that as well as errors
+      let res = AssertUnwindSafe(self.trees.get(&r.id)).catch_unwind().await;
+      let t = match res {
+        Ok(Ok(t)) => t,
+        Ok(Err(e)) => {
+          report.issues.push(if is_empty(&e) {
+            VerifyIssue::Empty { id: r.id, file }
+          } else {
+            VerifyIssue::Undecodable { id: r.id, file, error: e.to_string() }
+          });
+          continue;
+        },
+        Err(_) => {
+          let error = "panic while decoding".to_string();
+          report.issues.push(VerifyIssue::Undecodable { id: r.id, file, error });
+          continue;
+        },
+      };
+      let (records,xrefs) = t.lock().await.list();
+      report.trees += 1;
+      report.records += records.len();
+      for (p,_) in records.iter() {
+        if !r.bounds.contains(p) {
+          report.issues.push(VerifyIssue::OutOfBounds {
+            id: r.id,
+            file: file.clone(),
+            item: format!["{:?}", p],
+            bounds: format!["{:?}", &r.bounds],
+          });
+        }
+      }
+      let children = xrefs.iter().map(|x| x.id).collect::<Vec<TreeId>>();
+      loaded.push((r.clone(),records.len() as u64,children));
+      for x in xrefs {
+        if !r.bounds.contains(&x.bounds) {
+          report.issues.push(VerifyIssue::OutOfBounds {
+            id: r.id,
+            file: file.clone(),
+            item: format!["{:?}", &x],
+            bounds: format!["{:?}", &r.bounds],
+          });
+        }
+        refs.push_back(x);
+      }
+    }
+    // trees are loaded after the trees that reference them, so count them in reverse
+    let mut totals: HashMap<TreeId,u64> = HashMap::new();
+    for (r,records,children) in loaded.iter().rev() {
+      // a tree beneath this one could not be loaded, so its total is unknown
+      let found = match children.iter().map(|id| totals.get(id).copied()).sum::<Option<u64>>() {
+        Some(n) => records + n,
+        None => continue,
+      };
+      totals.insert(r.id, found);
+      match r.count {
+        Some(count) if count != found => {
+          let file = tree::get_file_from_id(&r.id);
+          report.issues.push(VerifyIssue::CountMismatch { id: r.id, file, count, found });
+        },
+        _ => {},
+      }
+    }
+    let removed = self.trees.removed_ids().await;
+    let live: HashSet<String> = seen.iter().chain(removed.iter())
+      .map(tree::get_file_from_id)
+      .collect();
+    let files = self.storage.lock().await.list("t/").await?;
+    for file in files {
+      if !live.contains(&file) {
+        report.issues.push(VerifyIssue::Orphan { file });
+      }
+    }
+    self.fields.log(&format![
+      "verify complete: {} trees, {} records, {} issues",
+      report.trees, report.records, report.issues.len()
+    ]).await?;
+    Ok(report)
+  }
+}
+
+fn is_empty(e: &Error) -> bool {
+  match e.downcast_ref::<EyrosError>() {
+    Some(e) => matches![e.kind(), EyrosErrorKind::TreeEmpty { .. }],
+    None => false,
+  }
+}
@@ -49,6 +49,7 @@ impl Value for V {
     }
     self.data.clone()
   }
+  fn type_name() -> String { "JsValue".to_string() }
 }
 impl ToBytes for V {
   fn to_bytes(&self) -> Result<Vec<u8>,E> {
@@ -207,7 +208,7 @@ macro_rules! def_mix {
               bbox_a.get($I+$n).as_f64().unwrap() as $T
             ),+)
           );
-          let mut db = db_ref.lock().await;
+          let db = db_ref.lock().await;
           if let Some(trace) = o_trace {
             db.query_trace(&bbox, Box::new(trace)).await
               .map_err(|e| Error::new(&format!["{:?}",e]).into())
@@ -227,6 +228,18 @@ macro_rules! def_mix {
           Ok(JsValue::NULL)
         })
       }
+      pub fn gc(&self) -> Promise {
+        let db_ref = Arc::clone(&self.db);
+        future_to_promise(async move {
+          let mut db = db_ref.lock().await;
+          let removed = db.gc().await.map_err(|e| Error::new(&format!["{:?}",e]))?;
+          let files = Array::new();
+          for file in removed.iter() {
+            files.push(&file.into());
+          }
+          Ok(files.into())
+        })
+      }
     }
     #[wasm_bindgen]
     pub async fn $open(opts: JsValue) -> Result<$C,Error> {
@@ -240,6 +253,7 @@ macro_rules! def_mix {
         Ok(remove) => remove,
         Err(_) => { return Err(Error::new("must provide opts.remove function")) },
       };
+      let list_fn: Option<Function> = get(&opts,&"list".into()).map_err(errf)?.dyn_into().ok();
       let mut setup = Setup::from_storage(Box::new(JsStorage {
         storage_rpc: {
           let (sender,receiver): (
@@ -272,6 +286,28 @@ macro_rules! def_mix {
           });
           sender
         },
+        list_rpc: {
+          let (sender,receiver): (
+            Sender<(String,Sender<Result<Vec<String>,E>>)>,
+            Receiver<(String,Sender<Result<Vec<String>,E>>)>,
+          ) = unbounded();
+          spawn_local(async move {
+            while let Ok((prefix,s)) = receiver.recv().await {
+              s.send(match &list_fn {
+                // without opts.list, files can't be enumerated
+                None => Ok(vec![]),
+                Some(f) => match f.call1(&JsValue::NULL, &prefix.into()) {
+                  Err(e) => JsError::wrap(Err(e.into())),
+                  Ok(files) if Array::is_array(&files) => {
+                    Ok(Array::from(&files).iter().filter_map(|x| x.as_string()).collect())
+                  },
+                  Ok(_) => Ok(vec![]),
+                },
+              }).await.unwrap();
+            }
+          });
+          sender
+        },
       }));
       match get(&opts,&"branchFactor".into()).map_err(errf)?.as_f64() {
         Some(x) => { setup = setup.branch_factor(x as usize); },
@@ -297,6 +333,10 @@ macro_rules! def_mix {
         Some(x) => { setup = setup.rebuild_depth(x as usize); },
         _ => {},
       };
+      match get(&opts,&"readOnly".into()).map_err(errf)?.as_bool() {
+        Some(x) => { setup = setup.read_only(x); },
+        _ => {},
+      };
       match get(&opts,&"debug".into()).map_err(errf)?.dyn_into::<Function>() {
         Ok(f) => {
           let (sender,receiver): (Sender<String>, Receiver<String>) = unbounded();
@@ -8,6 +8,7 @@ use async_std::channel::{unbounded,Sender,Receiver};
 pub struct JsStorage {
   pub storage_rpc: Sender<(String,Sender<Result<JsRandomAccess,Error>>)>,
   pub remove_rpc: Sender<(String,Sender<Result<(),Error>>)>,
+  pub list_rpc: Sender<(String,Sender<Result<Vec<String>,Error>>)>,
 }
 
 #[async_trait::async_trait]
@@ -22,6 +23,11 @@ impl Storage<JsRandomAccess> for JsStorage {
     self.remove_rpc.send((name.to_string(),sender)).await?;
     receiver.recv().await?
   }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    let (sender, receiver) = unbounded();
+    self.list_rpc.send((prefix.to_string(),sender)).await?;
+    receiver.recv().await?
+  }
 }
 
 pub enum JRequest {
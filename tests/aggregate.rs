@@ -0,0 +1,80 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,Fold,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn aggregate() -> Result<(),Error> {
+  let inserts = rows(3000);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .ext_records(100)
+    .build().await?;
+  db.batch(&inserts[0..2000]).await?;
+  db.batch(&inserts[2000..3000]).await?;
+  let deletes: Vec<Row<P,V>> = inserts[0..300].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  db.batch(&deletes).await?;
+  db.sync().await?;
+
+  let bboxes = [
+    ((-1.0,-1.0,0.0),(1.0,1.0,1000.0)),
+    ((-0.5,-0.5,0.0),(0.5,0.5,500.0)),
+    ((0.2,-0.9,100.0),(0.3,0.9,900.0)),
+    ((2.0,2.0,0.0),(3.0,3.0,1000.0)),
+  ];
+  for bbox in bboxes.iter() {
+    let mut stream = db.query(bbox).await?;
+    let mut count = 0;
+    let mut sum = 0u64;
+    while let Some(result) = stream.next().await {
+      let (_,v) = result?;
+      count += 1;
+      sum += v as u64;
+    }
+    assert_eq![db.count(bbox).await?, count, "count for bbox={:?}", bbox];
+    let fold_sum = db.aggregate(bbox, Fold::new(0u64, |acc, _p, v| {
+      *acc += *v as u64;
+    })).await?;
+    assert_eq![fold_sum, sum, "sum of values for bbox={:?}", bbox];
+  }
+
+  // counts per cell of a heatmap add up to the count of the whole bbox
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let cells = db.aggregate(&bbox, Fold::new(vec![0u64;16], |cells, p: &P, _v| {
+    let x = match p.0 { Coord::Scalar(x) => x, Coord::Interval(x,_) => x };
+    let y = match p.1 { Coord::Scalar(y) => y, Coord::Interval(y,_) => y };
+    let i = (((x+1.0)*2.0) as usize).min(3) + 4*(((y+1.0)*2.0) as usize).min(3);
+    cells[i] += 1;
+  })).await?;
+  assert_eq![cells.iter().sum::<u64>(), 2700, "heatmap cells"];
+  assert![cells.iter().all(|c| *c > 0), "every heatmap cell has records"];
+
+  let snap = db.snapshot().await?;
+  db.batch(&inserts[0..300]).await?;
+  assert_eq![snap.aggregate(&bbox, eyros::Count::new()).await?, 2700, "count from a snapshot"];
+  assert_eq![db.count(&bbox).await?, 3000, "count after inserting"];
+  Ok(())
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
@@ -0,0 +1,137 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,Tree3,Error,EyrosError,EyrosErrorKind};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+use std::cmp::Ordering;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn checkpoint() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let inserts = rows(4000);
+  let deletes = to_deletes(&inserts[0..500]);
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .ext_records(100)
+    .build().await?;
+  db.batch(&inserts[0..2000]).await?;
+  db.checkpoint("a").await?;
+  assert_eq![db.list_checkpoints().await?, vec!["a".to_string()], "list after checkpoint"];
+
+  // overwrite and remove the trees of checkpoint a
+  db.batch(&inserts[2000..3000]).await?;
+  db.batch(&deletes).await?;
+  db.optimize(2).await?;
+  db.sync().await?;
+  assert![!store.list("c/").await?.is_empty(), "trees of the checkpoint are copied"];
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[500..3000]),
+    "query after writes"];
+  db.checkpoint("b").await?;
+
+  // pending changes are discarded
+  db.batch(&inserts[3000..4000]).await?;
+  db.restore("a").await?;
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[0..2000]),
+    "query after restoring a"];
+  assert![db.verify().await?.is_ok(), "verify after restoring a"];
+
+  drop(db);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .ext_records(100)
+    .build().await?;
+  assert_eq![db.list_checkpoints().await?, vec!["a".to_string(),"b".to_string()],
+    "list after reopening"];
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[0..2000]),
+    "query after reopening"];
+  db.restore("b").await?;
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[500..3000]),
+    "query after restoring b"];
+  db.restore("a").await?;
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[0..2000]),
+    "query after restoring a again"];
+  db.restore("b").await?;
+
+  // replacing a checkpoint pins the current trees
+  db.batch(&inserts[3000..4000]).await?;
+  db.checkpoint("a").await?;
+  db.batch(&to_deletes(&inserts[500..1000])).await?;
+  db.sync().await?;
+  db.restore("a").await?;
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[500..4000]),
+    "query after restoring the replaced checkpoint"];
+
+  db.drop_checkpoint("a").await?;
+  db.drop_checkpoint("b").await?;
+  assert_eq![db.list_checkpoints().await?, Vec::<String>::new(), "list after dropping"];
+  assert_eq![store.list("c/").await?, Vec::<String>::new(), "copies are removed"];
+  match_kind(db.restore("a").await, "restore a dropped checkpoint");
+  match_kind(db.drop_checkpoint("a").await, "drop a dropped checkpoint");
+  assert_eq![db.gc().await?, Vec::<String>::new(), "no files left behind"];
+  assert![db.verify().await?.is_ok(), "verify after dropping"];
+  assert_eq![sorted(collect(db.query(&bbox).await?).await?), expected(&inserts[500..4000]),
+    "query after dropping"];
+  Ok(())
+}
+
+fn match_kind(r: Result<(),Error>, msg: &str) {
+  match r.err().as_ref().and_then(|e| e.downcast_ref::<EyrosError>()).map(|e| e.kind()) {
+    Some(EyrosErrorKind::CheckpointMissing { .. }) => {},
+    kind => panic!["{}: expected a missing checkpoint error, got {:?}", msg, kind],
+  }
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<(P,V)>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  Ok(results)
+}
+
+fn to_deletes(rows: &[Row<P,V>]) -> Vec<Row<P,V>> {
+  rows.iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect()
+}
+
+fn expected(rows: &[Row<P,V>]) -> Vec<(P,V)> {
+  sorted(rows.iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect())
+}
+
+fn sorted(mut results: Vec<(P,V)>) -> Vec<(P,V)> {
+  results.sort_unstable_by(cmp);
+  results
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
@@ -0,0 +1,109 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Meta,Schema,Tree3,TreeId,Error,
+  FromBytesVersion,ToBytesVersion,TREE_VERSION,UNCHECKED_VERSION};
+use desert::ToBytes;
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn checksum() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let size = 500;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let (root,meta): (TreeId,Meta<P>) = {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone())).build().await?;
+    db.batch(&inserts).await?;
+    db.sync().await?;
+    let meta = db.meta.read().await;
+    (meta.roots.iter().flatten().next().unwrap().id, meta.clone())
+  };
+  let file = eyros::tree::get_file_from_id(&root);
+  let tree_bytes = read_all(&mut store, &file).await?;
+  let meta_bytes = read_all(&mut store, "meta").await?;
+
+  // a tree file missing its last few bytes is detected
+  write_all(&mut store, &file, &tree_bytes[0..tree_bytes.len()-3]).await?;
+  let err = count(&mut store).await.expect_err("query with a truncated tree fails");
+  assert![err.to_string().contains("checksum mismatch"), "unexpected error: {}", err];
+
+  // a tree file without its trailer is detected in a database written with trailers
+  let body = &tree_bytes[0..tree_bytes.len()-9];
+  let v1 = T::from_bytes_version(body, TREE_VERSION)?.1.to_bytes_version(1)?;
+  write_all(&mut store, &file, &v1).await?;
+  let err = count(&mut store).await.expect_err("query with a tree without a trailer fails");
+  assert![err.to_string().contains("checksum mismatch"), "unexpected error: {}", err];
+
+  // but is read as version 1 without a check in a database from before trees had trailers
+  let legacy: Meta<P> = Meta {
+    schema: meta.schema.clone().map(|s| Schema { version: UNCHECKED_VERSION, ..s }),
+    roots: meta.roots.clone(),
+    next_tree: meta.next_tree,
+  };
+  write_all(&mut store, "meta", &legacy.to_bytes()?).await?;
+  assert_eq![count(&mut store).await?, size, "tree without a checksum in a legacy database"];
+  write_all(&mut store, "meta", &meta_bytes).await?;
+
+  // a flipped bit in a tree file is detected
+  let mut bad = tree_bytes.clone();
+  let i = bad.len()/2;
+  bad[i] ^= 0x01;
+  write_all(&mut store, &file, &bad).await?;
+  let err = count(&mut store).await.expect_err("query with a corrupted tree fails");
+  assert![err.to_string().contains("checksum mismatch"), "unexpected error: {}", err];
+  write_all(&mut store, &file, &tree_bytes).await?;
+  assert_eq![count(&mut store).await?, size, "restored tree"];
+
+  // a flipped bit in the meta is detected
+  let mut bad = meta_bytes.clone();
+  bad[1] ^= 0x01;
+  write_all(&mut store, "meta", &bad).await?;
+  let err = count(&mut store).await.expect_err("open with a corrupted meta fails");
+  assert![err.to_string().contains("checksum mismatch"), "unexpected error: {}", err];
+
+  // a meta without its trailer is read without a check
+  write_all(&mut store, "meta", &meta_bytes[0..meta_bytes.len()-9]).await?;
+  assert_eq![count(&mut store).await?, size, "meta without a checksum"];
+  Ok(())
+}
+
+async fn count(store: &mut MemoryStore) -> Result<usize,Error> {
+  let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  Ok(n)
+}
+
+async fn read_all(store: &mut MemoryStore, name: &str) -> Result<Vec<u8>,Error> {
+  let mut f = store.open(name).await?;
+  let len = f.len().await?;
+  f.read(0, len).await
+}
+
+async fn write_all(store: &mut MemoryStore, name: &str, data: &[u8]) -> Result<(),Error> {
+  let mut f = store.open(name).await?;
+  f.write(0, data).await?;
+  f.truncate(data.len() as u64).await?;
+  Ok(())
+}
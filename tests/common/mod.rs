@@ -0,0 +1,153 @@
+// Storage shared by the suites that run against either backend. They use files in a temporary
+// directory by default, or a `MemoryStore` for each directory with `EYROS_TEST_STORAGE=memory`.
+#![allow(dead_code)]
+use eyros::{DB,Setup,Storage,StorageLock,FileStore,MemoryStore,MemoryFile,Tree2,Tree3,Tree4,
+  Coord,Scalar,Value,Error};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+use std::path::{Path,PathBuf};
+use std::sync::Mutex;
+
+pub const STORAGE_VAR: &str = "EYROS_TEST_STORAGE";
+
+// memory stores by directory, so a suite that opens the same directory again sees its files
+static STORES: Mutex<Option<HashMap<PathBuf,MemoryStore>>> = Mutex::new(None);
+
+pub fn in_memory() -> bool {
+  matches![std::env::var(STORAGE_VAR).as_deref(), Ok("memory")]
+}
+
+pub enum TestFile {
+  Disk(RandomAccessDisk),
+  Memory(MemoryFile),
+}
+
+#[async_trait::async_trait]
+impl RandomAccess for TestFile {
+  type Error = Error;
+  async fn write(&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    match self {
+      TestFile::Disk(f) => f.write(offset, data).await,
+      TestFile::Memory(f) => f.write(offset, data).await,
+    }
+  }
+  async fn read(&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    match self {
+      TestFile::Disk(f) => f.read(offset, length).await,
+      TestFile::Memory(f) => f.read(offset, length).await,
+    }
+  }
+  async fn read_to_writer(&mut self, offset: u64, length: u64,
+  buf: &mut (impl async_std::io::Write + Send)) -> Result<(),Error> {
+    match self {
+      TestFile::Disk(f) => f.read_to_writer(offset, length, buf).await,
+      TestFile::Memory(f) => f.read_to_writer(offset, length, buf).await,
+    }
+  }
+  async fn del(&mut self, offset: u64, length: u64) -> Result<(),Error> {
+    match self {
+      TestFile::Disk(f) => f.del(offset, length).await,
+      TestFile::Memory(f) => f.del(offset, length).await,
+    }
+  }
+  async fn truncate(&mut self, length: u64) -> Result<(),Error> {
+    match self {
+      TestFile::Disk(f) => f.truncate(length).await,
+      TestFile::Memory(f) => f.truncate(length).await,
+    }
+  }
+  async fn len(&self) -> Result<u64,Error> {
+    match self {
+      TestFile::Disk(f) => f.len().await,
+      TestFile::Memory(f) => f.len().await,
+    }
+  }
+  async fn is_empty(&mut self) -> Result<bool,Error> {
+    match self {
+      TestFile::Disk(f) => f.is_empty().await,
+      TestFile::Memory(f) => f.is_empty().await,
+    }
+  }
+  async fn sync_all(&mut self) -> Result<(),Error> {
+    match self {
+      TestFile::Disk(f) => f.sync_all().await,
+      TestFile::Memory(f) => f.sync_all().await,
+    }
+  }
+}
+
+pub enum TestStore {
+  Disk(FileStore),
+  Memory(MemoryStore),
+}
+
+impl TestStore {
+  pub fn new(path: &Path) -> Self {
+    if !in_memory() {
+      return TestStore::Disk(FileStore::new(path));
+    }
+    let mut stores = STORES.lock().unwrap();
+    let store = stores.get_or_insert_with(HashMap::new)
+      .entry(path.to_path_buf())
+      .or_default();
+    TestStore::Memory(store.clone())
+  }
+}
+
+#[async_trait::async_trait]
+impl Storage<TestFile> for TestStore {
+  async fn open(&mut self, name: &str) -> Result<TestFile,Error> {
+    match self {
+      TestStore::Disk(s) => Ok(TestFile::Disk(s.open(name).await?)),
+      TestStore::Memory(s) => Ok(TestFile::Memory(s.open(name).await?)),
+    }
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    match self {
+      TestStore::Disk(s) => s.remove(name).await,
+      TestStore::Memory(s) => s.remove(name).await,
+    }
+  }
+  async fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>,Error> {
+    match self {
+      TestStore::Disk(s) => s.read(name).await,
+      TestStore::Memory(s) => s.read(name).await,
+    }
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    match self {
+      TestStore::Disk(s) => s.list(prefix).await,
+      TestStore::Memory(s) => s.list(prefix).await,
+    }
+  }
+  async fn lock(&mut self) -> Result<StorageLock,Error> {
+    match self {
+      TestStore::Disk(s) => s.lock().await,
+      TestStore::Memory(s) => s.lock().await,
+    }
+  }
+}
+
+/// Set up a database for the directory `path` on the backend chosen by `EYROS_TEST_STORAGE`.
+pub fn setup(path: &Path) -> Setup<TestFile> {
+  Setup::from_storage(Box::new(TestStore::new(path)))
+}
+
+pub async fn open2<X,Y,V>(path: &Path)
+-> Result<DB<TestFile,Tree2<X,Y,V>,(Coord<X>,Coord<Y>),V>,Error>
+where X: Scalar, Y: Scalar, V: Value {
+  setup(path).build().await
+}
+
+pub async fn open3<X,Y,Z,V>(path: &Path)
+-> Result<DB<TestFile,Tree3<X,Y,Z,V>,(Coord<X>,Coord<Y>,Coord<Z>),V>,Error>
+where X: Scalar, Y: Scalar, Z: Scalar, V: Value {
+  setup(path).build().await
+}
+
+pub async fn open4<X,Y,Z,W,V>(path: &Path)
+-> Result<DB<TestFile,Tree4<X,Y,Z,W,V>,(Coord<X>,Coord<Y>,Coord<Z>,Coord<W>),V>,Error>
+where X: Scalar, Y: Scalar, Z: Scalar, W: Scalar, V: Value {
+  setup(path).build().await
+}
@@ -0,0 +1,142 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,MemoryFile,Tree3,Error,BatchOptions};
+use random::{Source,default as rand};
+use async_std::{prelude::*,task::spawn};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn concurrent_queries() -> Result<(),Error> {
+  let inserts = rows(4000);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .ext_records(100)
+    .build().await?;
+  for batch in inserts[0..2000].chunks(500) {
+    db.batch(batch).await?;
+  }
+  db.sync().await?;
+  {
+    // queries only borrow the db, so several can be in flight at once
+    let r = &db;
+    let (a,b) = futures::future::join(count(r), count(r)).await;
+    assert_eq![a?, 2000, "first concurrent query"];
+    assert_eq![b?, 2000, "second concurrent query"];
+  }
+
+  // query from clones while another clone writes
+  let mut readers = vec![];
+  for _ in 0..4 {
+    let r = db.clone();
+    readers.push(spawn(async move {
+      let mut counts = vec![];
+      for _ in 0..20 {
+        counts.push(count(&r).await?);
+        async_std::task::yield_now().await;
+      }
+      let res: Result<Vec<usize>,Error> = Ok(counts);
+      res
+    }));
+  }
+  let mut w = db.clone();
+  let inserted = inserts[2000..4000].to_vec();
+  let writer = spawn(async move {
+    for batch in inserted.chunks(500) {
+      w.batch(batch).await?;
+      async_std::task::yield_now().await;
+    }
+    w.sync().await
+  });
+  for reader in readers {
+    for c in reader.await? {
+      assert![(2000..=4000).contains(&c) && c % 500 == 0, "query saw a whole number of batches: {}", c];
+    }
+  }
+  writer.await?;
+  assert_eq![count(&db).await?, 4000, "every record after the writer finishes"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn concurrent_deletes() -> Result<(),Error> {
+  let inserts = rows(4000);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .ext_records(100)
+    .build().await?;
+  for batch in inserts.chunks(500) {
+    db.batch(batch).await?;
+  }
+  db.sync().await?;
+  let deletes: Vec<Row<P,V>> = inserts.iter().map(|row| match row {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+
+  // a batch that fails partway through leaves every tree as it was
+  let missing = vec![deletes[0].clone(), Row::Delete(match &deletes[0] {
+    Row::Delete(p,_) => p.clone(),
+    _ => panic!["unexpected row type"],
+  }, 0)];
+  let opts = BatchOptions::new().error_if_missing(true);
+  assert![db.batch_with_options(&missing, &opts).await.is_err(), "delete a missing record"];
+  assert_eq![count(&db).await?, 4000, "records after a failed batch"];
+
+  // query from clones while another clone deletes
+  let mut readers = vec![];
+  for _ in 0..4 {
+    let r = db.clone();
+    readers.push(spawn(async move {
+      let mut counts = vec![];
+      for _ in 0..20 {
+        counts.push(count(&r).await?);
+        async_std::task::yield_now().await;
+      }
+      let res: Result<Vec<usize>,Error> = Ok(counts);
+      res
+    }));
+  }
+  let mut w = db.clone();
+  let writer = spawn(async move {
+    for batch in deletes[0..2000].chunks(500) {
+      w.batch(batch).await?;
+      async_std::task::yield_now().await;
+    }
+    w.sync().await
+  });
+  for reader in readers {
+    for c in reader.await? {
+      assert![(2000..=4000).contains(&c) && c % 500 == 0, "query saw a whole number of batches: {}", c];
+    }
+  }
+  writer.await?;
+  assert_eq![count(&db).await?, 2000, "remaining records after the writer finishes"];
+  Ok(())
+}
+
+async fn count(db: &DB<MemoryFile,T,P,V>) -> Result<usize,Error> {
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  Ok(n)
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
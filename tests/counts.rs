@@ -0,0 +1,91 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,MemoryFile,Tree3,Error};
+use random::{Source,default as rand};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn counts() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let inserts = rows(4000);
+  let deletes: Vec<Row<P,V>> = inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .max_records(200)
+    .ext_records(50)
+    .build().await?;
+  assert_eq![db.len().await?, 0, "empty"];
+  assert![db.is_empty().await?, "is_empty"];
+
+  db.batch(&inserts[0..2000]).await?;
+  check(&db, 2000, "first batch").await?;
+  db.batch(&inserts[2000..3000]).await?;
+  check(&db, 3000, "second batch").await?;
+
+  // deletes without inserts rebuild trees in place
+  db.batch(&deletes[0..100]).await?;
+  check(&db, 2900, "deletes").await?;
+  db.batch(&deletes[2500..2510]).await?;
+  check(&db, 2890, "deletes from the second root").await?;
+
+  // deletes with inserts are merged into a new root
+  let mut mixed = deletes[100..400].to_vec();
+  mixed.extend_from_slice(&inserts[3000..4000]);
+  db.batch(&mixed).await?;
+  check(&db, 3590, "deletes with inserts").await?;
+
+  db.optimize(3).await?;
+  check(&db, 3590, "optimize").await?;
+  db.sync().await?;
+
+  let snap = db.snapshot().await?;
+  db.batch(&deletes[400..500]).await?;
+  assert_eq![snap.len().await?, 3590, "len of a snapshot"];
+  check(&db, 3490, "deletes after snapshot").await?;
+  db.sync().await?;
+  drop(snap);
+  drop(db);
+
+  // counts are read back from the meta and tree files
+  let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  check(&db, 3490, "reopen").await?;
+  assert_eq![
+    db.count(&((-0.5,-0.5,0.0),(0.5,0.5,500.0))).await?,
+    db.aggregate(&((-0.5,-0.5,0.0),(0.5,0.5,500.0)), eyros::Fold::new(0u64, |n, _p, _v| {
+      *n += 1;
+    })).await?,
+    "count of part of the database"
+  ];
+  assert_eq![db.count(&bbox).await?, 3490, "count of the whole database"];
+  Ok(())
+}
+
+async fn check(db: &DB<MemoryFile,T,P,V>, len: u64, msg: &str) -> Result<(),Error> {
+  assert_eq![db.len().await?, len, "len after {}", msg];
+  let report = db.verify().await?;
+  assert![report.is_ok(), "record counts after {}: {:?}", msg, report.issues];
+  assert_eq![report.records as u64, len, "records found after {}", msg];
+  Ok(())
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
@@ -0,0 +1,82 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::{prelude::*,sync::{Arc,Mutex}};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+// storage where removing files fails while `fail` is set, leaving orphaned tree files behind
+#[derive(Clone)]
+struct NoRemoveStore {
+  store: MemoryStore,
+  fail: Arc<Mutex<bool>>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for NoRemoveStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    if *self.fail.lock().await {
+      return Err("remove failed".into());
+    }
+    self.store.remove(name).await
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    self.store.list(prefix).await
+  }
+}
+
+#[async_std::test]
+async fn gc() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let fail = Arc::new(Mutex::new(true));
+  let size = 3000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let nstore = NoRemoveStore { store: store.clone(), fail: fail.clone() };
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(nstore))
+    .max_records(100)
+    .ext_records(100)
+    .build().await?;
+  for batch in inserts.chunks(100) {
+    db.batch(batch).await?;
+    db.sync().await?;
+  }
+  let report = db.verify().await?;
+  let orphans = report.issues.len();
+  assert![orphans > 0, "replaced trees left behind"];
+  assert_eq![report.trees + orphans, store.list("t/").await?.len()];
+
+  *fail.lock().await = false;
+  let removed = db.gc().await?;
+  assert_eq![removed.len(), orphans, "every orphan removed"];
+  assert_eq![store.list("t/").await?.len(), report.trees, "only live trees remain"];
+  assert![db.verify().await?.is_ok(), "no issues after gc"];
+  assert_eq![db.gc().await?.len(), 0, "nothing left to collect"];
+
+  let db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut count = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    count += 1;
+  }
+  assert_eq![count, size, "every record remains after gc"];
+  Ok(())
+}
@@ -0,0 +1,243 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,BatchOptions,Tree3,Value,
+  EyrosError,EyrosErrorKind,Error};
+use desert::{ToBytes,FromBytes,CountBytes};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+use std::sync::{Arc,atomic::{AtomicUsize,Ordering}};
+use std::hash::{Hash,Hasher};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+type TS = Tree3<f32,f32,f32,Shared>;
+
+// storage that counts how many times tree files are opened
+#[derive(Clone)]
+struct CountStore {
+  store: MemoryStore,
+  opens: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for CountStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if name.starts_with("t/") { self.opens.fetch_add(1, Ordering::SeqCst); }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    self.store.list(prefix).await
+  }
+}
+
+#[async_std::test]
+async fn id_index() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let size = 5000;
+  let mut r = rand().seed([13,12]);
+  // values are distinct so each is its own id
+  let inserts: Vec<Row<P,V>> = (0..size).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, i as u32)
+  }).collect();
+  let records: Vec<(P,V)> = inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+
+  let (mut db,_) = open(&store, true).await?;
+  db.batch(&inserts[0..3000]).await?;
+  db.batch(&inserts[3000..4000]).await?;
+  for (p,v) in records[0..4000].iter().step_by(7) {
+    assert_eq![db.get(v).await?, Some((p.clone(),*v)), "get id={}", v];
+  }
+  assert_eq![db.get(&(size as u32 + 1)).await?, None, "get a missing id"];
+
+  // delete by id, by point, and by id again after the record was moved by a merge
+  let mut deletes: Vec<Row<P,V>> = (0..400).map(|i| Row::DeleteId(i as u32)).collect();
+  deletes.extend(records[400..500].iter().map(|(p,v)| Row::Delete(p.clone(),*v)));
+  db.batch(&deletes).await?;
+  db.batch(&inserts[4000..5000]).await?;
+  let deletes: Vec<Row<P,V>> = (500..800).map(|i| Row::DeleteId(i as u32)).collect();
+  db.batch(&deletes).await?;
+  db.sync().await?;
+  let expected = &records[800..5000];
+  let results = collect(db.query(&bbox()).await?).await?;
+  assert_eq![sorted(results), values(expected), "query after deletes"];
+  for v in [0,399,450,799].iter() {
+    assert_eq![db.get(v).await?, None, "get deleted id={}", v];
+  }
+  for (p,v) in expected.iter().step_by(7) {
+    assert_eq![db.get(v).await?, Some((p.clone(),*v)), "get id={} after deletes", v];
+  }
+
+  // deleting an id that is not present fails unless error_if_missing is turned off
+  let missing = vec![Row::DeleteId(0)];
+  match_kind(db.batch(&missing).await, "delete a missing id", |k| {
+    matches![k, EyrosErrorKind::RemoveIdsMissing { .. }]
+  });
+  db.batch_with_options(&missing, &BatchOptions::new().error_if_missing(false)).await?;
+  drop(db);
+
+  // the index is read back from storage without loading any trees
+  let (db,opens) = open(&store, true).await?;
+  assert_eq![opens.load(Ordering::SeqCst), 0, "trees loaded to open the index"];
+  for (p,v) in expected.iter().step_by(11) {
+    assert_eq![db.get(v).await?, Some((p.clone(),*v)), "get id={} after reopening", v];
+  }
+  drop(db);
+
+  // an index that was not written for the latest sync is rebuilt
+  {
+    let (mut db,_) = open(&store, false).await?;
+    match_kind(db.get(&1000).await.map(|_| ()), "get without the index", |k| {
+      matches![k, EyrosErrorKind::IdIndexDisabled { .. }]
+    });
+    match_kind(db.batch(&[Row::DeleteId(1000)]).await, "delete by id without the index", |k| {
+      matches![k, EyrosErrorKind::IdIndexDisabled { .. }]
+    });
+    db.batch(&[Row::Delete(records[1000].0.clone(),1000)]).await?;
+    db.sync().await?;
+  }
+  let (mut db,opens) = open(&store, true).await?;
+  assert![opens.load(Ordering::SeqCst) > 0, "trees loaded to rebuild the index"];
+  assert_eq![db.get(&1000).await?, None, "get an id deleted without the index"];
+  assert_eq![db.get(&1001).await?, Some(records[1001].clone()), "get after rebuilding"];
+
+  // records keep being found after optimize() and restore() move them to other trees
+  db.checkpoint("a").await?;
+  db.optimize(3).await?;
+  for (p,v) in expected.iter().skip(201).step_by(13) {
+    assert_eq![db.get(v).await?, Some((p.clone(),*v)), "get id={} after optimize", v];
+  }
+  db.batch(&(1001..2000).map(|i| Row::DeleteId(i as u32)).collect::<Vec<_>>()).await?;
+  assert_eq![db.get(&1001).await?, None, "get deleted id after optimize"];
+  db.restore("a").await?;
+  assert_eq![db.get(&1001).await?, Some(records[1001].clone()), "get after restore"];
+  db.batch(&[Row::DeleteId(1001)]).await?;
+  assert_eq![db.get(&1001).await?, None, "get deleted id after restore"];
+  Ok(())
+}
+
+// a value whose ids only hash to 4 different values, so most index entries name several records
+#[derive(Debug,Clone,Copy,Hash,PartialEq)]
+struct Shared(u32);
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+struct SharedId(u32);
+
+impl Hash for SharedId {
+  fn hash<H: Hasher>(&self, h: &mut H) {
+    (self.0 % 4).hash(h)
+  }
+}
+
+impl Value for Shared {
+  type Id = SharedId;
+  fn get_id(&self) -> SharedId { SharedId(self.0) }
+}
+impl ToBytes for Shared {
+  fn to_bytes(&self) -> Result<Vec<u8>,Error> {
+    self.0.to_bytes()
+  }
+}
+impl CountBytes for Shared {
+  fn count_from_bytes(src: &[u8]) -> Result<usize,Error> {
+    u32::count_from_bytes(src)
+  }
+  fn count_bytes(&self) -> usize {
+    self.0.count_bytes()
+  }
+}
+impl FromBytes for Shared {
+  fn from_bytes(src: &[u8]) -> Result<(usize,Self),Error> {
+    let (size,x) = u32::from_bytes(src)?;
+    Ok((size, Shared(x)))
+  }
+}
+
+#[async_std::test]
+async fn id_index_shared_hashes() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let records: Vec<(P,Shared)> = (0..2000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let time: f32 = r.read::<f32>()*1000.0;
+    ((Coord::Scalar(x),Coord::Scalar(y),Coord::Scalar(time)), Shared(i))
+  }).collect();
+  let mut db: DB<_,TS,P,Shared> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .id_index(true)
+    .build().await?;
+  let inserts: Vec<Row<P,Shared>> = records.iter().map(|(p,v)| Row::Insert(p.clone(),*v)).collect();
+  for batch in inserts.chunks(500) {
+    db.batch(batch).await?;
+  }
+
+  // deleting a record keeps the index entries of the other records with the same hash
+  db.batch(&(0..300).map(|i| Row::DeleteId(SharedId(i))).collect::<Vec<_>>()).await?;
+  db.batch(&records[300..400].iter().map(|(p,v)| Row::Delete(p.clone(),v.get_id()))
+    .collect::<Vec<_>>()).await?;
+  db.batch(&(400..600).step_by(2).map(|i| Row::DeleteId(SharedId(i))).collect::<Vec<_>>()).await?;
+  db.sync().await?;
+  for (p,v) in records.iter() {
+    let deleted = v.0 < 400 || (v.0 < 600 && v.0 % 2 == 0);
+    let expected = if deleted { None } else { Some((p.clone(),*v)) };
+    assert_eq![db.get(&v.get_id()).await?, expected, "get id={}", v.0];
+  }
+  Ok(())
+}
+
+fn bbox() -> ((f32,f32,f32),(f32,f32,f32)) {
+  ((-1.0,-1.0,0.0),(1.0,1.0,1000.0))
+}
+
+fn match_kind<F>(r: Result<(),Error>, msg: &str, f: F) where F: Fn(&EyrosErrorKind) -> bool {
+  match r.err().as_ref().and_then(|e| e.downcast_ref::<EyrosError>()).map(|e| e.kind()) {
+    Some(kind) if f(kind) => {},
+    kind => panic!["{}: unexpected result {:?}", msg, kind],
+  }
+}
+
+async fn open(store: &MemoryStore, id_index: bool)
+-> Result<(DB<MemoryFile,T,P,V>,Arc<AtomicUsize>),Error> {
+  let opens = Arc::new(AtomicUsize::new(0));
+  let cstore = CountStore { store: store.clone(), opens: Arc::clone(&opens) };
+  let db = Setup::from_storage(Box::new(cstore))
+    .max_records(50)
+    .ext_records(20)
+    .id_index(id_index)
+    .build().await?;
+  Ok((db,opens))
+}
+
+fn values(records: &[(P,V)]) -> Vec<V> {
+  let mut values: Vec<V> = records.iter().map(|(_,v)| *v).collect();
+  values.sort_unstable();
+  values
+}
+
+fn sorted(records: Vec<(P,V)>) -> Vec<V> {
+  values(&records)
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<(P,V)>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  Ok(results)
+}
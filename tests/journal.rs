@@ -0,0 +1,127 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Tree3,Error};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use async_std::{prelude::*,sync::{Arc,Mutex}};
+
+use std::cmp::Ordering;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+// fails to open tree files while `fail` is set, simulating a crash partway through a sync
+#[derive(Clone)]
+struct FailStore {
+  store: MemoryStore,
+  fail: Arc<Mutex<bool>>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for FailStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if *self.fail.lock().await && name.starts_with("t/") {
+      return Err("simulated crash".into());
+    }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+}
+
+#[async_std::test]
+async fn journal_replay() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let fail = Arc::new(Mutex::new(false));
+  let inserts = rows(3000);
+  {
+    let fstore = FailStore { store: store.clone(), fail: fail.clone() };
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(fstore))
+      .max_records(500)
+      .build().await?;
+    db.batch(&inserts[0..1000]).await?;
+    db.sync().await?;
+    db.batch(&inserts[1000..3000]).await?;
+    *fail.lock().await = true;
+    assert![db.sync().await.is_err(), "sync fails while writing tree files"];
+  }
+  assert![store.clone().open("journal").await?.len().await? > 0, "journal left behind"];
+  {
+    let mut db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    check(&mut db, &inserts[0..3000]).await?;
+  }
+  assert_eq![store.clone().open("journal").await?.len().await?, 0, "journal cleared"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn journal_discard_incomplete() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let inserts = rows(2000);
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(500)
+      .build().await?;
+    db.batch(&inserts[0..1000]).await?;
+    db.sync().await?;
+  }
+  {
+    // a journal torn partway through writing
+    let mut j = store.open("journal").await?;
+    j.write(0, &[5,1,2,3,4,5,6,7]).await?;
+  }
+  {
+    let mut db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    check(&mut db, &inserts[0..1000]).await?;
+    db.batch(&inserts[1000..2000]).await?;
+    db.sync().await?;
+  }
+  {
+    let mut db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    check(&mut db, &inserts[0..2000]).await?;
+  }
+  Ok(())
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
+
+async fn check(db: &mut DB<MemoryFile,T,P,V>, inserts: &[Row<P,V>]) -> Result<(),Error> {
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let mut results = vec![];
+  let mut stream = db.query(&bbox).await?;
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  let mut expected: Vec<(P,V)> = inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  results.sort_unstable_by(cmp);
+  expected.sort_unstable_by(cmp);
+  assert_eq![results.len(), expected.len(), "incorrect length for full region"];
+  assert_eq![results, expected, "incorrect results for full region"];
+  Ok(())
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
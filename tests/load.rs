@@ -1,3 +1,4 @@
+mod common;
 use eyros::{DB,Tree,Scalar,Coord,Row,Error};
 use random_access_storage::RandomAccess;
 use random::{Source,default as rand};
@@ -30,7 +31,7 @@ async fn load() -> Result<(),Error> {
   }).collect();
   {
     // seed the db
-    let mut db = eyros::open_from_path3(dir.path()).await?;
+    let mut db = common::open3(dir.path()).await?;
     let n = 4;
     let batches: Vec<Vec<Row<P,V>>> = (0..n).map(|i| {
       inserts[size/n*i..size/n*(i+1)].to_vec()
@@ -43,7 +44,7 @@ async fn load() -> Result<(),Error> {
   {
     // let the previous db fall out of scope and create a new one
     // so it loads records from zero
-    let mut db = eyros::open_from_path3(dir.path()).await?;
+    let mut db = common::open3(dir.path()).await?;
     check(&mut db, &inserts, size).await?;
   }
   // create more records
@@ -64,7 +65,7 @@ async fn load() -> Result<(),Error> {
   }
   {
     // batch insert the records on a new db
-    let mut db = eyros::open_from_path3(dir.path()).await?;
+    let mut db = common::open3(dir.path()).await?;
     let n = 5;
     let batches: Vec<Vec<Row<P,V>>> = (0..n).map(|i| {
       inserts[size+newsize/n*i..size+newsize/n*(i+1)].to_vec()
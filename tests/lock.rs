@@ -0,0 +1,51 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,Tree2,Error,EyrosError,EyrosErrorKind};
+use tempfile::Builder as Tmpfile;
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree2<f32,f32,V>;
+
+#[async_std::test]
+async fn lock_file_store() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut a: DB<_,T,P,V> = Setup::from_path(dir.path()).build().await?;
+  a.batch(&[Row::Insert((Coord::Scalar(0.5),Coord::Interval(-0.5,0.5)),7)]).await?;
+  a.sync().await?;
+  assert_locked(Setup::from_path(dir.path()).build::<T,P,V>().await, "second writer");
+  {
+    let r: DB<_,T,P,V> = Setup::from_path(dir.path()).read_only(true).build().await?;
+    let mut stream = r.query(&((-1.0,-1.0),(1.0,1.0))).await?;
+    assert_eq![stream.next().await.transpose()?.map(|(_,v)| v), Some(7), "read-only query"];
+  }
+  let c = a.clone();
+  drop(a);
+  assert_locked(Setup::from_path(dir.path()).build::<T,P,V>().await, "writer while a clone is open");
+  drop(c);
+  let _b: DB<_,T,P,V> = Setup::from_path(dir.path()).build().await?;
+  Ok(())
+}
+
+#[async_std::test]
+async fn lock_memory_store() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  {
+    let _a: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone())).build().await?;
+    assert_locked(Setup::from_storage(Box::new(store.clone())).build::<T,P,V>().await,
+      "second writer");
+    assert_locked(store.lock().await, "lock from storage");
+    let _r: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .read_only(true).build().await?;
+  }
+  let _b: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone())).build().await?;
+  // other stores are not locked
+  let _c: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new())).build().await?;
+  Ok(())
+}
+
+fn assert_locked<X>(r: Result<X,Error>, msg: &str) {
+  match r.err().as_ref().and_then(|e| e.downcast_ref::<EyrosError>()).map(|e| e.kind()) {
+    Some(EyrosErrorKind::Locked { .. }) => {},
+    kind => panic!["{}: expected a locked error, got {:?}", msg, kind],
+  }
+}
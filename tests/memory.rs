@@ -0,0 +1,124 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,FileStore,Tree3,Error};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use async_std::prelude::*;
+
+use std::cmp::Ordering;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn memory_db() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let size = 3000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(500)
+      .build().await?;
+    for batch in inserts.chunks(1000) {
+      db.batch(batch).await?;
+    }
+    db.sync().await?;
+  }
+  {
+    // open again from a clone of the same store
+    let db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+    let mut results = vec![];
+    let mut stream = db.query(&bbox).await?;
+    while let Some(result) = stream.next().await {
+      results.push(result?);
+    }
+    let mut expected: Vec<(P,V)> = inserts.iter().map(|r| match r {
+      Row::Insert(p,v) => (p.clone(),*v),
+      _ => panic!["unexpected row type"],
+    }).collect();
+    results.sort_unstable_by(cmp);
+    expected.sort_unstable_by(cmp);
+    assert_eq![results.len(), size, "incorrect length for full region"];
+    assert_eq![results, expected, "incorrect results for full region"];
+  }
+  {
+    let db = eyros::open_in_memory3::<f32,f32,f32,V>().await?;
+    let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+    assert![stream.next().await.is_none(), "fresh in-memory db is empty"];
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn memory_disk_parity() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut disk = FileStore::new(dir.path());
+  let mut mem = MemoryStore::new();
+  {
+    let mut d = disk.open("a/b").await?;
+    let mut m = mem.open("a/b").await?;
+    assert_eq![d.len().await?, 0];
+    assert_eq![m.len().await?, 0];
+    d.write(4, &[1,2,3]).await?;
+    m.write(4, &[1,2,3]).await?;
+    assert_eq![d.len().await?, 7];
+    assert_eq![m.len().await?, 7];
+    assert_eq![d.read(0,7).await?, m.read(0,7).await?];
+    assert![d.read(5,5).await.is_err(), "disk read out of bounds"];
+    assert![m.read(5,5).await.is_err(), "memory read out of bounds"];
+    d.truncate(5).await?;
+    m.truncate(5).await?;
+    assert_eq![d.len().await?, 5];
+    assert_eq![m.len().await?, 5];
+    assert_eq![d.read(0,5).await?, m.read(0,5).await?];
+    let mut out: Vec<u8> = vec![];
+    assert![m.read_to_writer(3, 2, &mut out).await.is_err(), "memory read to writer is unsupported"];
+    d.sync_all().await?;
+    m.sync_all().await?;
+  }
+  {
+    // a second handle sees the same data
+    let mut m = mem.open("a/b").await?;
+    assert_eq![m.read(0,5).await?, vec![0,0,0,0,1]];
+  }
+  disk.open("a/c/d").await?.write(0, &[1]).await?;
+  mem.open("a/c/d").await?.write(0, &[1]).await?;
+  assert_eq![disk.list("a/").await?, vec!["a/b","a/c/d"], "disk list"];
+  assert_eq![mem.list("a/").await?, vec!["a/b","a/c/d"], "memory list"];
+  assert_eq![disk.list("x/").await?, Vec::<String>::new(), "disk list missing dir"];
+  assert_eq![mem.list("x/").await?, Vec::<String>::new(), "memory list missing dir"];
+  assert_eq![disk.read("a/c/d").await?, Some(vec![1]), "disk read file"];
+  assert_eq![mem.read("a/c/d").await?, Some(vec![1]), "memory read file"];
+  assert_eq![disk.read("a/e").await?, None, "disk read missing file"];
+  assert_eq![mem.read("a/e").await?, None, "memory read missing file"];
+  assert_eq![disk.list("a/").await?, vec!["a/b","a/c/d"], "disk read does not create"];
+  assert_eq![mem.list("a/").await?, vec!["a/b","a/c/d"], "memory read does not create"];
+  disk.remove("a/b").await?;
+  mem.remove("a/b").await?;
+  assert![disk.remove("a/b").await.is_err(), "disk remove missing file"];
+  assert![mem.remove("a/b").await.is_err(), "memory remove missing file"];
+  assert_eq![mem.open("a/b").await?.len().await?, 0, "removed file reopens empty"];
+  Ok(())
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,148 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Meta,Tree3,Tree,Error,VerifyIssue,
+  FromBytesVersion,ToBytesVersion,TREE_VERSION,SCHEMA_VERSION,tree::get_file_from_id};
+use desert::{ToBytes,FromBytes};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn migrate() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let size = 3000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(100)
+      .ext_records(100)
+      .build().await?;
+    db.batch(&inserts).await?;
+    db.sync().await?;
+  }
+  // rewrite every file as it was before files had a versioned trailer or record counts
+  let files = store.list("t/").await?;
+  assert![files.len() > 1, "need more than one tree"];
+  for file in files.iter() {
+    let mut s = store.open(file).await?;
+    let len = s.len().await?;
+    assert_eq![&s.read(len-4,4).await?, &[0x00,b'e',b'y',b'v'], "versioned trailer"];
+    let body = s.read(0,len-9).await?;
+    let bytes = T::from_bytes_version(&body, TREE_VERSION)?.1.to_bytes_version(1)?;
+    s.write(0, &bytes).await?;
+    s.truncate(bytes.len() as u64).await?;
+  }
+  write_legacy_meta(&mut store).await?;
+  let mut db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  assert_eq![db.count(&bbox).await?, size as u64, "count of trees without record counts"];
+  assert_eq![db.migrate().await?, files.len(), "every tree rewritten"];
+  for file in files.iter().chain(["meta".to_string()].iter()) {
+    let mut s = store.open(file).await?;
+    let len = s.len().await?;
+    assert_eq![&s.read(len-4,4).await?, &[0x00,b'e',b'y',b'v'], "versioned trailer after migrate"];
+    if file == "meta" { continue }
+    let mut t = T::from_bytes_version(&s.read(0,len-9).await?, TREE_VERSION)?.1;
+    assert![t.list_refs().iter().all(|r| r.count.is_some()), "record counts after migrate"];
+  }
+  assert_eq![db.meta.read().await.schema.as_ref().unwrap().version, SCHEMA_VERSION,
+    "schema version after migrate"];
+  assert_eq![db.migrate().await?, 0, "nothing left to migrate"];
+  assert![db.verify().await?.is_ok(), "no issues after migrate"];
+  assert_eq![db.len().await?, size as u64, "record counts filled in by migrate"];
+
+  drop(db);
+  let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut count = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    count += 1;
+  }
+  assert_eq![count, size, "every record remains after migrate"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn migrate_shared_tree() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3000).map(|_| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert((Coord::Scalar(x),Coord::Scalar(y),Coord::Scalar(time)), r.read())
+  }).collect();
+  let roots: Vec<u64> = {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(50)
+      .ext_records(20)
+      .build().await?;
+    for batch in inserts.chunks(1000) {
+      db.batch(batch).await?;
+    }
+    db.sync().await?;
+    let roots = db.meta.read().await.roots.iter().flatten().map(|r| r.id).collect();
+    roots
+  };
+  assert_eq![roots.len(), 2, "two roots"];
+  // point a ref of the first root at a tree of the last root, which the walk reaches first
+  let mut last = read_tree(&mut store, roots[1]).await?;
+  let shared = last.list_refs()[0].clone();
+  let mut first = read_tree(&mut store, roots[0]).await?;
+  let mut replaced = false;
+  let bytes = first.map_refs(&mut |r| {
+    if !replaced {
+      *r = shared.clone();
+      replaced = true;
+    }
+  }).to_bytes_version(1)?;
+  assert![replaced, "first root has a ref"];
+  let mut s = store.open(&get_file_from_id(&roots[0])).await?;
+  s.write(0, &bytes).await?;
+  s.truncate(bytes.len() as u64).await?;
+  write_legacy_meta(&mut store).await?;
+
+  let mut db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  assert![db.migrate().await? > 0, "migrate a tree referenced twice"];
+  assert![
+    db.verify().await?.issues.iter().any(|i| matches![i, VerifyIssue::DuplicateRef { .. }]),
+    "verify reports the tree referenced twice"
+  ];
+  Ok(())
+}
+
+// rewrite the meta without its trailer and with the schema version from before tree files had one
+async fn write_legacy_meta(store: &mut MemoryStore) -> Result<(),Error> {
+  let mut s = store.open("meta").await?;
+  let len = s.len().await?;
+  assert_eq![&s.read(len-4,4).await?, &[0x00,b'e',b'y',b'v'], "versioned trailer"];
+  let mut meta = Meta::<P>::from_bytes(&s.read(0,len-9).await?)?.1;
+  meta.schema.as_mut().unwrap().version = 2;
+  let bytes = meta.to_bytes()?;
+  s.write(0, &bytes).await?;
+  s.truncate(bytes.len() as u64).await?;
+  Ok(())
+}
+
+async fn read_tree(store: &mut MemoryStore, id: u64) -> Result<T,Error> {
+  let mut s = store.open(&get_file_from_id(&id)).await?;
+  let len = s.len().await?;
+  Ok(T::from_bytes_version(&s.read(0,len-9).await?, TREE_VERSION)?.1)
+}
@@ -1,3 +1,4 @@
+mod common;
 use eyros::{Coord,Scalar,Row,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
@@ -10,7 +11,7 @@ type V = u32;
 #[async_std::test]
 async fn multi_batch() -> Result<(),Error> {
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db = eyros::open_from_path3(dir.path()).await?;
+  let mut db = common::open3(dir.path()).await?;
   let mut r = rand().seed([13,12]);
   let size = 4000;
   let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
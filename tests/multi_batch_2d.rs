@@ -1,3 +1,4 @@
+mod common;
 use eyros::{Coord,Scalar,Row,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
@@ -9,7 +10,7 @@ async fn multi_batch_f32_f64_u16() -> Result<(),Error> {
   type P = (Coord<f32>,Coord<f64>);
   type V = u16;
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db = eyros::open_from_path2(dir.path()).await?;
+  let mut db = common::open2(dir.path()).await?;
   let mut r = rand().seed([13,12]);
   let size = 10_000;
   let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
@@ -82,7 +83,7 @@ async fn multi_batch_f64iv_f32_u16() -> Result<(),Error> {
   type P = (Coord<f64>,Coord<f32>);
   type V = u16;
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db = eyros::open_from_path2(dir.path()).await?;
+  let mut db = common::open2(dir.path()).await?;
   let mut r = rand().seed([13,12]);
   let size = 10_000;
   let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
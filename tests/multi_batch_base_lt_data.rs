@@ -1,4 +1,5 @@
-use eyros::{DB,Tree3,Coord,Scalar,Row,Setup,Error};
+mod common;
+use eyros::{DB,Tree3,Coord,Scalar,Row,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
 use std::time;
@@ -11,7 +12,7 @@ type V = u32;
 #[async_std::test]
 async fn multi_batch() -> Result<(),Error> {
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db: DB<_,Tree3<f32,f32,f32,V>,P,V> = Setup::from_path(dir.path())
+  let mut db: DB<_,Tree3<f32,f32,f32,V>,P,V> = common::setup(dir.path())
     .branch_factor(5)
     .max_records(3_000)
     .build()
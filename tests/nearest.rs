@@ -0,0 +1,102 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,Dimensions,Metric,Tree3,Error};
+use random::{Source,default as rand};
+
+use std::cmp::Ordering;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn nearest() -> Result<(),Error> {
+  let inserts = rows(3000);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .ext_records(100)
+    .build().await?;
+  db.batch(&inserts[0..1000]).await?;
+  db.batch(&inserts[1000..1500]).await?;
+  db.batch(&inserts[1500..3000]).await?;
+  let deletes: Vec<Row<P,V>> = inserts[0..300].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  db.batch(&deletes).await?;
+  db.sync().await?;
+  let records: Vec<(P,V)> = inserts[300..].iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+
+  let scaled = Metric::custom(|d: &[f64]| {
+    (d[0]*d[0] + d[1]*d[1] + (d[2]/1000.0)*(d[2]/1000.0)).sqrt()
+  });
+  let points: Vec<P> = vec![
+    (Coord::Scalar(0.1),Coord::Scalar(-0.2),Coord::Scalar(500.0)),
+    (Coord::Scalar(-0.9),Coord::Scalar(0.95),Coord::Scalar(10.0)),
+    (Coord::Interval(0.2,0.3),Coord::Interval(-0.5,-0.4),Coord::Interval(100.0,200.0)),
+    (Coord::Scalar(3.0),Coord::Scalar(3.0),Coord::Scalar(2000.0)),
+  ];
+  for metric in [Metric::Euclidean, Metric::Manhattan, scaled].iter() {
+    for point in points.iter() {
+      for k in [1,20,150].iter() {
+        let results = db.nearest(point, *k, metric).await?;
+        let expected = brute_force(&records, point, *k, metric);
+        let msg = format!["metric={:?} point={:?} k={}", metric, point, k];
+        assert_eq![results.len(), *k, "{}: result count", msg];
+        assert_eq![
+          results.iter().map(|(_,_,d)| *d).collect::<Vec<f64>>(), expected,
+          "{}: distances in increasing order", msg
+        ];
+        for (p,v,d) in results.iter() {
+          assert![records.contains(&(p.clone(),*v)), "{}: result is a live record", msg];
+          assert_eq![metric.interval_distance(&point.to_f64_intervals(), &p.to_f64_intervals()),
+            *d, "{}: distance of result", msg];
+        }
+      }
+    }
+  }
+
+  let point = (Coord::Scalar(0.0),Coord::Scalar(0.0),Coord::Scalar(0.0));
+  let all = db.nearest(&point, 5000, &Metric::Euclidean).await?;
+  assert_eq![all.len(), records.len(), "k larger than the number of records"];
+  assert_eq![db.nearest(&point, 0, &Metric::Euclidean).await?.len(), 0, "k=0"];
+  Ok(())
+}
+
+fn brute_force(records: &[(P,V)], point: &P, k: usize, metric: &Metric) -> Vec<f64> {
+  let q = point.to_f64_intervals();
+  let mut distances: Vec<f64> = records.iter().map(|(p,_)| {
+    let deltas: Vec<f64> = q.iter().zip(p.to_f64_intervals().iter()).map(|((q0,q1),(p0,p1))| {
+      if p1 < q0 { q0 - p1 } else if q1 < p0 { p0 - q1 } else { 0.0 }
+    }).collect();
+    metric.distance(&deltas)
+  }).collect();
+  distances.sort_unstable_by(cmp);
+  distances.truncate(k);
+  distances
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
@@ -0,0 +1,122 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,QueryOptions,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::{prelude::*,task::sleep};
+use std::{time::Duration,sync::{Arc,atomic::{AtomicUsize,Ordering}}};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+// storage that counts how many times tree files are opened
+#[derive(Clone)]
+struct CountStore {
+  store: MemoryStore,
+  opens: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for CountStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if name.starts_with("t/") { self.opens.fetch_add(1, Ordering::SeqCst); }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    self.store.list(prefix).await
+  }
+}
+
+#[async_std::test]
+async fn query_limit() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let size = 5000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(50)
+      .ext_records(20)
+      .build().await?;
+    db.batch(&inserts[0..3000]).await?;
+    db.batch(&inserts[3000..5000]).await?;
+    db.sync().await?;
+  }
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+
+  let (db,opens) = open(&store).await?;
+  let mut all = collect(db.query(&bbox).await?).await?;
+  assert_eq![all.len(), size, "results without a limit"];
+  let all_opens = opens.load(Ordering::SeqCst);
+  assert![all_opens > 50, "enough trees to load: {}", all_opens];
+  all.sort_unstable();
+
+  for limit in [0,1,10,500,size,size+10].iter() {
+    let (db,_) = open(&store).await?;
+    let opts = QueryOptions::new().limit(*limit);
+    let results = collect(db.query_with_options(&bbox, &opts).await?).await?;
+    assert_eq![results.len(), (*limit).min(size), "results with limit={}", limit];
+    assert![
+      results.iter().all(|v| all.binary_search(v).is_ok()),
+      "results with limit={} are records of the database", limit
+    ];
+  }
+
+  // the workers stop loading trees once the limit is reached
+  let (db,opens) = open(&store).await?;
+  let opts = QueryOptions::new().limit(10);
+  let results = collect(db.query_with_options(&bbox, &opts).await?).await?;
+  assert_eq![results.len(), 10, "results with a limit"];
+  sleep(Duration::from_millis(200)).await;
+  let limit_opens = opens.load(Ordering::SeqCst);
+  assert![
+    limit_opens < all_opens/2,
+    "trees loaded with a limit: {} of {}", limit_opens, all_opens
+  ];
+
+  // dropping a stream stops loading trees
+  let (db,opens) = open(&store).await?;
+  let mut stream = db.query(&bbox).await?;
+  for _ in 0..10 {
+    stream.next().await.unwrap()?;
+  }
+  drop(stream);
+  sleep(Duration::from_millis(200)).await;
+  let drop_opens = opens.load(Ordering::SeqCst);
+  assert![
+    drop_opens < all_opens/2,
+    "trees loaded before the stream was dropped: {} of {}", drop_opens, all_opens
+  ];
+  Ok(())
+}
+
+async fn open(store: &MemoryStore) -> Result<(DB<MemoryFile,T,P,V>,Arc<AtomicUsize>),Error> {
+  let opens = Arc::new(AtomicUsize::new(0));
+  let cstore = CountStore { store: store.clone(), opens: Arc::clone(&opens) };
+  let db = DB::open_from_storage(Box::new(cstore)).await?;
+  opens.store(0, Ordering::SeqCst);
+  Ok((db,opens))
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<V>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut values = vec![];
+  while let Some(result) = stream.next().await {
+    values.push(result?.1);
+  }
+  Ok(values)
+}
@@ -0,0 +1,135 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+use std::sync::{Arc,atomic::{AtomicUsize,Ordering}};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+type B = ((f32,f32,f32),(f32,f32,f32));
+
+// storage that counts how many times tree files are opened
+#[derive(Clone)]
+struct CountStore {
+  store: MemoryStore,
+  opens: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for CountStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if name.starts_with("t/") { self.opens.fetch_add(1, Ordering::SeqCst); }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    self.store.list(prefix).await
+  }
+}
+
+#[async_std::test]
+async fn query_many() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let size = 5000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let deletes: Vec<Row<P,V>> = inserts[0..500].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(50)
+      .ext_records(20)
+      .build().await?;
+    db.batch(&inserts[0..3000]).await?;
+    db.batch(&inserts[3000..5000]).await?;
+    db.batch(&deletes).await?;
+    db.sync().await?;
+  }
+
+  // tiles of a 4x4 grid over part of the space, plus boxes that overlap them and an empty box
+  let mut bboxes: Vec<B> = vec![];
+  for i in 0..4 {
+    for j in 0..4 {
+      let (x,y) = (-0.8 + 0.3*(i as f32), -0.8 + 0.3*(j as f32));
+      bboxes.push(((x,y,0.0),(x+0.3,y+0.3,1000.0)));
+    }
+  }
+  bboxes.push(((-0.5,-0.5,0.0),(0.5,0.5,500.0)));
+  bboxes.push(((-0.5,-0.5,0.0),(0.5,0.5,500.0)));
+  bboxes.push(((2.0,2.0,0.0),(3.0,3.0,1000.0)));
+
+  let (db,opens) = open(&store).await?;
+  let mut results = vec![];
+  let mut stream = db.query_many(&bboxes).await?;
+  while let Some(result) = stream.next().await {
+    let (k,(_,v)) = result?;
+    results.push((k,v));
+  }
+  results.sort_unstable();
+  let many_opens = opens.load(Ordering::SeqCst);
+
+  // separate queries from a fresh database each, without trees cached from earlier queries
+  let mut expected = vec![];
+  let mut each_opens = 0;
+  for (k,bbox) in bboxes.iter().enumerate() {
+    let (db,opens) = open(&store).await?;
+    let mut stream = db.query(bbox).await?;
+    while let Some(result) = stream.next().await {
+      expected.push((k,result?.1));
+    }
+    each_opens += opens.load(Ordering::SeqCst);
+  }
+  expected.sort_unstable();
+  assert![expected.len() > 2000, "results for every box: {}", expected.len()];
+  assert![
+    expected.iter().filter(|(k,_)| *k == 16).count() > 0
+      && expected.iter().filter(|(k,_)| *k == 16).count()
+        == expected.iter().filter(|(k,_)| *k == 17).count(),
+    "records are provided for each box they intersect"
+  ];
+  assert_eq![results.len(), expected.len(), "number of results"];
+  assert_eq![results, expected, "results"];
+
+  // each tree is loaded at most once for the whole batch
+  let (db,opens) = open(&store).await?;
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  assert_eq![n, size-500, "records"];
+  let all_opens = opens.load(Ordering::SeqCst);
+  assert![many_opens <= all_opens, "trees loaded: {} of {}", many_opens, all_opens];
+  assert![many_opens*4 < each_opens, "trees loaded: {} instead of {}", many_opens, each_opens];
+
+  let (db,_) = open(&store).await?;
+  let mut stream = db.query_many(&[]).await?;
+  assert![stream.next().await.is_none(), "query_many without any boxes"];
+  Ok(())
+}
+
+async fn open(store: &MemoryStore) -> Result<(DB<MemoryFile,T,P,V>,Arc<AtomicUsize>),Error> {
+  let opens = Arc::new(AtomicUsize::new(0));
+  let cstore = CountStore { store: store.clone(), opens: Arc::clone(&opens) };
+  let db = DB::open_from_storage(Box::new(cstore)).await?;
+  opens.store(0, Ordering::SeqCst);
+  Ok((db,opens))
+}
@@ -0,0 +1,109 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,QueryOptions,QueryMode,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+type B = ((f32,f32,f32),(f32,f32,f32));
+
+#[async_std::test]
+async fn query_mode() -> Result<(),Error> {
+  let size = 4000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(2.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(2.0)*(1.0-ymin);
+    let tmin: f32 = r.read::<f32>()*1000.0;
+    let tmax: f32 = tmin + r.read::<f32>()*200.0;
+    let value: u32 = r.read();
+    // some records are points in time instead of ranges
+    let time = if i % 5 == 0 { Coord::Scalar(tmin) } else { Coord::Interval(tmin,tmax) };
+    Row::Insert((Coord::Interval(xmin,xmax), Coord::Interval(ymin,ymax), time), value)
+  }).collect();
+  let deletes: Vec<Row<P,V>> = inserts[0..500].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .build().await?;
+  db.batch(&inserts[0..2500]).await?;
+  db.batch(&inserts[2500..4000]).await?;
+  db.batch(&deletes).await?;
+  db.sync().await?;
+  let records: Vec<(P,V)> = inserts[500..].iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+
+  let bboxes: Vec<B> = vec![
+    ((-1.0,-1.0,0.0),(1.0,1.0,1000.0)),
+    ((-0.5,-0.5,0.0),(0.5,0.5,500.0)),
+    ((0.2,-0.9,100.0),(0.3,0.9,900.0)),
+    ((0.3,0.3,400.0),(0.3,0.3,400.0)),
+    ((0.5,0.6,650.0),(0.55,0.62,660.0)),
+    ((-0.2,0.1,250.0),(-0.1,0.2,250.0)),
+  ];
+  let modes = [QueryMode::Intersects, QueryMode::Within, QueryMode::Contains];
+  for bbox in bboxes.iter() {
+    for mode in modes.iter() {
+      let mut expected: Vec<V> = records.iter()
+        .filter(|(p,_)| matches(p, bbox, *mode))
+        .map(|(_,v)| *v)
+        .collect();
+      expected.sort_unstable();
+      let opts = QueryOptions::new().mode(*mode);
+      let mut stream = db.query_with_options(bbox, &opts).await?;
+      let mut results = vec![];
+      while let Some(result) = stream.next().await {
+        let (p,v) = result?;
+        assert![matches(&p, bbox, *mode), "{:?} result {:?} for bbox={:?}", mode, p, bbox];
+        results.push(v);
+      }
+      results.sort_unstable();
+      assert_eq![results.len(), expected.len(), "{:?} results for bbox={:?}", mode, bbox];
+      assert_eq![results, expected, "{:?} results for bbox={:?}", mode, bbox];
+    }
+  }
+
+  // the default mode is Intersects
+  let bbox = bboxes[1];
+  let mut stream = db.query(&bbox).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  assert_eq![
+    n,
+    records.iter().filter(|(p,_)| matches(p, &bbox, QueryMode::Intersects)).count(),
+    "default mode"
+  ];
+  assert![
+    records.iter().any(|(p,_)| matches(p, &bboxes[3], QueryMode::Contains)),
+    "records cover a point"
+  ];
+  Ok(())
+}
+
+fn matches(p: &P, bbox: &B, mode: QueryMode) -> bool {
+  matches_coord(&p.0, (bbox.0).0, (bbox.1).0, mode)
+    && matches_coord(&p.1, (bbox.0).1, (bbox.1).1, mode)
+    && matches_coord(&p.2, (bbox.0).2, (bbox.1).2, mode)
+}
+
+fn matches_coord(c: &Coord<f32>, low: f32, high: f32, mode: QueryMode) -> bool {
+  let (min,max) = match c {
+    Coord::Scalar(x) => (*x,*x),
+    Coord::Interval(x,y) => (*x,*y),
+  };
+  match mode {
+    QueryMode::Intersects => min <= high && low <= max,
+    QueryMode::Within => low <= min && max <= high,
+    QueryMode::Contains => min <= low && high <= max,
+  }
+}
@@ -0,0 +1,177 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Order,Tree2,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+use std::sync::{Arc,atomic::{AtomicUsize,Ordering}};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+// storage that counts how many times tree files are opened
+#[derive(Clone)]
+struct CountStore {
+  store: MemoryStore,
+  opens: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for CountStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if name.starts_with("t/") { self.opens.fetch_add(1, Ordering::SeqCst); }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+  async fn list(&mut self, prefix: &str) -> Result<Vec<String>,Error> {
+    self.store.list(prefix).await
+  }
+}
+
+#[async_std::test]
+async fn query_ordered() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let size = 5000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let tmin: f32 = r.read::<f32>()*1000.0;
+    let tmax: f32 = tmin + r.read::<f32>()*50.0;
+    let value: u32 = r.read();
+    // some records are points in time instead of ranges
+    let time = if i % 4 == 0 { Coord::Scalar(tmin) } else { Coord::Interval(tmin,tmax) };
+    Row::Insert((Coord::Interval(xmin,xmax), Coord::Interval(ymin,ymax), time), value)
+  }).collect();
+  let deletes: Vec<Row<P,V>> = inserts[0..500].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(50)
+      .ext_records(20)
+      .build().await?;
+    db.batch(&inserts[0..3000]).await?;
+    db.batch(&inserts[3000..5000]).await?;
+    db.batch(&deletes).await?;
+    db.sync().await?;
+  }
+
+  let bboxes = [
+    ((-1.0,-1.0,0.0),(1.0,1.0,1000.0)),
+    ((-0.5,-0.5,200.0),(0.5,0.5,700.0)),
+    ((0.2,-0.9,0.0),(0.3,0.9,1000.0)),
+  ];
+  let (db,_) = open(&store).await?;
+  for bbox in bboxes.iter() {
+    let mut expected = collect(db.query(bbox).await?).await?;
+    expected.sort_unstable_by_key(|(_,v)| *v);
+    for (dim,order) in [(2,Order::Ascending),(2,Order::Descending),(0,Order::Ascending)].iter() {
+      let mut results = collect(db.query_ordered(bbox, *dim, *order).await?).await?;
+      let starts: Vec<f32> = results.iter().map(|(p,_)| start(p, *dim)).collect();
+      assert![
+        starts.windows(2).all(|w| match order {
+          Order::Ascending => w[0] <= w[1],
+          Order::Descending => w[0] >= w[1],
+        }),
+        "results sorted by dim={} order={:?} for bbox={:?}", dim, order, bbox
+      ];
+      results.sort_unstable_by_key(|(_,v)| *v);
+      assert_eq![results.len(), expected.len(), "number of results for bbox={:?}", bbox];
+      assert_eq![results, expected, "results for bbox={:?}", bbox];
+    }
+  }
+
+  // reading the first results loads only the trees they can come from
+  let bbox = bboxes[0];
+  let (db,opens) = open(&store).await?;
+  collect(db.query(&bbox).await?).await?;
+  let all_opens = opens.load(Ordering::SeqCst);
+  let (db,opens) = open(&store).await?;
+  let mut stream = db.query_ordered(&bbox, 2, Order::Ascending).await?;
+  let mut first = vec![];
+  for _ in 0..10 {
+    first.push(start(&stream.next().await.unwrap()?.0, 2));
+  }
+  let first_opens = opens.load(Ordering::SeqCst);
+  assert![first[0] < 1.0, "earliest result: {}", first[0]];
+  assert![
+    first_opens*2 < all_opens,
+    "trees loaded for the first results: {} of {}", first_opens, all_opens
+  ];
+
+  let (db,_) = open(&store).await?;
+  assert![db.query_ordered(&bbox, 3, Order::Ascending).await.is_err(), "dimension out of range"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn query_ordered_u64() -> Result<(),Error> {
+  // nanosecond timestamps are too large for an f64 to tell apart
+  let base: u64 = 1_700_000_000_000_000_000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<(Coord<u64>,Coord<u64>),u32>> = (0..2000).map(|i| {
+    let t = base + r.read::<u64>() % 5000;
+    let x = r.read::<u64>() % 100;
+    Row::Insert((Coord::Scalar(t), Coord::Interval(x,x+10)), i)
+  }).collect();
+  let mut db: DB<_,Tree2<u64,u64,u32>,(Coord<u64>,Coord<u64>),u32> =
+    Setup::from_storage(Box::new(MemoryStore::new()))
+      .max_records(50)
+      .ext_records(20)
+      .build().await?;
+  db.batch(&inserts[0..1000]).await?;
+  db.batch(&inserts[1000..2000]).await?;
+  let bbox = ((base,0),(base+5000,200));
+  for order in [Order::Ascending,Order::Descending].iter() {
+    let mut stream = db.query_ordered(&bbox, 0, *order).await?;
+    let mut times = vec![];
+    while let Some(result) = stream.next().await {
+      match result?.0.0 {
+        Coord::Scalar(t) => times.push(t),
+        c => panic!["unexpected coordinate {:?}", c],
+      }
+    }
+    assert_eq![times.len(), inserts.len(), "number of results in order={:?}", order];
+    assert![
+      times.windows(2).all(|w| match order {
+        Order::Ascending => w[0] <= w[1],
+        Order::Descending => w[0] >= w[1],
+      }),
+      "results sorted by u64 time in order={:?}", order
+    ];
+  }
+  Ok(())
+}
+
+fn start(p: &P, dim: usize) -> f32 {
+  let c = match dim {
+    0 => &p.0,
+    1 => &p.1,
+    _ => &p.2,
+  };
+  match c {
+    Coord::Scalar(x) => *x,
+    Coord::Interval(x,_) => *x,
+  }
+}
+
+async fn open(store: &MemoryStore) -> Result<(DB<MemoryFile,T,P,V>,Arc<AtomicUsize>),Error> {
+  let opens = Arc::new(AtomicUsize::new(0));
+  let cstore = CountStore { store: store.clone(), opens: Arc::clone(&opens) };
+  let db = DB::open_from_storage(Box::new(cstore)).await?;
+  opens.store(0, Ordering::SeqCst);
+  Ok((db,opens))
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<(P,V)>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  Ok(results)
+}
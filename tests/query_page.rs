@@ -0,0 +1,215 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,QueryCursor,Tree3,EyrosError,EyrosErrorKind,Error};
+use desert::{ToBytes,FromBytes};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+type B = ((f32,f32,f32),(f32,f32,f32));
+
+#[async_std::test]
+async fn query_page() -> Result<(),Error> {
+  let size = 5000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .build().await?;
+  db.batch(&inserts[0..3000]).await?;
+  db.batch(&inserts[3000..4000]).await?;
+  db.batch(&to_deletes(&inserts[0..500])).await?;
+  db.sync().await?;
+
+  let bboxes: [B;3] = [
+    ((-1.0,-1.0,0.0),(1.0,1.0,1000.0)),
+    ((-0.5,-0.5,0.0),(0.5,0.5,500.0)),
+    ((2.0,2.0,0.0),(3.0,3.0,1000.0)),
+  ];
+  for bbox in bboxes.iter() {
+    let expected = sorted(collect(db.query(bbox).await?).await?);
+    for page_size in [1,37,500,10_000].iter() {
+      // the cursor goes through bytes between pages as it would through a client
+      let mut results = vec![];
+      let mut cursor: Option<Vec<u8>> = None;
+      loop {
+        let c = match &cursor {
+          Some(bytes) => Some(QueryCursor::<P>::from_bytes(bytes)?.1),
+          None => None,
+        };
+        let (page,next) = db.query_page(bbox, c.as_ref(), *page_size).await?;
+        assert![page.len() <= *page_size, "page of {} with page_size={}", page.len(), page_size];
+        if next.is_some() {
+          assert_eq![page.len(), *page_size, "full page before the last"];
+        }
+        results.extend(page);
+        match next {
+          Some(c) => cursor = Some(c.to_bytes()?),
+          None => break,
+        }
+      }
+      assert_eq![
+        sorted(results), expected,
+        "results with page_size={} for bbox={:?}", page_size, bbox
+      ];
+    }
+  }
+
+  // a page can't be empty, since the same cursor would come back forever
+  let bbox = bboxes[0];
+  let (first,cursor) = db.query_page(&bbox, None, 100).await?;
+  assert_eq![first.len(), 100, "first page"];
+  let cursor = cursor.unwrap();
+  match db.query_page(&bbox, Some(&cursor), 0).await.err().as_ref().and_then(kind) {
+    Some(EyrosErrorKind::PageSizeZero {}) => {},
+    kind => panic!["expected a page size error, got {:?}", kind],
+  }
+
+  // bytes from a client that are not a cursor fail to decode
+  let bytes = db.query_page(&bbox, None, 1).await?.1.unwrap().to_bytes()?;
+  for len in 0..bytes.len() {
+    assert_invalid(QueryCursor::<P>::from_bytes(&bytes[0..len]).map(|_| ()), "truncated cursor");
+  }
+  assert_invalid(QueryCursor::<P>::from_bytes(&[0xff;9]).map(|_| ()), "cursor with a huge length");
+  assert_invalid(QueryCursor::<P>::from_bytes(&[0,7]).map(|_| ()), "cursor with an unknown flag");
+  // before the 4 byte checksum are the records provided and held by the tree the page ended in
+  let n = bytes.len() - 4;
+  assert![bytes[n-2] < 0x80 && bytes[n-1] < 0x80, "short counts for the partial tree"];
+  for skip in [0,bytes[n-1],bytes[n-1]+1].iter() {
+    let mut forged = bytes.clone();
+    forged[n-2] = *skip;
+    let c = QueryCursor::<P>::from_bytes(&forged)?.1;
+    assert_invalid(db.query_page(&bbox, Some(&c), 10).await.map(|_| ()), "cursor past its tree");
+  }
+
+  // a snapshot keeps serving pages while the database changes
+  let snapshot = db.snapshot().await?;
+  let expected = sorted(collect(snapshot.query(&bbox).await?).await?);
+  let (mut results,mut cursor) = snapshot.query_page(&bbox, None, 250).await?;
+  let db_cursor = db.query_page(&bbox, None, 250).await?.1;
+  db.batch(&inserts[4000..5000]).await?;
+  db.batch(&to_deletes(&inserts[500..4000])).await?;
+  db.sync().await?;
+  while let Some(c) = cursor {
+    let (page,next) = snapshot.query_page(&bbox, Some(&c), 250).await?;
+    results.extend(page);
+    cursor = next;
+  }
+  assert_eq![sorted(results), expected, "results from a snapshot"];
+
+  // a cursor into trees that were since removed fails cleanly
+  let mut cursor = db_cursor;
+  let mut stale = false;
+  while let Some(c) = cursor {
+    match db.query_page(&bbox, Some(&c), 250).await {
+      Ok((_,next)) => cursor = next,
+      Err(e) => {
+        match e.downcast_ref::<EyrosError>().map(|e| e.kind()) {
+          Some(EyrosErrorKind::CursorStale { .. }) => {},
+          kind => panic!["expected a stale cursor error, got {:?}", kind],
+        }
+        stale = true;
+        break;
+      },
+    }
+  }
+  assert![stale, "cursor from before the trees were removed"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn query_page_rewritten() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..2000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert((Coord::Scalar(x),Coord::Scalar(y),Coord::Scalar(time)), i)
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .rebuild_depth(1)
+    .build().await?;
+  for batch in inserts.chunks(500) {
+    db.batch(batch).await?;
+  }
+  db.sync().await?;
+
+  // optimize() rewrites the root the page ended in under the same id
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let (page,cursor) = db.query_page(&bbox, None, 1).await?;
+  assert_eq![page.len(), 1, "first page"];
+  db.optimize(4).await?;
+  match db.query_page(&bbox, cursor.as_ref(), 100).await.err().as_ref().and_then(kind) {
+    Some(EyrosErrorKind::CursorStale { .. }) => {},
+    kind => panic!["expected a stale cursor error after optimize, got {:?}", kind],
+  }
+
+  // deleting records outside of the bbox rewrites the tree under the same id with as many
+  // matching records as before, in another order
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .build().await?;
+  db.batch(&inserts[0..200]).await?;
+  let bbox = ((0.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let (page,cursor) = db.query_page(&bbox, None, 3).await?;
+  assert_eq![page.len(), 3, "first page"];
+  let outside: Vec<Row<P,V>> = inserts[0..200].iter().filter(|row| match row {
+    Row::Insert((Coord::Scalar(x),_,_),_) => *x < 0.0,
+    _ => false,
+  }).cloned().collect();
+  db.batch(&to_deletes(&outside)).await?;
+  match db.query_page(&bbox, cursor.as_ref(), 3).await.err().as_ref().and_then(kind) {
+    Some(EyrosErrorKind::CursorStale { .. }) => {},
+    kind => panic!["expected a stale cursor error after a delete, got {:?}", kind],
+  }
+  Ok(())
+}
+
+fn kind(e: &Error) -> Option<&EyrosErrorKind> {
+  e.downcast_ref::<EyrosError>().map(|e| e.kind())
+}
+
+fn assert_invalid(r: Result<(),Error>, msg: &str) {
+  match r.err().as_ref().and_then(kind) {
+    Some(EyrosErrorKind::CursorInvalid {}) => {},
+    kind => panic!["{}: expected an invalid cursor error, got {:?}", msg, kind],
+  }
+}
+
+fn to_deletes(rows: &[Row<P,V>]) -> Vec<Row<P,V>> {
+  rows.iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect()
+}
+
+fn sorted(mut results: Vec<(P,V)>) -> Vec<V> {
+  results.sort_unstable_by_key(|(_,v)| *v);
+  results.into_iter().map(|(_,v)| v).collect()
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<(P,V)>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  Ok(results)
+}
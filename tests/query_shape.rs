@@ -0,0 +1,137 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,Tree2,Tree3,QueryShape,Polygon,Ball,MultiBounds,
+  Metric,Point,Dimensions,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+type V = u32;
+
+#[async_std::test]
+async fn query_shape_2d() -> Result<(),Error> {
+  type P = (Coord<f32>,Coord<f32>);
+  type T = Tree2<f32,f32,V>;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..4000).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(16.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(16.0)*(1.0-ymin);
+    let value: u32 = r.read();
+    // some records are points
+    if i % 3 == 0 {
+      Row::Insert((Coord::Scalar(xmin),Coord::Scalar(ymin)), value)
+    } else {
+      Row::Insert((Coord::Interval(xmin,xmax),Coord::Interval(ymin,ymax)), value)
+    }
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .build().await?;
+  db.batch(&inserts[0..2500]).await?;
+  db.batch(&inserts[2500..4000]).await?;
+  db.sync().await?;
+  let records = to_records(&inserts);
+
+  // a concave polygon shaped like an L
+  let polygon = Polygon::new(vec![
+    (-0.8,-0.8), (0.6,-0.8), (0.6,-0.4), (-0.4,-0.4), (-0.4,0.7), (-0.8,0.7),
+  ]);
+  let n = check(&db, &records, &polygon, "polygon").await?;
+  assert![n > 100, "records in the polygon: {}", n];
+  // records inside the notch of the L are not returned
+  assert![!polygon.matches(&(Coord::Scalar(0.2),Coord::Scalar(0.2))), "point in the notch"];
+  assert![polygon.matches(&(Coord::Interval(-0.5,0.2),Coord::Scalar(0.2))), "box across an edge"];
+  assert![polygon.matches(&(Coord::Scalar(-0.6),Coord::Scalar(0.0))), "point inside"];
+
+  let triangle = Polygon::new(vec![(-0.1,-0.1),(0.1,-0.1),(0.0,0.1)]);
+  check(&db, &records, &triangle, "triangle").await?;
+
+  let circle = Ball::new(&(Coord::Scalar(0.3f32),Coord::Scalar(0.2f32)), 0.25, Metric::Euclidean);
+  let n = check(&db, &records, &circle, "circle").await?;
+  assert![n > 50, "records in the circle: {}", n];
+
+  // a corridor made of boxes along a route
+  let corridor = MultiBounds::<P>::new(vec![
+    ((-0.9,0.0),(-0.3,0.05)),
+    ((-0.3,0.0),(-0.25,0.6)),
+    ((-0.3,0.55),(0.8,0.6)),
+  ]);
+  check(&db, &records, &corridor, "corridor").await?;
+  Ok(())
+}
+
+#[async_std::test]
+async fn query_shape_3d() -> Result<(),Error> {
+  type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+  type T = Tree3<f32,f32,f32,V>;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    Row::Insert((Coord::Interval(xmin,xmax),Coord::Interval(ymin,ymax),Coord::Scalar(time)), value)
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .build().await?;
+  db.batch(&inserts[0..2000]).await?;
+  db.batch(&inserts[2000..3000]).await?;
+  let records = to_records(&inserts);
+
+  let center = (Coord::Scalar(0.1f32),Coord::Scalar(-0.2f32),Coord::Scalar(500.0f32));
+  let ball = Ball::new(&center, 300.0, Metric::Manhattan);
+  let n = check(&db, &records, &ball, "ball").await?;
+  assert![n > 50, "records in the ball: {}", n];
+
+  let boxes = MultiBounds::<P>::new(vec![
+    ((-1.0,-1.0,0.0),(-0.5,-0.5,100.0)),
+    ((-0.6,-0.6,50.0),(0.0,0.0,300.0)),
+    ((0.5,0.5,900.0),(1.0,1.0,1000.0)),
+  ]);
+  check(&db, &records, &boxes, "boxes").await?;
+
+  // a custom shape: the half-space above a plane
+  #[derive(Clone)]
+  struct Above(f64);
+  impl QueryShape<P> for Above {
+    fn overlaps_bounds(&self, bounds: &((f32,f32,f32),(f32,f32,f32))) -> bool {
+      ((bounds.1).0 + (bounds.1).1) as f64 + (bounds.1).2 as f64 / 1000.0 >= self.0
+    }
+    fn matches(&self, point: &P) -> bool {
+      let iv = point.to_f64_intervals();
+      iv[0].1 + iv[1].1 + iv[2].1 / 1000.0 >= self.0
+    }
+  }
+  check(&db, &records, &Above(1.5), "custom shape").await?;
+  Ok(())
+}
+
+fn to_records<P: Point>(inserts: &[Row<P,V>]) -> Vec<(P,V)> {
+  inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect()
+}
+
+async fn check<S,T,P,Q>(db: &DB<S,T,P,V>, records: &[(P,V)], shape: &Q, msg: &str)
+-> Result<usize,Error>
+where S: eyros::RA, T: eyros::Tree<P,V>, P: Point, Q: QueryShape<P>+Clone {
+  let mut expected: Vec<V> = records.iter()
+    .filter(|(p,_)| shape.matches(p))
+    .map(|(_,v)| *v)
+    .collect();
+  expected.sort_unstable();
+  let mut results = vec![];
+  let mut stream = db.query_shape(shape).await?;
+  while let Some(result) = stream.next().await {
+    results.push(result?.1);
+  }
+  results.sort_unstable();
+  assert_eq![results.len(), expected.len(), "{} results", msg];
+  assert_eq![results, expected, "{} results", msg];
+  Ok(results.len())
+}
@@ -0,0 +1,201 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,FileStore,Tree3,Error,EyrosError,
+  EyrosErrorKind};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use async_std::{prelude::*,sync::{Arc,Mutex}};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+// fails to open tree files while `fail` is set, simulating a crash partway through a sync
+#[derive(Clone)]
+struct FailStore {
+  store: MemoryStore,
+  fail: Arc<Mutex<bool>>,
+}
+
+#[async_trait::async_trait]
+impl Storage<MemoryFile> for FailStore {
+  async fn open(&mut self, name: &str) -> Result<MemoryFile,Error> {
+    if *self.fail.lock().await && name.starts_with("t/") {
+      return Err("simulated crash".into());
+    }
+    self.store.open(name).await
+  }
+  async fn remove(&mut self, name: &str) -> Result<(),Error> {
+    self.store.remove(name).await
+  }
+}
+
+#[async_std::test]
+async fn read_only() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let inserts = rows(3000);
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(500)
+      .build().await?;
+    db.batch(&inserts).await?;
+    db.sync().await?;
+  }
+  let before = snapshot(&mut store).await?;
+  let mut a: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .read_only(true)
+    .build().await?;
+  let mut b: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .read_only(true)
+    .build().await?;
+  assert![a.is_read_only(), "opened read-only"];
+  assert_eq![count(&mut a).await?, inserts.len(), "query from the first read-only db"];
+  assert_eq![count(&mut b).await?, inserts.len(), "query from the second read-only db"];
+
+  assert_read_only(a.batch(&inserts[0..10]).await, "batch");
+  assert_read_only(a.optimize(2).await, "optimize");
+  assert_read_only(a.sync().await, "sync");
+  assert_read_only(a.gc().await, "gc");
+  assert_read_only(a.migrate().await, "migrate");
+  assert_eq![count(&mut a).await?, inserts.len(), "query after rejected writes"];
+  assert![a.verify().await?.is_ok(), "verify a read-only db"];
+  assert![snapshot(&mut store).await? == before, "files are unchanged"];
+  Ok(())
+}
+
+#[async_std::test]
+async fn read_only_pending_journal() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let fail = Arc::new(Mutex::new(false));
+  let inserts = rows(3000);
+  {
+    let fstore = FailStore { store: store.clone(), fail: fail.clone() };
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(fstore))
+      .max_records(500)
+      .build().await?;
+    db.batch(&inserts[0..1000]).await?;
+    db.sync().await?;
+    db.batch(&inserts[1000..3000]).await?;
+    *fail.lock().await = true;
+    assert![db.sync().await.is_err(), "sync fails while writing tree files"];
+  }
+  let r: Result<DB<_,T,P,V>,Error> = Setup::from_storage(Box::new(store.clone()))
+    .read_only(true)
+    .build().await;
+  assert_read_only(r, "open with a pending journal");
+  assert![store.clone().open("journal").await?.len().await? > 0, "journal left in place"];
+  {
+    // a writable open replays the journal
+    let _db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+  }
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .read_only(true)
+    .build().await?;
+  assert_eq![count(&mut db).await?, inserts.len(), "records after the journal is replayed"];
+  Ok(())
+}
+
+#[cfg(unix)]
+#[async_std::test]
+async fn read_only_dir() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut store = FileStore::new(dir.path());
+  let inserts = rows(3000);
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_path(dir.path())
+      .max_records(500)
+      .id_index(true)
+      .build().await?;
+    db.batch(&inserts).await?;
+    db.sync().await?;
+  }
+  // files that a database without pending changes reads as empty when they are missing
+  for name in ["journal","checkpoints","checkpoints.next","index"].iter() {
+    let _ = store.remove(name).await;
+  }
+  let before = files(&mut store).await?;
+  set_mode(dir.path(), 0o555, 0o444)?;
+  let r: Result<(usize,bool,usize),Error> = async {
+    let mut db: DB<_,T,P,V> = Setup::from_path(dir.path())
+      .read_only(true)
+      .id_index(true)
+      .build().await?;
+    Ok((count(&mut db).await?, db.verify().await?.is_ok(), db.list_checkpoints().await?.len()))
+  }.await;
+  set_mode(dir.path(), 0o755, 0o644)?;
+  let (n,ok,checkpoints) = r?;
+  assert_eq![n, inserts.len(), "query from a read-only directory"];
+  assert![ok, "verify a read-only directory"];
+  assert_eq![checkpoints, 0, "no checkpoints"];
+  assert![files(&mut store).await? == before, "files are unchanged"];
+  Ok(())
+}
+
+fn assert_read_only<X>(r: Result<X,Error>, msg: &str) {
+  match r.err().as_ref().and_then(|e| e.downcast_ref::<EyrosError>()).map(|e| e.kind()) {
+    Some(EyrosErrorKind::ReadOnly { .. }) => {},
+    kind => panic!["{}: expected a read-only error, got {:?}", msg, kind],
+  }
+}
+
+async fn snapshot(store: &mut MemoryStore) -> Result<Vec<(String,Vec<u8>)>,Error> {
+  let mut files = vec![];
+  for name in store.list("").await? {
+    let mut s = store.open(&name).await?;
+    let len = s.len().await?;
+    files.push((name, s.read(0, len).await?));
+  }
+  Ok(files)
+}
+
+async fn files(store: &mut FileStore) -> Result<Vec<(String,Vec<u8>)>,Error> {
+  let mut files = vec![];
+  for name in store.list("").await? {
+    let bytes = store.read(&name).await?.unwrap_or_default();
+    files.push((name, bytes));
+  }
+  Ok(files)
+}
+
+// set the permissions of `dir` and every directory and file beneath it
+#[cfg(unix)]
+fn set_mode(dir: &std::path::Path, dir_mode: u32, file_mode: u32) -> Result<(),Error> {
+  use std::os::unix::fs::PermissionsExt;
+  std::fs::set_permissions(dir, std::fs::Permissions::from_mode(dir_mode))?;
+  for entry in std::fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      set_mode(&path, dir_mode, file_mode)?;
+    } else {
+      std::fs::set_permissions(&path, std::fs::Permissions::from_mode(file_mode))?;
+    }
+  }
+  Ok(())
+}
+
+async fn count<S>(db: &mut DB<S,T,P,V>) -> Result<usize,Error> where S: eyros::RA {
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  Ok(n)
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
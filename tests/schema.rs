@@ -0,0 +1,101 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Meta,Tree3,Error};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use desert::ToBytes;
+use async_std::prelude::*;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+// the same database opened with a different scalar type or value type
+type OtherP = (Coord<f64>,Coord<f32>,Coord<f32>);
+type OtherPDB = DB<MemoryFile,Tree3<f64,f32,f32,V>,OtherP,V>;
+type OtherVDB = DB<MemoryFile,Tree3<f32,f32,f32,u64>,P,u64>;
+
+#[async_std::test]
+async fn schema_mismatch() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone())).build().await?;
+    db.batch(&rows(100)).await?;
+    db.sync().await?;
+  }
+  {
+    let r: Result<OtherPDB,Error> = DB::open_from_storage(Box::new(store.clone())).await;
+    let err = r.err().expect("open with a different scalar type fails");
+    assert![err.to_string().contains("schema"), "unexpected error: {}", err];
+  }
+  {
+    let r: Result<OtherVDB,Error> = DB::open_from_storage(Box::new(store.clone())).await;
+    let err = r.err().expect("open with a different value type fails");
+    assert![err.to_string().contains("schema"), "unexpected error: {}", err];
+  }
+  {
+    let mut db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    assert_eq![count(&mut db).await?, 100, "open with the same types"];
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn schema_legacy_meta() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone())).build().await?;
+    db.batch(&rows(100)).await?;
+    db.sync().await?;
+    // rewrite the meta in the format used before the schema header
+    let meta = db.meta.read().await;
+    let legacy: Meta<P> = Meta {
+      schema: None,
+      roots: meta.roots.clone(),
+      next_tree: meta.next_tree,
+    };
+    let bytes = legacy.to_bytes()?;
+    let mut m = store.open("meta").await?;
+    m.write(0, &bytes).await?;
+    m.truncate(bytes.len() as u64).await?;
+  }
+  {
+    let mut db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    assert_eq![count(&mut db).await?, 100, "legacy meta opens"];
+    db.sync().await?;
+  }
+  {
+    let mut m = store.open("meta").await?;
+    let len = m.len().await?;
+    assert_eq![&m.read(0,len).await?[0..7], &[0xff,0x00,b'e',b'y',b'r',b'o',b's'],
+      "schema header added on sync"];
+    let r: Result<OtherVDB,Error> = DB::open_from_storage(Box::new(store.clone())).await;
+    assert![r.is_err(), "upgraded meta is checked"];
+  }
+  Ok(())
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
+
+async fn count(db: &mut DB<MemoryFile,T,P,V>) -> Result<usize,Error> {
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut n = 0;
+  while let Some(result) = stream.next().await {
+    result?;
+    n += 1;
+  }
+  Ok(n)
+}
@@ -0,0 +1,54 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,MemoryFile,Tree2,Error};
+use random::{Source,default as rand};
+
+type P = (Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree2<f32,f32,V>;
+
+#[async_std::test]
+async fn setup_fields_stored() -> Result<(),Error> {
+  let store = MemoryStore::new();
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..500).map(|_| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert((Coord::Scalar(x),Coord::Scalar(y)), r.read())
+  }).collect();
+  {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .branch_factor(5)
+      .max_records(50)
+      .inline(10)
+      .rebuild_depth(3)
+      .build().await?;
+    db.batch(&inserts).await?;
+    db.sync().await?;
+  }
+  {
+    // stored parameters are used when none are given
+    let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    let fields = db.setup_fields();
+    assert_eq![fields.branch_factor, 5];
+    assert_eq![fields.max_records, 50];
+    assert_eq![fields.inline, 10];
+    assert_eq![fields.rebuild_depth, 3];
+    assert_eq![fields.max_depth, 8, "default parameter stored on creation"];
+  }
+  {
+    // explicit parameters override the stored ones
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(100)
+      .build().await?;
+    let fields = db.setup_fields();
+    assert_eq![fields.max_records, 100];
+    assert_eq![fields.branch_factor, 5];
+    db.batch(&inserts[0..10]).await?;
+    db.sync().await?;
+  }
+  {
+    // overrides are not stored
+    let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    assert_eq![db.setup_fields().max_records, 50];
+  }
+  Ok(())
+}
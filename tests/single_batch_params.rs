@@ -1,4 +1,5 @@
-use eyros::{DB,Tree3,Coord,Scalar,Setup,Row,Error};
+mod common;
+use eyros::{DB,Tree3,Coord,Scalar,Row,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
 use std::time;
@@ -40,7 +41,7 @@ async fn single_200k_batch_xxyyz_9_bf_400_data_1k_base() -> Result<(),Error> {
 
 async fn from_params (size: usize, bf: usize, max_records: usize) -> Result<(),Error> {
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db: DB<_,Tree3<f32,f32,f32,V>,P,V> = Setup::from_path(dir.path())
+  let mut db: DB<_,Tree3<f32,f32,f32,V>,P,V> = common::setup(dir.path())
     .branch_factor(bf)
     .max_records(max_records)
     .build()
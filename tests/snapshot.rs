@@ -0,0 +1,124 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,Tree3,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+
+use std::cmp::Ordering;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn snapshot() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let inserts = rows(4000);
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .ext_records(100)
+    .build().await?;
+  db.batch(&inserts[0..2000]).await?;
+  db.sync().await?;
+  let before = store.list("t/").await?;
+
+  let snap = db.snapshot().await?;
+  let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+  let mut streamed = vec![];
+  for _ in 0..10 {
+    streamed.push(stream.next().await.unwrap()?);
+  }
+
+  // replace and remove every tree the snapshot and the stream can read
+  let deletes: Vec<Row<P,V>> = inserts[0..500].iter().map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  db.batch(&inserts[2000..4000]).await?;
+  db.batch(&deletes).await?;
+  db.sync().await?;
+  db.optimize(2).await?;
+  db.sync().await?;
+  assert_eq![collect(db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?).await?.len(), 3500,
+    "query after writes"];
+  let pinned = store.list("t/").await?;
+  assert_eq![db.gc().await?, Vec::<String>::new(), "gc keeps files for snapshots"];
+
+  while let Some(result) = stream.next().await {
+    streamed.push(result?);
+  }
+  assert_eq![sorted(streamed), expected(&inserts[0..2000]), "stream started before the writes"];
+  let results = collect(snap.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?).await?;
+  assert_eq![sorted(results), expected(&inserts[0..2000]), "snapshot taken before the writes"];
+  let bbox = ((-0.5,-0.5,0.0),(0.5,0.5,500.0));
+  let results = collect(snap.clone().query(&bbox).await?).await?;
+  let inside: Vec<Row<P,V>> = inserts[0..2000].iter().filter(|r| match r {
+    Row::Insert(p,_) => intersects(p, &bbox),
+    _ => false,
+  }).cloned().collect();
+  assert_eq![sorted(results), expected(&inside), "snapshot query in a smaller bbox"];
+
+  drop(stream);
+  drop(snap);
+  db.sync().await?;
+  let files = store.list("t/").await?;
+  assert![files.len() < pinned.len(), "files kept for snapshots are removed once they are dropped"];
+  assert![before.iter().any(|f| !files.contains(f)), "trees from before the snapshot are removed"];
+  assert_eq![db.gc().await?, Vec::<String>::new(), "no files left behind"];
+  assert![db.verify().await?.is_ok(), "verify after snapshots are dropped"];
+  Ok(())
+}
+
+async fn collect<S>(mut stream: S) -> Result<Vec<(P,V)>,Error>
+where S: Stream<Item=Result<(P,V),Error>>+Unpin {
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    results.push(result?);
+  }
+  Ok(results)
+}
+
+fn intersects(p: &P, bbox: &((f32,f32,f32),(f32,f32,f32))) -> bool {
+  fn coord(c: &Coord<f32>, min: f32, max: f32) -> bool {
+    match c {
+      Coord::Scalar(x) => min <= *x && *x <= max,
+      Coord::Interval(x0,x1) => *x0 <= max && min <= *x1,
+    }
+  }
+  coord(&p.0, (bbox.0).0, (bbox.1).0) && coord(&p.1, (bbox.0).1, (bbox.1).1)
+    && coord(&p.2, (bbox.0).2, (bbox.1).2)
+}
+
+fn expected(rows: &[Row<P,V>]) -> Vec<(P,V)> {
+  sorted(rows.iter().map(|r| match r {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect())
+}
+
+fn sorted(mut results: Vec<(P,V)>) -> Vec<(P,V)> {
+  results.sort_unstable_by(cmp);
+  results
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
+
+fn rows(size: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect()
+}
@@ -1,3 +1,4 @@
+mod common;
 use eyros::{DB,Coord,Row,Scalar,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
@@ -11,7 +12,7 @@ type V = Vec<u8>;
 #[async_std::test]
 async fn var_size_vec_value() -> Result<(),Error> {
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db: DB<_,_,P,V> = eyros::open_from_path3(dir.path()).await?;
+  let mut db: DB<_,_,P,V> = common::open3(dir.path()).await?;
   let mut r = rand().seed([13,12]);
   let size = 40_000;
   let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
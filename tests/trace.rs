@@ -1,3 +1,4 @@
+mod common;
 use eyros::{TreeRef,TreeId,Point,Coord,Row,QTrace,Error};
 use random::{Source,default as rand};
 use tempfile::Builder as Tmpfile;
@@ -23,7 +24,7 @@ async fn trace() -> Result<(),Error> {
     );
     Row::Insert(point, value)
   }).collect();
-  let mut db = eyros::open_from_path2(dir.path()).await?;
+  let mut db = common::open2(dir.path()).await?;
   db.batch(&batch).await?;
   db.sync().await?;
 
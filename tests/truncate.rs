@@ -0,0 +1,89 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Tree,Tree3,TreeId,Error};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use desert::ToBytes;
+use async_std::prelude::*;
+
+use std::collections::HashMap;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn truncate_shrunk_trees() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let size = 2000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+    .max_records(500)
+    .build().await?;
+  db.batch(&inserts).await?;
+  db.sync().await?;
+  let before = file_sizes(&mut db, &mut store).await?;
+  let meta_before = store.open("meta").await?.len().await?;
+
+  // delete most of the records so the trees are rebuilt in place with fewer bytes
+  let deletes: Vec<Row<P,V>> = inserts.iter().skip(10).map(|r| match r {
+    Row::Insert(p,v) => Row::Delete(p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  db.batch(&deletes).await?;
+  db.sync().await?;
+  let after = file_sizes(&mut db, &mut store).await?;
+  assert![
+    after.iter().any(|(id,len)| before.get(id).map(|b| len < b).unwrap_or(false)),
+    "at least one tree was rewritten shorter"
+  ];
+
+  let meta_bytes = db.meta.read().await.to_bytes()?;
+  let meta_len = store.open("meta").await?.len().await?;
+  // files end with a 9 byte checksum trailer
+  assert_eq![meta_len, meta_bytes.len() as u64 + 9, "meta file is exactly the serialized size"];
+  assert![meta_len <= meta_before, "meta did not grow"];
+
+  drop(db);
+  {
+    let db: DB<_,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    let mut stream = db.query(&((-1.0,-1.0,0.0),(1.0,1.0,1000.0))).await?;
+    let mut count = 0;
+    while let Some(result) = stream.next().await {
+      result?;
+      count += 1;
+    }
+    assert_eq![count, 10, "remaining records after reopening"];
+  }
+  Ok(())
+}
+
+// check that every live tree file is exactly its serialized size and return those sizes
+async fn file_sizes(db: &mut DB<MemoryFile,T,P,V>, store: &mut MemoryStore)
+-> Result<HashMap<TreeId,u64>,Error> {
+  let mut sizes = HashMap::new();
+  let mut ids: Vec<TreeId> = db.meta.read().await.roots.iter()
+    .filter_map(|r| r.as_ref().map(|r| r.id))
+    .collect();
+  while let Some(id) = ids.pop() {
+    let t = db.trees.get(&id).await?;
+    let mut tree = t.lock().await;
+    let len = store.open(&eyros::tree::get_file_from_id(&id)).await?.len().await?;
+    assert_eq![len, tree.to_bytes()?.len() as u64 + 9, "tree file is exactly the serialized size"];
+    sizes.insert(id, len);
+    ids.extend(tree.list_refs().iter().map(|r| r.id));
+  }
+  Ok(sizes)
+}
@@ -0,0 +1,192 @@
+use eyros::{DB,Coord,Row,Setup,MemoryStore,Tree3,EyrosError,EyrosErrorKind,Error};
+use random::{Source,default as rand};
+use async_std::prelude::*;
+use std::collections::HashMap;
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn upsert() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut point = || random_point(&mut r);
+  // values are distinct so each is its own id
+  let inserts: Vec<Row<P,V>> = (0..3000).map(|i| Row::Insert(point(), i as u32)).collect();
+  let mut expected: HashMap<V,P> = inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => (*v,p.clone()),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .id_index(true)
+    .build().await?;
+  db.batch(&inserts[0..2000]).await?;
+  db.batch(&inserts[2000..3000]).await?;
+
+  // move existing records, add new ones, and upsert one id twice with the last upsert kept
+  let mut rows: Vec<Row<P,V>> = vec![];
+  for i in (0..3000).step_by(6) {
+    rows.push(Row::Upsert(point(), i as u32));
+  }
+  for i in 3000..3200 {
+    rows.push(Row::Upsert(point(), i as u32));
+  }
+  rows.push(Row::Upsert(point(), 7));
+  rows.push(Row::Upsert(point(), 7));
+  for row in rows.iter() {
+    if let Row::Upsert(p,v) = row {
+      expected.insert(*v, p.clone());
+    }
+  }
+  db.batch(&rows).await?;
+  check(&db, &expected, "after upserts").await?;
+
+  // the old place of a moved record no longer has it
+  let (p,v) = match &inserts[6] {
+    Row::Insert(p,v) => (p.clone(),*v),
+    _ => panic!["unexpected row type"],
+  };
+  let mut stream = db.query(&bounds(&p)).await?;
+  while let Some(result) = stream.next().await {
+    assert![result?.1 != v, "record {} is still at its old point", v];
+  }
+
+  // upserts and deletes in one batch, after a sync
+  db.sync().await?;
+  let rows = vec![
+    Row::Upsert(point(), 12),
+    Row::DeleteId(18),
+    Row::Upsert(point(), 3500),
+  ];
+  for row in rows.iter() {
+    match row {
+      Row::Upsert(p,v) => { expected.insert(*v, p.clone()); },
+      Row::DeleteId(v) => { expected.remove(v); },
+      _ => {},
+    }
+  }
+  db.batch(&rows).await?;
+  check(&db, &expected, "after upserts and deletes").await?;
+
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new())).build().await?;
+  match db.batch(&[Row::Upsert(point(), 1)]).await.err().as_ref()
+    .and_then(|e| e.downcast_ref::<EyrosError>()).map(|e| e.kind()) {
+    Some(EyrosErrorKind::IdIndexDisabled { .. }) => {},
+    kind => panic!["upsert without the index: unexpected result {:?}", kind],
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn upsert_rows_of_one_id() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut point = || random_point(&mut r);
+  let inserts: Vec<Row<P,V>> = (0..500).map(|i| Row::Insert(point(), i as u32)).collect();
+  let mut expected: HashMap<V,P> = inserts.iter().map(|r| match r {
+    Row::Insert(p,v) => (*v,p.clone()),
+    _ => panic!["unexpected row type"],
+  }).collect();
+  let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(MemoryStore::new()))
+    .max_records(50)
+    .ext_records(20)
+    .id_index(true)
+    .build().await?;
+  db.batch(&inserts).await?;
+
+  // an upsert replaces inserts of its id earlier in the batch
+  let (p0,p1,p2) = (point(),point(),point());
+  db.batch(&[
+    Row::Insert(p0.clone(), 1000),
+    Row::Upsert(p1.clone(), 1000),
+    Row::Insert(point(), 10),
+    Row::Upsert(p2.clone(), 10),
+  ]).await?;
+  expected.insert(1000, p1);
+  expected.insert(10, p2);
+  check(&db, &expected, "after upserts of inserted ids").await?;
+
+  // a delete after the last upsert of an id cancels it and deletes the stored record, while a
+  // delete before it only deletes the stored record
+  let p3 = point();
+  db.batch(&[
+    Row::Upsert(point(), 20),
+    Row::DeleteId(20),
+    Row::Upsert(point(), 21),
+    Row::Delete(expected[&21].clone(), 21),
+    Row::DeleteId(22),
+    Row::Upsert(p3.clone(), 22),
+    Row::Upsert(point(), 1001),
+    Row::DeleteId(1001),
+    Row::Insert(p0, 1002),
+    Row::Upsert(point(), 1002),
+    Row::Upsert(point(), 1002),
+    Row::DeleteId(1002),
+  ]).await?;
+  expected.remove(&20);
+  expected.remove(&21);
+  expected.insert(22, p3);
+  check(&db, &expected, "after upserts and deletes of one id").await?;
+  for v in [20,21,1001,1002].iter() {
+    assert_eq![db.get(v).await?, None, "get deleted id={}", v];
+  }
+
+  // an insert after the last upsert of an id replaces it, so only the last of those rows is kept,
+  // and a delete after that row cancels it
+  let (p4,p5) = (point(),point());
+  db.batch(&[
+    Row::Upsert(point(), 30),
+    Row::Insert(p4.clone(), 30),
+    Row::Upsert(point(), 1003),
+    Row::Insert(point(), 1003),
+    Row::Insert(p5.clone(), 1003),
+    Row::Upsert(point(), 31),
+    Row::Insert(point(), 31),
+    Row::DeleteId(31),
+  ]).await?;
+  expected.insert(30, p4);
+  expected.insert(1003, p5);
+  expected.remove(&31);
+  check(&db, &expected, "after inserts following upserts of one id").await?;
+  assert_eq![db.get(&31).await?, None, "get deleted id=31"];
+  Ok(())
+}
+
+async fn check<S>(db: &DB<S,T,P,V>, expected: &HashMap<V,P>, msg: &str) -> Result<(),Error>
+where S: eyros::RA {
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let mut results = vec![];
+  let mut stream = db.query(&bbox).await?;
+  while let Some(result) = stream.next().await {
+    let (p,v) = result?;
+    assert_eq![expected.get(&v), Some(&p), "point of {} {}", v, msg];
+    results.push(v);
+  }
+  results.sort_unstable();
+  let mut values: Vec<V> = expected.keys().copied().collect();
+  values.sort_unstable();
+  assert_eq![results, values, "records {}", msg];
+  for (v,p) in expected.iter().take(100) {
+    assert_eq![db.get(v).await?, Some((p.clone(),*v)), "get id={} {}", v, msg];
+  }
+  Ok(())
+}
+
+fn random_point<R: Source>(r: &mut R) -> P {
+  let xmin: f32 = r.read::<f32>()*2.0-1.0;
+  let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+  let ymin: f32 = r.read::<f32>()*2.0-1.0;
+  let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+  let time: f32 = r.read::<f32>()*1000.0;
+  (Coord::Interval(xmin,xmax), Coord::Interval(ymin,ymax), Coord::Scalar(time))
+}
+
+fn bounds(p: &P) -> ((f32,f32,f32),(f32,f32,f32)) {
+  let iv = |c: &Coord<f32>| match c {
+    Coord::Scalar(x) => (*x,*x),
+    Coord::Interval(x,y) => (*x,*y),
+  };
+  let (x,y,t) = (iv(&p.0), iv(&p.1), iv(&p.2));
+  ((x.0,y.0,t.0),(x.1,y.1,t.1))
+}
@@ -0,0 +1,87 @@
+use eyros::{DB,Coord,Row,Setup,Storage,MemoryStore,MemoryFile,Tree,Tree3,TreeId,VerifyIssue,Error};
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+
+type P = (Coord<f32>,Coord<f32>,Coord<f32>);
+type V = u32;
+type T = Tree3<f32,f32,f32,V>;
+
+#[async_std::test]
+async fn verify() -> Result<(),Error> {
+  let mut store = MemoryStore::new();
+  let size = 3000;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    let value: u32 = r.read();
+    let point = (
+      Coord::Interval(xmin,xmax),
+      Coord::Interval(ymin,ymax),
+      Coord::Scalar(time)
+    );
+    Row::Insert(point, value)
+  }).collect();
+  let children = {
+    let mut db: DB<_,T,P,V> = Setup::from_storage(Box::new(store.clone()))
+      .max_records(100)
+      .ext_records(100)
+      .build().await?;
+    for batch in inserts.chunks(1000) {
+      db.batch(batch).await?;
+    }
+    db.sync().await?;
+    let report = db.verify().await?;
+    assert![report.is_ok(), "unexpected issues: {:?}", report.issues];
+    assert_eq![report.records, size, "every record found"];
+    let roots: Vec<TreeId> = db.meta.read().await.roots.iter().flatten().map(|r| r.id).collect();
+    let mut children = vec![];
+    for id in roots {
+      children.extend(db.trees.get(&id).await?.lock().await.list_refs().iter().map(|r| r.id));
+    }
+    assert![children.len() >= 2, "need at least 2 child trees"];
+    children
+  };
+  // damage the files on storage
+  store.open(&eyros::tree::get_file_from_id(&children[0])).await?.truncate(0).await?;
+  store.open(&eyros::tree::get_file_from_id(&children[1])).await?
+    .write(0, &[0xff,0xff,0xff,0xff]).await?;
+  let orphan = eyros::tree::get_file_from_id(&u64::MAX);
+  store.open(&orphan).await?.write(0, &[1,2,3]).await?;
+  {
+    let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    let report = db.verify().await?;
+    assert![!report.is_ok()];
+    assert![report.issues.iter().any(|i| matches![i, VerifyIssue::Empty { id, .. } if *id == children[0]]),
+      "empty tree reported: {:?}", report.issues];
+    assert![report.issues.iter().any(|i| matches![i, VerifyIssue::Undecodable { id, .. } if *id == children[1]]),
+      "undecodable tree reported: {:?}", report.issues];
+    assert![report.issues.contains(&VerifyIssue::Orphan { file: orphan.clone() }),
+      "orphan reported: {:?}", report.issues];
+    assert![report.records < size];
+  }
+  {
+    // shrink a root's bounds and reference it twice
+    let db: DB<MemoryFile,T,P,V> = DB::open_from_storage(Box::new(store.clone())).await?;
+    {
+      let mut meta = db.meta.write().await;
+      let root = meta.roots.iter_mut().flatten().next().unwrap();
+      root.bounds = (
+        Coord::Interval(0.0,0.1),
+        Coord::Interval(0.0,0.1),
+        Coord::Interval(0.0,0.1),
+      );
+      let root = root.clone();
+      meta.roots.push(Some(root));
+    }
+    let report = db.verify().await?;
+    assert![report.issues.iter().any(|i| matches![i, VerifyIssue::OutOfBounds { .. }]),
+      "out of bounds reported: {:?}", report.issues];
+    assert![report.issues.iter().any(|i| matches![i, VerifyIssue::DuplicateRef { .. }]),
+      "duplicate ref reported: {:?}", report.issues];
+  }
+  Ok(())
+}